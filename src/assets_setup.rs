@@ -12,63 +12,107 @@ pub enum Ases {
     TkHurt,
     TkRoll,
     TkSlash,
+    TkCharge,
+    TkChargedSlash,
     SlimeIdle,
     SlimeAttack,
     SlimeHurt,
     SlimeDie,
+    WallBreak,
 }
 
-#[derive(Resource, Deref, DerefMut, Default)]
-pub struct AnimationsMap(HashMap<Ases, Handle<CharAnimation>>);
-
-/// Sets up a shared hashmap resource of loaded animated sprite assets.
-pub fn load_sprite_assets(asset_server: Res<AssetServer>, mut animations: ResMut<AnimationsMap>) {
-    // Tutorial Kitty
-    animations.insert(
+impl Ases {
+    pub const ALL: [Ases; 12] = [
+        Ases::TkIdle,
         Ases::TkRun,
-        asset_server.load("sprites/sPlayerRun.aseprite"),
-    );
-    animations.insert(Ases::TkIdle, asset_server.load("sprites/sPlayer.aseprite"));
-    animations.insert(
         Ases::TkHurt,
-        asset_server.load("sprites/sPlayerHurt.aseprite"),
-    );
-    animations.insert(
         Ases::TkRoll,
-        asset_server.load("sprites/sPlayerRoll.aseprite"),
-    );
-    animations.insert(
         Ases::TkSlash,
-        asset_server.load("sprites/sPlayerAttackSlash.aseprite"),
-    );
-
-    // Tutorial Slime
-    animations.insert(
+        Ases::TkCharge,
+        Ases::TkChargedSlash,
         Ases::SlimeIdle,
-        asset_server.load("sprites/sSlime.aseprite"),
-    );
-    animations.insert(
         Ases::SlimeAttack,
-        asset_server.load("sprites/sSlimeAttack.aseprite"),
-    );
-    animations.insert(
         Ases::SlimeHurt,
-        asset_server.load("sprites/sSlimeHurt.aseprite"),
-    );
-    animations.insert(
         Ases::SlimeDie,
-        asset_server.load("sprites/sSlimeDie.aseprite"),
-    );
+        Ases::WallBreak,
+    ];
+
+    /// Where the sprite lives. Loading the same path twice gets you the same
+    /// handle, so anything that can't reach AnimationsMap (like LDtk bundle
+    /// constructors) can load from here and still match. Animations packed into
+    /// one omnibus file go by label, e.g. "sprites/sTk.aseprite#Run".
+    pub fn path(self) -> &'static str {
+        match self {
+            // Tutorial Kitty
+            Ases::TkIdle => "sprites/sPlayer.aseprite",
+            Ases::TkRun => "sprites/sPlayerRun.aseprite",
+            Ases::TkHurt => "sprites/sPlayerHurt.aseprite",
+            Ases::TkRoll => "sprites/sPlayerRoll.aseprite",
+            Ases::TkSlash => "sprites/sPlayerAttackSlash.aseprite",
+            Ases::TkCharge => "sprites/sPlayerCharge.aseprite",
+            Ases::TkChargedSlash => "sprites/sPlayerAttackSpin.aseprite",
+            // Tutorial Slime
+            Ases::SlimeIdle => "sprites/sSlime.aseprite",
+            Ases::SlimeAttack => "sprites/sSlimeAttack.aseprite",
+            Ases::SlimeHurt => "sprites/sSlimeHurt.aseprite",
+            Ases::SlimeDie => "sprites/sSlimeDie.aseprite",
+            // Environment
+            Ases::WallBreak => "sprites/sWallBreak.aseprite",
+        }
+    }
+}
+
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct AnimationsMap(HashMap<Ases, Handle<CharAnimation>>);
+
+/// Sets up a shared hashmap resource of loaded animated sprite assets.
+pub fn load_sprite_assets(asset_server: Res<AssetServer>, mut animations: ResMut<AnimationsMap>) {
+    for ase in Ases::ALL {
+        animations.insert(ase, asset_server.load(ase.path()));
+    }
 }
 
 #[derive(Resource)]
 pub struct SoundEffects {
     pub thump: Handle<AudioSource>,
+    pub player_attack_sfx: Handle<AudioSource>,
+    pub player_hurt_sfx: Handle<AudioSource>,
+    pub enemy_die_sfx: Handle<AudioSource>,
+    pub pickup_sfx: Handle<AudioSource>,
+    pub level_transition_sfx: Handle<AudioSource>,
+    pub checkpoint_sfx: Handle<AudioSource>,
+    /// Footsteps on anything that doesn't have its own sound in TerrainSound.
+    pub footstep_sfx: Handle<AudioSource>,
 }
 
-pub fn load_sound_effects(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let thump: Handle<AudioSource> = asset_server.load("sfx/thump.mp3");
+/// Footstep sounds, keyed by IntGridCell value on the TerrainKind layer.
+#[derive(Resource, Deref)]
+pub struct TerrainSound(pub HashMap<i32, Handle<AudioSource>>);
 
-    let sfx = SoundEffects { thump };
+impl TerrainSound {
+    pub const LAYER: &'static str = "TerrainKind";
+    pub const GRASS: i32 = 1;
+    pub const DIRT: i32 = 2;
+}
+
+pub fn load_sound_effects(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // Everything but the thump is a placeholder for now; drop files in at
+    // these paths to replace them.
+    let sfx = SoundEffects {
+        thump: asset_server.load("sfx/thump.mp3"),
+        player_attack_sfx: asset_server.load("sfx/player_attack.ogg"),
+        player_hurt_sfx: asset_server.load("sfx/player_hurt.ogg"),
+        enemy_die_sfx: asset_server.load("sfx/enemy_die.ogg"),
+        pickup_sfx: asset_server.load("sfx/pickup.ogg"),
+        level_transition_sfx: asset_server.load("sfx/level_transition.ogg"),
+        checkpoint_sfx: asset_server.load("sfx/checkpoint.ogg"),
+        footstep_sfx: asset_server.load("sfx/step.ogg"),
+    };
     commands.insert_resource(sfx);
+
+    let terrain = TerrainSound(HashMap::from([
+        (TerrainSound::GRASS, asset_server.load("sfx/step_grass.ogg")),
+        (TerrainSound::DIRT, asset_server.load("sfx/step_dirt.ogg")),
+    ]));
+    commands.insert_resource(terrain);
 }
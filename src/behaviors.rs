@@ -1,24 +1,32 @@
 //! Behavioral components and events for... all kinds of shit.
 
 use crate::{
+    collision::{AbsCircle, Solid, Walkbox},
     debug_settings::NumbersSettings,
-    input::CurrentInputs,
-    movement::{Collided, Motion, PushPriority, Speed},
+    input::{CurrentInputs, PlayerIndex},
+    movement::{Collided, Motion, PlatformVelocity, PushPriority, Speed},
     phys_space::PhysTransform,
-    toolbox::turned_away_from,
+    space_lookup::RstarAccess,
+    status_effects::Stunned,
+    toolbox::{clamp_magnitude, turned_away_from},
+    triggers::CurrentGravity,
     Player,
 };
 use bevy::prelude::*;
+use bevy_ecs_ldtk::{prelude::*, utils::ldtk_grid_coords_to_translation};
 
 /// A Bundle-implementing type representing all behaviors. Useful for removing behaviors when resetting everything.
 pub type AllBehaviors = (
     AggroRange,
+    Flee,
     Headlong,
     Hitstun,
+    Invincible,
     Knockback,
     Launch,
     MobileFree,
     MobileFixed,
+    MobileOrbit,
 );
 
 // ------- Behavior components -------
@@ -54,6 +62,11 @@ pub struct Headlong;
 #[component(storage = "SparseSet")]
 pub struct Hitstun;
 
+/// Behavior: can't take damage right now. I-frames!
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Invincible;
+
 /// Behavior: experiencing knockback.
 #[derive(Component)]
 #[component(storage = "SparseSet")]
@@ -62,7 +75,27 @@ pub struct Knockback;
 /// Behavior: interested in finding a player to hunt, within a given distance.
 #[derive(Component)]
 #[component(storage = "SparseSet")]
-pub struct AggroRange(pub f32);
+pub struct AggroRange {
+    pub radius: f32,
+    /// How far the entity can see, for entities that need line of sight (see
+    /// HasLineOfSight). Ignored otherwise.
+    pub sight_range: f32,
+}
+
+impl AggroRange {
+    /// Sight range defaults to the same as the radius.
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius,
+            sight_range: radius,
+        }
+    }
+}
+
+/// Marker: can't aggro on things through walls. Opt-in, bc it's pricier than
+/// a plain distance check.
+#[derive(Component)]
+pub struct HasLineOfSight;
 
 /// Behavior: currently hunting a player
 #[derive(Component)]
@@ -73,6 +106,30 @@ pub struct Aggro {
     pub limit: Option<(Vec2, f32)>,
 }
 
+/// Behavior: running away from something, a bit faster than usual.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Flee {
+    pub from: Entity,
+    pub speed_multiplier: f32,
+}
+
+/// How long an orbiter takes to make up a gap between where it is and the
+/// edge of its circle.
+const ORBIT_CORRECTION_SECS: f32 = 0.25;
+
+/// Behavior: circling around something, like a moth around a lamp.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct MobileOrbit {
+    pub target: Entity,
+    pub radius: f32,
+    /// Radians/s; positive is counterclockwise.
+    pub angular_speed: f32,
+    /// Where we're at around the circle, in radians from +x.
+    pub current_angle: f32,
+}
+
 /// Behavior: currently pushing another entity
 #[derive(Component)]
 #[component(storage = "SparseSet")]
@@ -82,12 +139,111 @@ pub struct Pushing {
     pub activation_dir: Vec2,
 }
 
+/// Behavior: walking a fixed route, one waypoint after another. For
+/// projectiles, cutscenes, moving platforms, anything that doesn't need to
+/// think about where it's going.
+#[derive(Component)]
+pub struct FollowPath {
+    pub waypoints: Vec<Vec3>,
+    /// Pixels per second.
+    pub speed: f32,
+    /// Index of the waypoint we're headed for.
+    pub current: usize,
+    pub on_finish: PathEnd,
+}
+
+/// What to do after reaching the last waypoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathEnd {
+    /// Stay put, and drop the FollowPath.
+    Stop,
+    /// Head back to the first waypoint.
+    Loop,
+    /// Go away forever.
+    Despawn,
+}
+
+impl FollowPath {
+    const LDTK_PATH_FIELD: &'static str = "path";
+    const LDTK_SPEED_FIELD: &'static str = "path_speed";
+    const LDTK_END_FIELD: &'static str = "path_end";
+    const DEFAULT_SPEED: f32 = 40.0;
+
+    pub fn new(waypoints: Vec<Vec3>, speed: f32, on_finish: PathEnd) -> Self {
+        Self {
+            waypoints,
+            speed,
+            current: 0,
+            on_finish,
+        }
+    }
+
+    /// Build a route that starts at `start` and then visits each point in an
+    /// LDtk entity's "path" field. Same coordinate deal as
+    /// PatrolArea::waypoints_from_ldtk. Also reads "path_speed" (px/s) and
+    /// "path_end" (Loop, Stop, or Despawn); missing values mean a slow loop.
+    pub fn from_ldtk(
+        instance: &EntityInstance,
+        start: Vec2,
+        layer_grid_height: i32,
+        grid_size: i32,
+        layer_origin: Vec2,
+    ) -> Self {
+        let mut waypoints = vec![start.extend(0.0)];
+        if let Ok(points) = instance.iter_points_field(Self::LDTK_PATH_FIELD) {
+            waypoints.extend(points.map(|&coords| {
+                (layer_origin
+                    + ldtk_grid_coords_to_translation(
+                        coords,
+                        layer_grid_height,
+                        IVec2::splat(grid_size),
+                    ))
+                .extend(0.0)
+            }));
+        }
+        let speed = match instance.get_float_field(Self::LDTK_SPEED_FIELD).copied() {
+            Ok(speed) if speed.is_finite() && speed > 0.0 => speed,
+            _ => Self::DEFAULT_SPEED,
+        };
+        let on_finish = match instance
+            .get_string_field(Self::LDTK_END_FIELD)
+            .map_or("Loop", String::as_str)
+        {
+            "Loop" => PathEnd::Loop,
+            "Stop" => PathEnd::Stop,
+            "Despawn" => PathEnd::Despawn,
+            other => {
+                warn!(
+                    "Bad path_end {:?} on LDtk entity {}, looping instead",
+                    other, instance.iid
+                );
+                PathEnd::Loop
+            },
+        };
+        Self::new(waypoints, speed, on_finish)
+    }
+
+    /// Move on to the next waypoint. Returns false if the path's over.
+    fn advance(&mut self) -> bool {
+        self.current += 1;
+        if self.current >= self.waypoints.len() && self.on_finish == PathEnd::Loop {
+            self.current = 0;
+        }
+        self.current < self.waypoints.len()
+    }
+}
+
+/// How close counts as "at" a waypoint.
+const WAYPOINT_ARRIVE_DISTANCE: f32 = 1.0;
+
 // ------- Behavior events -------
 
 pub struct BehaviorEventsPlugin;
 impl Plugin for BehaviorEventsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<Rebound>().add_event::<AggroActivate>();
+        app.add_event::<Rebound>()
+            .add_event::<AggroActivate>()
+            .add_event::<KnockbackImpulse>();
     }
 }
 
@@ -105,22 +261,36 @@ pub struct AggroActivate {
     pub target: Entity,
 }
 
+/// Event: get shoved. `vector` gets added to the entity's planned velocity for
+/// one frame. A nonzero `z_velocity` pops the entity up into the air (and
+/// lets gravity bring it back down, via Launch).
+#[derive(Event)]
+pub struct KnockbackImpulse {
+    pub entity: Entity,
+    pub vector: Vec2,
+    pub z_velocity: f32,
+}
+
 // ------- Behavior systems -------
 
-/// Plan motion for player when moving freely per inputs.
+/// Plan motion for player when moving freely per inputs. Stunned entities
+/// don't get to listen to inputs.
 pub fn mobile_free_velocity(
-    mut free_q: Query<(&mut Motion, &Speed), With<MobileFree>>,
+    mut free_q: Query<(&mut Motion, &Speed, &PlayerIndex), (With<MobileFree>, Without<Stunned>)>,
     inputs: Res<CurrentInputs>,
 ) {
-    free_q.iter_mut().for_each(|(mut motion, speed)| {
-        motion.velocity += inputs.movement * speed.0;
+    free_q.iter_mut().for_each(|(mut motion, speed, index)| {
+        motion.add_velocity(inputs.player(*index).movement * speed.0);
     });
 }
 
-/// Plan motion for entities moving on a fixed vector.
-pub fn mobile_fixed_velocity(mut fixed_q: Query<(&mut Motion, &Speed, &MobileFixed)>) {
+/// Plan motion for entities moving on a fixed vector. Stunned entities stop
+/// in their tracks.
+pub fn mobile_fixed_velocity(
+    mut fixed_q: Query<(&mut Motion, &Speed, &MobileFixed), Without<Stunned>>,
+) {
     fixed_q.iter_mut().for_each(|(mut motion, speed, fixed)| {
-        motion.velocity += fixed.input * speed.0;
+        motion.add_velocity(fixed.input * speed.0);
         if fixed.face {
             motion.face(fixed.input);
         }
@@ -136,41 +306,193 @@ pub fn mobile_chase_entity(
     chase_q
         .iter_mut()
         .for_each(|(mut motion, aggro, speed, transform)| {
-            if let Ok(target_transform) = all_locs_q.get(aggro.target) {
-                let difference = target_transform.translation - transform.translation;
-                let input = difference.truncate().normalize_or_zero();
-                motion.velocity += input * speed.0;
+            let Ok(target_transform) = all_locs_q.get(aggro.target) else {
+                return;
+            };
+            // Already right on top of it: nowhere to go, nothing to face.
+            if transform.distance_to_2d_sq(target_transform) < f32::EPSILON {
+                return;
+            }
+            let difference = target_transform.translation - transform.translation;
+            let input = difference.truncate().normalize();
+            motion.add_velocity(input * speed.0);
+            motion.face(input);
+        });
+}
+
+/// Plan motion directly away from an entity.
+pub fn mobile_flee_velocity(
+    mut flee_q: Query<(&mut Motion, &Flee, &Speed, &PhysTransform)>,
+    all_locs_q: Query<&PhysTransform>,
+) {
+    flee_q
+        .iter_mut()
+        .for_each(|(mut motion, flee, speed, transform)| {
+            if let Ok(from_transform) = all_locs_q.get(flee.from) {
+                let input = flee_away_input(
+                    transform.translation.truncate(),
+                    from_transform.translation.truncate(),
+                );
+                motion.add_velocity(input * speed.0 * flee.speed_multiplier);
                 motion.face(input);
             }
         });
 }
 
+/// Keep the Speed-based planners from stacking. If something ends up with,
+/// say, MobileFree and MobileFixed at once, it shouldn't get to go twice as
+/// fast; cap its planned velocity at its Speed (times its flee multiplier,
+/// if it's fleeing). Goes after those planners, but before the ones that
+/// have their own idea of speed (orbits, paths, knockback), and before
+/// MoveModifiers, which are allowed to go over.
+pub fn velocity_cap_system(mut motion_q: Query<(&mut Motion, &Speed, Option<&Flee>)>) {
+    for (mut motion, speed, flee) in motion_q.iter_mut() {
+        let multiplier = flee.map_or(1.0, |f| f.speed_multiplier.max(1.0));
+        let capped = clamp_magnitude(motion.velocity, speed.0 * multiplier);
+        motion.set_velocity(capped);
+    }
+}
+
+/// Plan motion around an entity: along the circle at the orbit's angular
+/// speed, plus a push back toward the circle if we've drifted off of it.
+pub fn mobile_orbit_velocity(
+    mut orbit_q: Query<(&mut Motion, &mut MobileOrbit, &PhysTransform)>,
+    all_locs_q: Query<&PhysTransform>,
+) {
+    for (mut motion, mut orbit, transform) in orbit_q.iter_mut() {
+        let Ok(target_transform) = all_locs_q.get(orbit.target) else {
+            continue;
+        };
+        let offset = transform.translation.truncate() - target_transform.translation.truncate();
+        let current_radius = offset.length();
+        // Dead center has no angle, so just keep the last one.
+        if current_radius > f32::EPSILON {
+            orbit.current_angle = offset.to_angle();
+        }
+        let outward = Vec2::from_angle(orbit.current_angle);
+        let tangential = outward.perp() * orbit.angular_speed * orbit.radius;
+        let correction = outward * (orbit.radius - current_radius) / ORBIT_CORRECTION_SECS;
+        let velocity = tangential + correction;
+        motion.add_velocity(velocity);
+        motion.face(velocity);
+    }
+}
+
+/// Plan motion along a FollowPath, without overshooting the next waypoint.
+pub fn follow_path_system(
+    mut path_q: Query<(Entity, &mut FollowPath, &mut Motion, &PhysTransform)>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let delta = time.delta_seconds();
+    if delta == 0.0 {
+        return;
+    }
+    for (entity, mut path, mut motion, transform) in path_q.iter_mut() {
+        let mut target = path.waypoints.get(path.current).copied();
+        if target.is_some_and(|wp| transform.translation.distance(wp) <= WAYPOINT_ARRIVE_DISTANCE) {
+            target = path.advance().then(|| path.waypoints[path.current]);
+        }
+        let Some(target) = target else {
+            match path.on_finish {
+                PathEnd::Despawn => commands.entity(entity).despawn_recursive(),
+                _ => {
+                    commands.entity(entity).remove::<FollowPath>();
+                },
+            }
+            continue;
+        };
+
+        let distance = transform.translation.distance(target);
+        let alpha = (path.speed * delta / distance).min(1.0);
+        let velocity = (transform.lerp_towards(target, alpha) - transform.translation) / delta;
+        motion.add_velocity(velocity.truncate());
+        motion.z_velocity += velocity.z;
+        motion.face(velocity.truncate());
+    }
+}
+
+/// Unit vector pointing from `from` to `subject`, i.e. the way to run.
+fn flee_away_input(subject: Vec2, from: Vec2) -> Vec2 {
+    (subject - from).normalize_or_zero()
+}
+
+/// Apply one-frame knockback shoves. Goes in MovePlanners, so the shove stacks
+/// on top of whatever else the entity's doing this frame. Invincible
+/// entities shrug it off.
+pub fn apply_knockback_system(
+    mut knockbacks: EventReader<KnockbackImpulse>,
+    mut motion_q: Query<(&mut Motion, Option<&mut Launch>), Without<Invincible>>,
+    mut commands: Commands,
+) {
+    for kb in knockbacks.read() {
+        let Ok((mut motion, launch)) = motion_q.get_mut(kb.entity) else {
+            continue;
+        };
+        motion.add_velocity(kb.vector);
+        if kb.z_velocity != 0.0 {
+            // Bare z velocity would leave em hanging in midair, so go through Launch.
+            match launch {
+                Some(mut launch) => launch.z_velocity += kb.z_velocity,
+                None => {
+                    commands.entity(kb.entity).insert(Launch {
+                        z_velocity: kb.z_velocity,
+                    });
+                },
+            }
+        }
+    }
+}
+
 pub const LAUNCH_GRAVITY: f32 = 255.0; // Reduce z-velocity by X per second. idk!
 
-/// Plan vertical motion for entities that are launched (distinct from flying)
+/// Plan vertical motion for entities that are launched (distinct from flying).
+/// A CurrentGravity (from a gravity zone) beats the global setting.
 pub fn launch_and_fall(
-    mut launched_q: Query<(&mut Motion, &mut Launch)>,
+    mut launched_q: Query<(&mut Motion, &mut Launch, Option<&CurrentGravity>)>,
     time: Res<Time>,
     numbers: Res<NumbersSettings>,
 ) {
-    let gravity = numbers.launch_gravity;
-    launched_q.iter_mut().for_each(|(mut motion, mut launch)| {
-        motion.z_velocity += launch.z_velocity;
-        launch.z_velocity -= gravity * time.delta_seconds();
-    });
+    launched_q
+        .iter_mut()
+        .for_each(|(mut motion, mut launch, current)| {
+            let gravity = current.map_or(numbers.launch_gravity, |g| g.0);
+            motion.z_velocity += launch.z_velocity;
+            launch.z_velocity -= gravity * time.delta_seconds();
+        });
 }
 
 /// Aggro onto player if you spot one
 pub fn acquire_aggro(
-    player_q: Query<(Entity, &PhysTransform), With<Player>>,
-    enemy_q: Query<(Entity, &PhysTransform, &AggroRange), Without<Player>>,
+    player_q: Query<(Entity, &PhysTransform, &Walkbox), With<Player>>,
+    enemy_q: Query<
+        (Entity, &PhysTransform, &AggroRange, Has<HasLineOfSight>),
+        (Without<Player>, Without<Stunned>),
+    >,
+    solids_tree: Res<RstarAccess<Solid>>,
+    solids_q: Query<(&PhysTransform, &Walkbox), With<Solid>>,
     mut activate: EventWriter<AggroActivate>,
 ) {
     // ....... hmm, spatial query, or just skip it?
-    for (enemy, e_transform, range) in enemy_q.iter() {
+    for (enemy, e_transform, range, needs_sight) in enemy_q.iter() {
         let e_loc = e_transform.translation.truncate();
-        for (player, p_transform) in player_q.iter() {
-            if e_loc.distance(p_transform.translation.truncate()) <= range.0 {
+        for (player, p_transform, p_walkbox) in player_q.iter() {
+            let p_loc = p_transform.translation.truncate();
+            // Any part of the player's feet counts, not just their origin.
+            let in_range = |radius: f32| {
+                AbsCircle {
+                    center: e_loc,
+                    radius,
+                }
+                .collide_rect(p_transform.to_absbbox(p_walkbox))
+            };
+            let spotted = if needs_sight {
+                in_range(range.sight_range)
+                    && line_of_sight_clear(e_loc, p_loc, &solids_tree, &solids_q)
+            } else {
+                in_range(range.radius)
+            };
+            if spotted {
                 activate.send(AggroActivate {
                     subject: enemy,
                     target: player,
@@ -180,6 +502,32 @@ pub fn acquire_aggro(
     }
 }
 
+/// Slop for finding solids near a sight line. The tree only knows where solids'
+/// origins are, so we need to look a bit past the line itself to catch the
+/// edges of anything big that it clips.
+const SIGHT_LINE_SCAN_MARGIN: f32 = 32.0;
+
+/// Whether a straight line between two points gets through without touching
+/// any solids.
+fn line_of_sight_clear(
+    from: Vec2,
+    to: Vec2,
+    solids_tree: &RstarAccess<Solid>,
+    solids_q: &Query<(&PhysTransform, &Walkbox), With<Solid>>,
+) -> bool {
+    let sight_line = to - from;
+    let midpoint = from + sight_line / 2.0;
+    let scan_radius = sight_line.length() / 2.0 + SIGHT_LINE_SCAN_MARGIN;
+    !solids_tree
+        .within_distance(midpoint, scan_radius)
+        .into_iter()
+        .filter_map(|(_, solid)| solids_q.get(solid).ok())
+        .any(|(transform, walkbox)| {
+            let bbox = transform.to_absbbox(walkbox);
+            bbox.segment_collide(from, sight_line).is_some()
+        })
+}
+
 // Needs to go between main move planners and push system, with an apply_deferred.
 pub fn start_push(
     mut collision_events: EventReader<Collided>,
@@ -212,11 +560,13 @@ pub fn end_push(mut commands: Commands, pushing_q: Query<(Entity, &Motion, &Push
     }
 }
 
-/// Expects to go after all the "normal" move planners.
+/// Expects to go after all the "normal" move planners. Also handles carrying
+/// platform riders along, since that's just another kind of push.
 pub fn push_system(
     mut q_set: ParamSet<(
-        Query<(&Pushing, &Motion)>,             // pushing
-        Query<&mut Motion, With<PushPriority>>, // pushable
+        Query<(&Pushing, &Motion)>,                  // pushing
+        Query<&mut Motion, With<PushPriority>>,      // pushable
+        Query<(&mut Motion, &mut PlatformVelocity)>, // riding
     )>,
 ) {
     // Gotta do ParamSet and an intermediate collect, bc pushers and pushables aren't mutually exclusive.
@@ -228,7 +578,251 @@ pub fn push_system(
     let mut pushable_q = q_set.p1();
     for (entity, impulse) in impulses.into_iter() {
         if let Ok(mut motion) = pushable_q.get_mut(entity) {
-            motion.velocity += impulse;
+            motion.add_velocity(impulse);
         }
     }
+    // Platform riders: use it or lose it, the platform might be gone next frame.
+    for (mut motion, mut platform_velocity) in q_set.p2().iter_mut() {
+        motion.add_velocity(platform_velocity.0);
+        platform_velocity.0 = Vec2::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{ldtk_instance, phys_at, spawn_mover, velocity};
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn stunned_things_dont_plan_movement() {
+        let mut world = World::new();
+        let mover = || {
+            (
+                MobileFixed {
+                    input: Vec2::X,
+                    face: true,
+                },
+                Speed(100.0),
+            )
+        };
+        let free = spawn_mover(&mut world, 0.0, 0.0, mover());
+        let stunned = spawn_mover(&mut world, 0.0, 0.0, (mover(), Stunned::new(0.5)));
+        world.run_system_once(mobile_fixed_velocity);
+        assert_eq!(velocity(&world, free), Vec2::new(100.0, 0.0));
+        assert_eq!(velocity(&world, stunned), Vec2::ZERO);
+    }
+
+    #[test]
+    fn stacked_planners_cant_beat_speed() {
+        let mut world = World::new();
+        let target = world.spawn(phys_at(0.0, 50.0)).id();
+        let confused = spawn_mover(
+            &mut world,
+            0.0,
+            0.0,
+            (
+                MobileFixed {
+                    input: Vec2::X,
+                    face: true,
+                },
+                Aggro {
+                    target,
+                    limit: None,
+                },
+                Speed(40.0),
+            ),
+        );
+        let scared = spawn_mover(
+            &mut world,
+            0.0,
+            0.0,
+            (
+                Flee {
+                    from: target,
+                    speed_multiplier: 1.5,
+                },
+                Speed(40.0),
+            ),
+        );
+        world.run_system_once(mobile_fixed_velocity);
+        world.run_system_once(mobile_chase_entity);
+        world.run_system_once(mobile_flee_velocity);
+        world.run_system_once(velocity_cap_system);
+        // Both at once would be (40, 40); capped, it's the same heading at 40.
+        let capped = velocity(&world, confused);
+        assert!((capped.length() - 40.0).abs() < 0.001);
+        assert!((capped.x - capped.y).abs() < 0.001);
+        // Fleeing's allowed to go over, by its multiplier.
+        assert!(velocity(&world, scared).abs_diff_eq(Vec2::new(0.0, -60.0), 0.001));
+    }
+
+    #[test]
+    fn aggro_counts_the_players_whole_walkbox() {
+        use crate::collision::centered_rect;
+        use crate::space_lookup::RstarPlugin;
+        let mut app = App::new();
+        app.add_plugins(RstarPlugin::<Solid>::new())
+            .add_event::<AggroActivate>()
+            .add_systems(Update, acquire_aggro);
+        let spawn_enemy = |app: &mut App, x: f32| {
+            app.world_mut()
+                .spawn((phys_at(x, 0.0), AggroRange::new(50.0)))
+                .id()
+        };
+        // Player's origin is 54 away from this one, but the near edge of its
+        // walkbox is only 49 away.
+        let close = spawn_enemy(&mut app, 0.0);
+        let far = spawn_enemy(&mut app, -10.0);
+        app.world_mut().spawn((
+            Player,
+            phys_at(54.0, 0.0),
+            Walkbox(centered_rect(10.0, 4.0)),
+        ));
+        app.update();
+
+        let events = app.world().resource::<Events<AggroActivate>>();
+        let aggroed: Vec<Entity> = events
+            .get_reader()
+            .read(events)
+            .map(|a| a.subject)
+            .collect();
+        assert_eq!(aggroed, vec![close]);
+        assert!(!aggroed.contains(&far));
+    }
+
+    #[test]
+    fn orbiters_circle_and_hold_their_radius() {
+        let mut world = World::new();
+        let center = world.spawn(phys_at(10.0, 10.0)).id();
+        let orbit = |angle: f32| MobileOrbit {
+            target: center,
+            radius: 20.0,
+            angular_speed: 1.0,
+            current_angle: angle,
+        };
+        // Right on the circle, due east: straight up (counterclockwise).
+        let on_circle = spawn_mover(&mut world, 30.0, 10.0, orbit(0.0));
+        // Too far out, due north: heading west, and pulled back in.
+        let too_far = spawn_mover(&mut world, 10.0, 35.0, orbit(0.0));
+        // Dead center: goes by its last known angle.
+        let centered = spawn_mover(&mut world, 10.0, 10.0, orbit(std::f32::consts::PI));
+        world.run_system_once(mobile_orbit_velocity);
+
+        let angle = |world: &World, e: Entity| world.get::<MobileOrbit>(e).unwrap().current_angle;
+        assert!(velocity(&world, on_circle).abs_diff_eq(Vec2::new(0.0, 20.0), 0.001));
+        assert_eq!(angle(&world, on_circle), 0.0);
+        assert!(velocity(&world, too_far).abs_diff_eq(Vec2::new(-20.0, -20.0), 0.001));
+        assert!((angle(&world, too_far) - std::f32::consts::FRAC_PI_2).abs() < 0.001);
+        assert!(velocity(&world, centered).abs_diff_eq(Vec2::new(-80.0, -20.0), 0.001));
+        assert_eq!(angle(&world, centered), std::f32::consts::PI);
+    }
+
+    #[test]
+    fn follow_path_walks_the_route() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world
+            .resource_mut::<Time>()
+            .advance_by(bevy::utils::Duration::from_millis(50));
+        let walker = spawn_mover(
+            &mut world,
+            0.0,
+            0.0,
+            FollowPath::new(
+                vec![Vec3::new(10.0, 0.0, 0.0), Vec3::new(10.0, 10.0, 0.0)],
+                100.0,
+                PathEnd::Despawn,
+            ),
+        );
+        let take_velocity = |world: &mut World| {
+            let v = velocity(world, walker);
+            world.get_mut::<Motion>(walker).unwrap().velocity = Vec2::ZERO;
+            v
+        };
+        let teleport = |world: &mut World, to: Vec3| {
+            world.get_mut::<PhysTransform>(walker).unwrap().translation = to;
+        };
+
+        // 5px per frame, straight at the first waypoint.
+        world.run_system_once(follow_path_system);
+        assert!(take_velocity(&mut world).abs_diff_eq(Vec2::new(100.0, 0.0), 0.001));
+
+        // Close enough: on to the next one.
+        teleport(&mut world, Vec3::new(10.0, 0.5, 0.0));
+        world.run_system_once(follow_path_system);
+        assert_eq!(world.get::<FollowPath>(walker).unwrap().current, 1);
+        assert!(take_velocity(&mut world).abs_diff_eq(Vec2::new(0.0, 100.0), 0.001));
+
+        // Almost there: don't overshoot.
+        teleport(&mut world, Vec3::new(10.0, 7.0, 0.0));
+        world.run_system_once(follow_path_system);
+        assert!(take_velocity(&mut world).abs_diff_eq(Vec2::new(0.0, 60.0), 0.001));
+
+        // Done: bye.
+        teleport(&mut world, Vec3::new(10.0, 10.0, 0.0));
+        world.run_system_once(follow_path_system);
+        assert!(world.get_entity(walker).is_none());
+    }
+
+    #[test]
+    fn paths_come_from_ldtk_fields() {
+        let start = Vec2::new(8.0, 8.0);
+        // Two rows of 16px cells, so cell (2, 1) is the bottom row.
+        let from_fields =
+            |fields| FollowPath::from_ldtk(&ldtk_instance(fields), start, 2, 16, Vec2::ZERO);
+
+        let shuttle = from_fields(vec![
+            ("path", FieldValue::Points(vec![Some(IVec2::new(2, 1))])),
+            ("path_speed", FieldValue::Float(Some(30.0))),
+            ("path_end", FieldValue::String(Some("Despawn".to_string()))),
+        ]);
+        assert_eq!(
+            shuttle.waypoints,
+            vec![start.extend(0.0), Vec3::new(40.0, 8.0, 0.0)]
+        );
+        assert_eq!(shuttle.speed, 30.0);
+        assert_eq!(shuttle.on_finish, PathEnd::Despawn);
+
+        // Nothing set: sit at the start, looping slowly.
+        let idle = from_fields(vec![(
+            "path_end",
+            FieldValue::String(Some("Dance".to_string())),
+        )]);
+        assert_eq!(idle.waypoints, vec![start.extend(0.0)]);
+        assert_eq!(idle.speed, FollowPath::DEFAULT_SPEED);
+        assert_eq!(idle.on_finish, PathEnd::Loop);
+    }
+
+    #[test]
+    fn flee_goes_away() {
+        let mut world = World::new();
+        let scary = world.spawn(phys_at(10.0, 10.0)).id();
+        let scaredy = spawn_mover(
+            &mut world,
+            20.0,
+            10.0,
+            (
+                Speed(10.0),
+                Flee {
+                    from: scary,
+                    speed_multiplier: 2.0,
+                },
+            ),
+        );
+        world.run_system_once(mobile_flee_velocity);
+        let motion = world.get::<Motion>(scaredy).unwrap();
+        assert_eq!(motion.velocity, Vec2::new(20.0, 0.0));
+        // Facing away too.
+        assert_eq!(motion.facing, 0.0);
+
+        // Diagonal, and the distance from the scary thing got bigger.
+        let subject = Vec2::new(-3.0, -4.0);
+        let from = Vec2::new(0.0, 0.0);
+        let away = flee_away_input(subject, from);
+        assert!(away.abs_diff_eq(Vec2::new(-0.6, -0.8), 0.0001));
+        assert!((subject + away).distance(from) > subject.distance(from));
+        // On top of it: nowhere in particular to go.
+        assert_eq!(flee_away_input(from, from), Vec2::ZERO);
+    }
 }
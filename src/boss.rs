@@ -0,0 +1,140 @@
+//! Bosses: enemies whose fight comes in phases. A boss carries a BossPhase
+//! and a list of health thresholds; as its health drops past each one, it
+//! moves to the next phase and gets shoved into whatever state that phase
+//! starts with. Anybody who cares (camera shake, eventually music) can listen
+//! for BossPhaseChanged.
+
+use crate::{
+    entity_states::{enemy_state_changes, enemy_state_read_events, EnemyState, EnemyStateMachine},
+    health::Health,
+};
+use bevy::prelude::*;
+
+pub struct BossPlugin;
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BossPhaseChanged>().add_systems(
+            Update,
+            boss_phase_system
+                .after(enemy_state_read_events)
+                .before(enemy_state_changes),
+        );
+    }
+}
+
+/// Component: which phase of the fight a boss is in. Starts at 0, and goes up
+/// by one for every threshold passed.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BossPhase(pub u8);
+
+/// Component: when a boss changes phases. Each entry is (health, state): once
+/// current health is at or below `health`, start the next phase by switching
+/// to `state`. Keep them in order from highest health to lowest.
+#[derive(Component, Clone)]
+pub struct BossThresholds {
+    pub phases: Vec<(i32, EnemyState)>,
+}
+
+/// Event: a boss just entered a new phase.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BossPhaseChanged(pub Entity, pub u8);
+
+// ------- Systems -------
+
+/// Check bosses' health against their thresholds, and move them along. A big
+/// enough hit can skip a phase, in which case every phase in between still
+/// gets its event, and the state transitions fight it out by priority like
+/// always. (Dying beats everything, so a killing blow stays a killing blow.)
+pub fn boss_phase_system(
+    mut boss_q: Query<(
+        Entity,
+        &mut BossPhase,
+        &BossThresholds,
+        &Health,
+        &mut EnemyStateMachine,
+    )>,
+    mut phase_events: EventWriter<BossPhaseChanged>,
+) {
+    for (entity, mut phase, thresholds, health, mut machine) in boss_q.iter_mut() {
+        while let Some((threshold, state)) = thresholds.phases.get(phase.0 as usize) {
+            if health.current > *threshold {
+                break;
+            }
+            phase.0 += 1;
+            machine.push_transition(state.clone());
+            phase_events.send(BossPhaseChanged(entity, phase.0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boss_app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins(BossPlugin);
+        let mut machine = EnemyStateMachine::new(EnemyState::Idle);
+        machine.do_transition(|_| ());
+        let boss = app
+            .world_mut()
+            .spawn((
+                BossPhase::default(),
+                BossThresholds {
+                    phases: vec![(6, EnemyState::Hurt), (3, EnemyState::Idle)],
+                },
+                Health::new(10),
+                machine,
+            ))
+            .id();
+        (app, boss)
+    }
+
+    fn set_health(app: &mut App, boss: Entity, current: i32) {
+        app.world_mut().get_mut::<Health>(boss).unwrap().current = current;
+    }
+
+    #[test]
+    fn bosses_change_phases_at_thresholds() {
+        let (mut app, boss) = boss_app();
+        let mut reader = app
+            .world()
+            .resource::<Events<BossPhaseChanged>>()
+            .get_reader();
+        let mut changes = |app: &App| -> Vec<BossPhaseChanged> {
+            let events = app.world().resource::<Events<BossPhaseChanged>>();
+            reader.read(events).copied().collect()
+        };
+
+        set_health(&mut app, boss, 7);
+        app.update();
+        assert_eq!(changes(&app), vec![]);
+
+        set_health(&mut app, boss, 6);
+        app.update();
+        assert_eq!(changes(&app), vec![BossPhaseChanged(boss, 1)]);
+        let mut machine = app.world_mut().get_mut::<EnemyStateMachine>(boss).unwrap();
+        machine.do_transition(|_| ());
+        assert!(matches!(machine.current(), EnemyState::Hurt));
+
+        // Doesn't go off again while you're still in the same phase.
+        set_health(&mut app, boss, 5);
+        app.update();
+        assert_eq!(changes(&app), vec![]);
+        assert_eq!(app.world().get::<BossPhase>(boss), Some(&BossPhase(1)));
+    }
+
+    #[test]
+    fn big_hits_can_skip_phases() {
+        let (mut app, boss) = boss_app();
+        set_health(&mut app, boss, 1);
+        app.update();
+        let events = app.world().resource::<Events<BossPhaseChanged>>();
+        let got: Vec<BossPhaseChanged> = events.get_reader().read(events).copied().collect();
+        assert_eq!(
+            got,
+            vec![BossPhaseChanged(boss, 1), BossPhaseChanged(boss, 2)]
+        );
+        assert_eq!(app.world().get::<BossPhase>(boss), Some(&BossPhase(2)));
+    }
+}
@@ -3,49 +3,316 @@
 //! just use shit from main.
 
 use crate::{
+    boss::BossPhaseChanged,
+    goofy_time::{delta_seconds_for, SmoothedTime, StaticTime},
+    health::{EntityDied, TakeDamage},
     phys_space::{PhysOffset, PhysTransform},
-    Player,
+    sounds::SFX_EAR_GAP,
+    toolbox::{approach, approach_vec2, snap_to_grid},
+    CameraKind, DebugSettings, Enemy, Player,
 };
 use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_prng::Xoshiro256Plus;
+use bevy_rand::prelude::*;
+use rand::prelude::Rng;
+
+/// Knobs for how the camera follows the player.
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct CameraConfig {
+    /// How far the player can wander from the camera's center before it
+    /// starts following, in world pixels. Only used by CameraKind::LerpWithDeadzone.
+    pub deadzone_radius: f32,
+    /// How far ahead of the player's movement the camera aims, as a fraction
+    /// (0 to 1) of half the viewport. Used by the lerp camera kinds.
+    pub lead_ahead_strength: f32,
+}
+
+impl CameraConfig {
+    /// Roughly how long the lead takes to catch up to a change in direction,
+    /// or to settle back to center once the player stops.
+    const LEAD_SETTLE_SECS: f32 = 0.3;
+    /// What fraction of the remaining distance the camera covers per second
+    /// (give or take; it's re-measured every frame).
+    const FOLLOW_RATE: f32 = 4.0;
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            deadzone_radius: 16.0,
+            lead_ahead_strength: 0.3,
+        }
+    }
+}
+
+/// World-space bounds the camera's viewport should stay inside. Gets set from
+/// the LDtk level whenever one finishes spawning.
+#[derive(Resource, Reflect, Default)]
+pub struct CameraClamp {
+    pub bounds: Option<Rect>,
+}
+
+/// Smooth zooming. The camera_zoom_system walks the projection scale toward
+/// target_scale at a constant rate; use set_camera_zoom to aim for a scale
+/// over a given amount of time. (Smaller scale = more zoomed in.)
+#[derive(Resource, Reflect)]
+pub struct CameraZoom {
+    pub target_scale: f32,
+    /// Scale units per second.
+    pub speed: f32,
+    /// Mirrors the actual projection scale as of the last zoom system run.
+    current_scale: f32,
+}
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        Self {
+            target_scale: Self::DEFAULT_SCALE,
+            speed: 0.0,
+            current_scale: Self::DEFAULT_SCALE,
+        }
+    }
+}
+
+impl CameraZoom {
+    pub const DEFAULT_SCALE: f32 = 1.0 / 4.0;
+    pub const PLAYER_DIED_SCALE: f32 = 1.0 / 3.0;
+    pub const PLAYER_DIED_ZOOM_SECS: f32 = 1.5;
+
+    /// Zoom to `scale`, arriving in about `duration_secs`. Zero or negative
+    /// durations snap on the next camera_zoom_system run, even if no time
+    /// passed.
+    pub fn set_camera_zoom(&mut self, scale: f32, duration_secs: f32) {
+        self.target_scale = scale;
+        self.speed = if duration_secs > 0.0 {
+            (scale - self.current_scale).abs() / duration_secs
+        } else {
+            f32::INFINITY
+        };
+    }
+}
+
+/// Screenshake, trauma-style: things that hurt add trauma, trauma decays over
+/// time, and the actual shake scales with trauma squared so small bumps stay
+/// small and big hits feel big.
+#[derive(Resource, Reflect)]
+pub struct CameraShake {
+    /// 0.0 to 1.0.
+    pub trauma: f32,
+    /// Offset at full trauma, in world pixels.
+    pub max_offset: Vec2,
+    /// Rotation at full trauma, in radians.
+    pub max_rotation: f32,
+    /// Trauma lost per second.
+    pub decay_rate: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            max_offset: Vec2::new(4.0, 3.0),
+            max_rotation: 0.05,
+            decay_rate: 1.5,
+        }
+    }
+}
+
+impl CameraShake {
+    pub const PLAYER_HURT_TRAUMA: f32 = 0.5;
+    pub const ENEMY_DIED_TRAUMA: f32 = 0.3;
+    pub const BOSS_PHASE_TRAUMA: f32 = 0.6;
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+}
 
 pub fn setup_camera(mut commands: Commands) {
     let mut camera_bundle = Camera2dBundle::default();
-    camera_bundle.projection.scale = 1.0 / 4.0;
+    camera_bundle.projection.scale = CameraZoom::DEFAULT_SCALE;
     commands.spawn((
         camera_bundle,
         PhysOffset(Vec2::ZERO),
         PhysTransform {
             translation: Vec3::new(0.0, 0.0, 999.0),
         },
+        // The camera's ears, for positional sound effects.
+        SpatialListener::new(SFX_EAR_GAP),
+        PixelPerfectCamera,
         // ^^ hack: I looked up the Z coord on new_2D and fudged it so we won't accidentally round it to 1000.
     ));
 }
 
+/// Where the camera should be looking: the midpoint of all the players. None
+/// if nobody's around.
+fn players_center(player_q: &Query<&PhysTransform, With<Player>>) -> Option<Vec2> {
+    let (sum, count) = player_q.iter().fold((Vec2::ZERO, 0.0), |(sum, count), tf| {
+        (sum + tf.translation.truncate(), count + 1.0)
+    });
+    (count > 0.0).then(|| sum / count)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn camera_lerp_system(
     time: Res<Time>,
-    // time: Res<StaticTime>,
-    // time: Res<SmoothedTime>,
+    smoothed_time: Res<SmoothedTime>,
+    static_time: Res<StaticTime>,
     mut params: ParamSet<(
         Query<&PhysTransform, With<Player>>,
-        Query<&mut PhysTransform, With<Camera>>,
+        Query<(&mut PhysTransform, &OrthographicProjection), With<Camera>>,
     )>,
+    config: Res<CameraConfig>,
+    debug_settings: Res<DebugSettings>,
+    mut lead: Local<Vec2>,
+    mut last_player_pos: Local<Option<Vec2>>,
 ) {
-    let delta = time.delta_seconds();
-    let player_pos = params.p0().single().translation.truncate();
+    let delta = delta_seconds_for(
+        debug_settings.time_mode,
+        &time,
+        &smoothed_time,
+        &static_time,
+    );
+    let deadzone_radius = match debug_settings.camera_kind {
+        CameraKind::LerpWithDeadzone => config.deadzone_radius,
+        _ => 0.0,
+    };
+    let Some(player_pos) = players_center(&params.p0()) else {
+        return;
+    };
+    // The movers have already eaten Motion.velocity by the time the camera
+    // runs, so just go by how far the player actually got.
+    let player_moved = last_player_pos
+        .replace(player_pos)
+        .map_or(Vec2::ZERO, |last| player_pos - last);
     // let player_pos = player_tf.translation.truncate();
     // let mut camera_tf = query.q1().get_single_mut().unwrap();
-    for mut camera_tf in params.p1().iter_mut() {
+    for (mut camera_tf, projection) in params.p1().iter_mut() {
+        let lead_target = player_moved.normalize_or_zero()
+            * config.lead_ahead_strength
+            * projection.area.half_size();
+        *lead = lead.lerp(
+            lead_target,
+            (delta / CameraConfig::LEAD_SETTLE_SECS).min(1.0),
+        );
+
         let camera_pos = camera_tf.translation.truncate();
-        let camera_distance = player_pos - camera_pos;
+        let raw_distance = player_pos + *lead - camera_pos;
+        // Inside the deadzone, stay put. Outside, chase from the edge of the
+        // deadzone rather than from the center.
+        if raw_distance.length() <= deadzone_radius {
+            continue;
+        }
+        let camera_distance = raw_distance - raw_distance.normalize_or_zero() * deadzone_radius;
         let follow_amount = if camera_distance.length() <= 1.0 {
             camera_distance
         } else {
-            (camera_distance * 4.0 * delta).round()
+            let step = camera_distance.length() * CameraConfig::FOLLOW_RATE * delta;
+            approach_vec2(Vec2::ZERO, camera_distance, step).round()
         };
         camera_tf.translation += follow_amount.extend(0.0);
         // let camera_z = camera_tf.translation.z;
         // camera_tf.translation = player_pos.extend(camera_z);
-        // ...and then you'd do room boundaries clamping, screenshake, etc.
+    }
+}
+
+/// Walk the camera's projection scale toward the zoom target. Only touches
+/// scale, so the camera's Z (and thus what it can see) stays put.
+pub fn camera_zoom_system(
+    time: Res<Time>,
+    mut zoom: ResMut<CameraZoom>,
+    mut camera_q: Query<&mut OrthographicProjection, (With<Camera>, With<PhysTransform>)>,
+) {
+    let max_step = zoom.speed * time.delta_seconds();
+    for mut projection in camera_q.iter_mut() {
+        if projection.scale != zoom.target_scale {
+            // Snap straight there; INFINITY times a zero-length frame is NaN.
+            projection.scale = if zoom.speed == f32::INFINITY {
+                zoom.target_scale
+            } else {
+                approach(projection.scale, zoom.target_scale, max_step)
+            };
+        }
+        zoom.current_scale = projection.scale;
+    }
+}
+
+/// Zoom out a bit when the player dies. (Boss rooms or whatever can zoom in
+/// via set_camera_zoom too, once they exist.)
+pub fn camera_zoom_events_system(
+    mut died_events: EventReader<EntityDied>,
+    player_q: Query<(), With<Player>>,
+    mut zoom: ResMut<CameraZoom>,
+) {
+    for EntityDied(entity) in died_events.read() {
+        if player_q.contains(*entity) {
+            zoom.set_camera_zoom(
+                CameraZoom::PLAYER_DIED_SCALE,
+                CameraZoom::PLAYER_DIED_ZOOM_SECS,
+            );
+        }
+    }
+}
+
+/// Keep the camera's viewport inside the level. If the level's smaller than
+/// the viewport on some axis, just center on it.
+pub fn camera_clamp_system(
+    clamp: Res<CameraClamp>,
+    mut camera_q: Query<(&mut PhysTransform, &OrthographicProjection), With<Camera>>,
+) {
+    let Some(bounds) = clamp.bounds else {
+        return;
+    };
+    for (mut camera_tf, projection) in camera_q.iter_mut() {
+        // The projection's area is already scaled, and centered on the camera.
+        let half_view = projection.area.half_size();
+        let min = bounds.min + half_view;
+        let max = bounds.max - half_view;
+        let center = bounds.center();
+        let pos = &mut camera_tf.translation;
+        pos.x = if min.x <= max.x {
+            pos.x.clamp(min.x, max.x)
+        } else {
+            center.x
+        };
+        pos.y = if min.y <= max.y {
+            pos.y.clamp(min.y, max.y)
+        } else {
+            center.y
+        };
+    }
+}
+
+/// Update the camera clamp bounds when a level finishes spawning. Levels'
+/// transforms are at their bottom left corner.
+pub fn camera_clamp_level_bounds_system(
+    mut level_events: EventReader<LevelEvent>,
+    level_q: Query<(&LevelIid, &GlobalTransform)>,
+    project_q: Query<&Handle<LdtkProject>>,
+    projects: Res<Assets<LdtkProject>>,
+    mut clamp: ResMut<CameraClamp>,
+) {
+    for event in level_events.read() {
+        let LevelEvent::Transformed(iid) = event else {
+            continue;
+        };
+        let Some((_, level_transform)) = level_q.iter().find(|(level_iid, _)| *level_iid == iid)
+        else {
+            continue;
+        };
+        let Some(level) = project_q
+            .iter()
+            .find_map(|handle| projects.get(handle))
+            .and_then(|project| project.get_raw_level_by_iid(iid.get()))
+        else {
+            warn!("Couldn't find level data for {}, not clamping camera", iid);
+            continue;
+        };
+        let origin = level_transform.translation().truncate();
+        let size = Vec2::new(level.px_wid as f32, level.px_hei as f32);
+        clamp.bounds = Some(Rect::from_corners(origin, origin + size));
     }
 }
 
@@ -55,9 +322,239 @@ pub fn camera_locked_system(
         Query<&mut PhysTransform, With<Camera>>,
     )>,
 ) {
-    let player_pos = params.p0().single().translation;
+    let Some(player_pos) = players_center(&params.p0()) else {
+        return;
+    };
     let mut camera_q = params.p1();
     let mut camera_tf = camera_q.single_mut();
     camera_tf.translation.x = player_pos.x;
     camera_tf.translation.y = player_pos.y;
 }
+
+/// Marker: keep this camera lined up with the screen's pixel grid. The
+/// projection scale is world px per screen px (1/4 means one art pixel is a
+/// 4x4 block on screen), so screen pixels land on multiples of `scale` in
+/// world space. Sitting between those makes the art shimmer as it scrolls,
+/// since every sprite edge gets resampled a little differently each frame.
+/// (Goes by the *current* scale, so it keeps up with zooming.)
+#[derive(Component)]
+pub struct PixelPerfectCamera;
+
+/// Snap pixel-perfect cameras to the screen pixel grid, after everything
+/// else has had its say about where the camera goes. The smooth_camera debug
+/// setting turns this off, for comparison. Unlike sprites, the camera doesn't
+/// get flattened (its Z is draw range, not height), so its X/Y are already
+/// where it ends up.
+pub fn camera_pixel_snap_system(
+    debug_settings: Res<DebugSettings>,
+    mut camera_q: Query<(&mut PhysTransform, &OrthographicProjection), With<PixelPerfectCamera>>,
+) {
+    if debug_settings.smooth_camera {
+        return;
+    }
+    for (mut camera_tf, projection) in camera_q.iter_mut() {
+        if projection.scale <= 0.0 {
+            continue;
+        }
+        let snapped = snap_to_grid(camera_tf.translation.truncate(), projection.scale);
+        camera_tf.translation.x = snapped.x;
+        camera_tf.translation.y = snapped.y;
+    }
+}
+
+/// Component: a background sprite that scrolls at its own pace. A factor of
+/// 1.0 scrolls with the world like anything else; 0.0 sticks to the camera
+/// like a skybox. Goes on plain sprites, not physical entities, since
+/// sync_phys_transforms would stomp the Transform.
+#[derive(Component, Reflect)]
+pub struct ParallaxLayer {
+    pub scroll_factor: Vec2,
+}
+
+/// Drag parallax layers along with however far the camera moved this frame,
+/// minus their own share of the scroll.
+pub fn parallax_system(
+    mut last_camera_pos: Local<Option<Vec2>>,
+    camera_q: Query<&PhysTransform, With<Camera>>,
+    mut layer_q: Query<(&mut Transform, &ParallaxLayer)>,
+) {
+    let Ok(camera_tf) = camera_q.get_single() else {
+        return;
+    };
+    let camera_pos = camera_tf.translation.truncate();
+    let camera_delta = camera_pos - last_camera_pos.unwrap_or(camera_pos);
+    *last_camera_pos = Some(camera_pos);
+    if camera_delta == Vec2::ZERO {
+        return;
+    }
+    for (mut transform, layer) in layer_q.iter_mut() {
+        let offset = camera_delta * (Vec2::ONE - layer.scroll_factor);
+        transform.translation += offset.extend(0.0);
+    }
+}
+
+/// Shake the camera based on current trauma, then let the trauma decay. This
+/// pokes the camera's Transform directly (which is normally off-limits for
+/// physical entities) so the shake rides on top of whatever the camera
+/// movers decided: sync_phys_transforms stomps the translation every frame,
+/// so the offset never accumulates or feeds back into the lerp. Must run
+/// after that sync.
+pub fn camera_shake_system(
+    time: Res<Time>,
+    mut shake: ResMut<CameraShake>,
+    mut rng: ResMut<GlobalEntropy<Xoshiro256Plus>>,
+    mut camera_q: Query<&mut Transform, (With<Camera>, With<PhysTransform>)>,
+) {
+    let shake_amount = shake.trauma * shake.trauma;
+    for mut camera_tf in camera_q.iter_mut() {
+        if shake_amount > 0.0 {
+            let offset = Vec2::new(
+                shake.max_offset.x * shake_amount * rng.gen_range(-1.0..=1.0),
+                shake.max_offset.y * shake_amount * rng.gen_range(-1.0..=1.0),
+            );
+            let angle = shake.max_rotation * shake_amount * rng.gen_range(-1.0..=1.0);
+            camera_tf.translation += offset.extend(0.0);
+            camera_tf.rotation = Quat::from_rotation_z(angle);
+        } else {
+            camera_tf.rotation = Quat::IDENTITY;
+        }
+    }
+    if shake.trauma > 0.0 {
+        let decay = shake.decay_rate * time.delta_seconds();
+        shake.trauma = (shake.trauma - decay).max(0.0);
+    }
+}
+
+/// Ow, my camera.
+pub fn camera_trauma_system(
+    mut damage_events: EventReader<TakeDamage>,
+    mut died_events: EventReader<EntityDied>,
+    mut boss_phase_events: EventReader<BossPhaseChanged>,
+    player_q: Query<(), With<Player>>,
+    enemy_q: Query<(), With<Enemy>>,
+    mut shake: ResMut<CameraShake>,
+) {
+    for damage in damage_events.read() {
+        if damage.amount > 0 && player_q.contains(damage.target) {
+            shake.add_trauma(CameraShake::PLAYER_HURT_TRAUMA);
+        }
+    }
+    for EntityDied(entity) in died_events.read() {
+        if enemy_q.contains(*entity) {
+            shake.add_trauma(CameraShake::ENEMY_DIED_TRAUMA);
+        }
+    }
+    for _ in boss_phase_events.read() {
+        shake.add_trauma(CameraShake::BOSS_PHASE_TRAUMA);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_perfect_cameras_land_on_screen_pixels() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = World::new();
+        world.init_resource::<DebugSettings>();
+        let projection = OrthographicProjection {
+            scale: 0.25,
+            ..default()
+        };
+        let spawn_at = |world: &mut World, x: f32, y: f32| {
+            world
+                .spawn((
+                    PhysTransform {
+                        translation: Vec3::new(x, y, 999.0),
+                    },
+                    projection.clone(),
+                    PixelPerfectCamera,
+                ))
+                .id()
+        };
+        let camera = spawn_at(&mut world, 10.1, -3.6);
+        world.run_system_once(camera_pixel_snap_system);
+        let pos = world.get::<PhysTransform>(camera).unwrap().translation;
+        assert_eq!(pos, Vec3::new(10.0, -3.5, 999.0));
+
+        world.resource_mut::<DebugSettings>().smooth_camera = true;
+        let smooth = spawn_at(&mut world, 10.1, -3.6);
+        world.run_system_once(camera_pixel_snap_system);
+        let pos = world.get::<PhysTransform>(smooth).unwrap().translation;
+        assert_eq!(pos, Vec3::new(10.1, -3.6, 999.0));
+    }
+
+    #[test]
+    fn instant_zooms_snap_even_on_a_zero_length_frame() {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<CameraZoom>()
+            .add_systems(Update, camera_zoom_system);
+        let camera = app
+            .world_mut()
+            .spawn((
+                Camera::default(),
+                PhysTransform {
+                    translation: Vec3::ZERO,
+                },
+                OrthographicProjection {
+                    scale: CameraZoom::DEFAULT_SCALE,
+                    ..default()
+                },
+            ))
+            .id();
+        app.world_mut()
+            .resource_mut::<CameraZoom>()
+            .set_camera_zoom(0.5, 0.0);
+        app.update();
+        let scale = app
+            .world()
+            .get::<OrthographicProjection>(camera)
+            .unwrap()
+            .scale;
+        assert_eq!(scale, 0.5);
+    }
+
+    #[test]
+    fn half_scroll_moves_half_as_far() {
+        let mut app = App::new();
+        app.add_systems(Update, parallax_system);
+        let camera = app
+            .world_mut()
+            .spawn((
+                Camera::default(),
+                PhysTransform {
+                    translation: Vec3::ZERO,
+                },
+            ))
+            .id();
+        let spawn_layer = |app: &mut App, factor: f32| {
+            app.world_mut()
+                .spawn((
+                    Transform::default(),
+                    ParallaxLayer {
+                        scroll_factor: Vec2::splat(factor),
+                    },
+                ))
+                .id()
+        };
+        let half = spawn_layer(&mut app, 0.5);
+        let skybox = spawn_layer(&mut app, 0.0);
+        let ground = spawn_layer(&mut app, 1.0);
+        app.update();
+
+        app.world_mut()
+            .get_mut::<PhysTransform>(camera)
+            .unwrap()
+            .translation = Vec3::new(40.0, -20.0, 0.0);
+        app.update();
+        let pos = |layer| app.world().get::<Transform>(layer).unwrap().translation;
+        // Half the camera's movement gets cancelled out, so on screen it
+        // scrolls by half as much as the world.
+        assert_eq!(pos(half), Vec3::new(20.0, -10.0, 0.0));
+        assert_eq!(pos(skybox), Vec3::new(40.0, -20.0, 0.0));
+        assert_eq!(pos(ground), Vec3::ZERO);
+    }
+}
@@ -5,9 +5,10 @@
 use super::types::*;
 use crate::toolbox::{flip_rect_y, move_rect_origin};
 
-use asefile::AsepriteFile;
+use asefile::{AsepriteFile, Slice};
 use bevy::asset::AsyncReadExt;
 use bevy::asset::{io::Reader, AssetLoader, LoadContext};
+use bevy::log::warn;
 use bevy::math::{prelude::*, Affine2, Rect};
 use bevy::render::{
     render_asset::RenderAssetUsages,
@@ -43,6 +44,10 @@ impl AssetLoader for CharAnimationLoader {
     }
 }
 
+/// Layer names the loader looks for. Exact match only, so anything close to
+/// one of these is probably a typo.
+const WELL_KNOWN_LAYERS: [&str; 4] = ["walkbox", "hitbox", "hurtbox", "origin"];
+
 const REFLECT_Y_COMPONENTS: [f32; 4] = [1.0, 0.0, 0.0, -1.0];
 const REFLECT_Y: Mat2 = Mat2::from_cols_array(&REFLECT_Y_COMPONENTS);
 const OFFSET_TO_CENTER: Vec2 = Vec2::new(-0.5, 0.5);
@@ -56,14 +61,27 @@ const OFFSET_TO_CENTER: Vec2 = Vec2::new(-0.5, 0.5);
 /// - Tag names are unique in the file. (Aseprite doesn't guarantee this.)
 /// - Named tags cover all of the needed animation frames.
 ///   - OR: there are zero tags and thus only one orientation.
+///   - OR: it's an omnibus file with several animations in it, and the tags are
+///     named like "Run/E". Each "Run" group becomes its own labeled
+///     `CharAnimation` (load it as `file.aseprite#Run`), sharing the file's
+///     texture and layout. Any plain un-grouped tags still go to the
+///     top-level animation, which is otherwise empty.
 /// - Walkbox layer: "walkbox"
 /// - Hitbox layer: "hitbox"
 /// - Hurtbox layer: "hurtbox"
 /// - Origin layer: "origin"
 /// - Layers for drawn-on metadata coordinates should be marked as invisible in
 ///   the saved file.
+/// - OR, any of those four can be a slice with the same name instead. If a
+///   frame has both, the slice wins. Every slice (well-known name or not)
+///   also lands in the frame's `slices` map, for whatever custom hotspots an
+///   artist wants to mark.
 fn load_aseprite(bytes: &[u8], load_context: &mut LoadContext) -> anyhow::Result<CharAnimation> {
     let ase = AsepriteFile::read(bytes)?;
+    let layer_names: Vec<String> = ase.layers().map(|l| l.name().to_string()).collect();
+    for warning in check_layer_names(layer_names.iter().map(String::as_str))? {
+        warn!("{}: {}", load_context.path().display(), warning);
+    }
     let width = ase.width();
     let height = ase.height();
     let num_frames = ase.num_frames();
@@ -127,11 +145,13 @@ fn load_aseprite(bytes: &[u8], load_context: &mut LoadContext) -> anyhow::Result
 
     // Since our final frame indices are reliable, processing tags is easy.
     let mut variants: VariantsMap = HashMap::new();
+    // For omnibus files: group name => that animation's variants.
+    let mut groups: HashMap<&str, VariantsMap> = HashMap::new();
 
     // Closure for the heavy lifting (since we can't handle some tags / 0 tags
     // in the same for-loop):
-    let mut process_frame_range =
-        |name: VariantName, frame_range: core::ops::RangeInclusive<u32>| {
+    let process_frame_range =
+        |name: VariantName, frame_range: core::ops::RangeInclusive<u32>| -> CharAnimationVariant {
             let mut total_duration = Duration::default();
             let frames: Vec<CharAnimationFrame> = frame_range
                 .map(|i| {
@@ -142,8 +162,18 @@ fn load_aseprite(bytes: &[u8], load_context: &mut LoadContext) -> anyhow::Result
 
                     total_duration += duration;
 
+                    let frame_slices: HashMap<&str, Rect> = ase
+                        .slices()
+                        .iter()
+                        .filter_map(|slice| Some((slice.name.as_str(), slice_rect(slice, i)?)))
+                        .collect();
+
                     // Wasteful, bc we could exit early on first non-clear px, but meh.
-                    let origin = match rect_from_cel(&ase, "origin", i) {
+                    let origin = match frame_slices
+                        .get("origin")
+                        .copied()
+                        .or_else(|| rect_from_cel(&ase, "origin", i))
+                    {
                         Some(origin_rect) => origin_rect.min,
                         None => Vec2::ZERO, // Origin's non-optional.
                     };
@@ -151,9 +181,18 @@ fn load_aseprite(bytes: &[u8], load_context: &mut LoadContext) -> anyhow::Result
                     // Get each box, position it relative to the origin, THEN flip the Y.
                     // (This is because source image coordinates go Y-down, but bevy spatial
                     // coordinates go Y-up.)
-                    let walkbox = anchored_physical_rect_from_cel(&ase, "walkbox", i, origin);
-                    let hitbox = anchored_physical_rect_from_cel(&ase, "hitbox", i, origin);
-                    let hurtbox = anchored_physical_rect_from_cel(&ase, "hurtbox", i, origin);
+                    let anchored = |r: Rect| flip_rect_y(move_rect_origin(r, origin));
+                    let get_box = |name: &str| match frame_slices.get(name) {
+                        Some(&r) => Some(anchored(r)),
+                        None => anchored_physical_rect_from_cel(&ase, name, i, origin),
+                    };
+                    let walkbox = get_box("walkbox");
+                    let hitbox = get_box("hitbox");
+                    let hurtbox = get_box("hurtbox");
+                    let slices = frame_slices
+                        .iter()
+                        .map(|(&name, &r)| (name.to_string(), anchored(r)))
+                        .collect();
 
                     let anchor = anchor_transform.transform_point2(origin);
 
@@ -165,49 +204,49 @@ fn load_aseprite(bytes: &[u8], load_context: &mut LoadContext) -> anyhow::Result
                         walkbox,
                         hitbox,
                         hurtbox,
+                        slices,
                     }
                 })
                 .collect();
-            let variant = CharAnimationVariant {
+            CharAnimationVariant {
                 name,
                 frames,
                 duration: total_duration,
-            };
-            variants.insert(name, variant);
+            }
         };
 
     if ase.num_tags() == 0 {
         // then treat whole file as one variant.
         let frame_range = 0..=(ase.num_frames() - 1);
-        process_frame_range(VariantName::Neutral, frame_range);
+        let name = VariantName::Neutral;
+        variants.insert(name, process_frame_range(name, frame_range));
     } else {
         // one variant per tag.
         for tag in (0..ase.num_tags()).map(|i| ase.tag(i)) {
-            let name: VariantName = tag.name().try_into()?; // Just propagate error, don't continue load.
+            let (group, variant_name) = split_tag_name(tag.name());
+            let name: VariantName = variant_name.try_into()?; // Just propagate error, don't continue load.
             let frame_range = tag.from_frame()..=tag.to_frame(); // inclusive
-            process_frame_range(name, frame_range);
+            let variant = process_frame_range(name, frame_range);
+            match group {
+                Some(group) => groups.entry(group).or_default().insert(name, variant),
+                None => variants.insert(name, variant),
+            };
         }
     }
 
-    // Determine directionality... maybe pull this out into a function someday
-    // Anyway, count em up... but, don't bother implementing directionalities I'm not using yet.
-    let directionality = if variants.len() >= 4
-        && variants.contains_key(&VariantName::E)
-        && variants.contains_key(&VariantName::N)
-        && variants.contains_key(&VariantName::W)
-        && variants.contains_key(&VariantName::S)
-    {
-        Directionality::Four
-    } else if variants.contains_key(&VariantName::E) {
-        Directionality::OneE
-    } else {
-        Directionality::Zero
-    };
+    for (group, group_variants) in groups {
+        load_context.labeled_asset_scope(group.to_string(), |_lc| CharAnimation {
+            directionality: directionality_of(&group_variants),
+            variants: group_variants,
+            layout: atlas_layout_handle.clone(),
+            texture: texture_handle.clone(),
+        });
+    }
 
     // The whole enchilada:
     let animation = CharAnimation {
+        directionality: directionality_of(&variants),
         variants,
-        directionality,
         layout: atlas_layout_handle,
         texture: texture_handle,
     };
@@ -216,6 +255,59 @@ fn load_aseprite(bytes: &[u8], load_context: &mut LoadContext) -> anyhow::Result
     Ok(animation)
 }
 
+/// Catch metadata layers whose names are *almost* right ("Walkbox", "HitBox")
+/// and would otherwise get silently skipped. Returns a warning for each one
+/// that has no exact-match twin. A botched walkbox is an error instead, since
+/// a character with no walkbox just falls through the world. (A file can
+/// still leave the walkbox out on purpose, like a death animation.)
+fn check_layer_names<'a>(names: impl Iterator<Item = &'a str>) -> anyhow::Result<Vec<String>> {
+    let names: Vec<&str> = names.collect();
+    let mut warnings = Vec::new();
+    for name in names.iter() {
+        let Some(known) = WELL_KNOWN_LAYERS
+            .into_iter()
+            .find(|known| name.trim().eq_ignore_ascii_case(known))
+        else {
+            continue;
+        };
+        if *name == known || names.contains(&known) {
+            continue;
+        }
+        let message = format!("layer \"{name}\" will be ignored; did you mean \"{known}\"?");
+        if known == "walkbox" {
+            anyhow::bail!(message);
+        }
+        warnings.push(message);
+    }
+    Ok(warnings)
+}
+
+/// Split an omnibus tag name like "Run/E" into its animation group and
+/// variant. Plain tag names have no group.
+fn split_tag_name(tag_name: &str) -> (Option<&str>, &str) {
+    match tag_name.split_once('/') {
+        Some((group, variant)) => (Some(group), variant),
+        None => (None, tag_name),
+    }
+}
+
+/// Count up the variants to see which directions we've got... but, don't
+/// bother implementing directionalities I'm not using yet.
+fn directionality_of(variants: &VariantsMap) -> Directionality {
+    if variants.len() >= 4
+        && variants.contains_key(&VariantName::E)
+        && variants.contains_key(&VariantName::N)
+        && variants.contains_key(&VariantName::W)
+        && variants.contains_key(&VariantName::S)
+    {
+        Directionality::Four
+    } else if variants.contains_key(&VariantName::E) {
+        Directionality::OneE
+    } else {
+        Directionality::Zero
+    }
+}
+
 /// Convert the image buffer returned by `asefile::Frame.image()` into a
 /// `bevy::render::texture::Image`. Consumes the argument and re-uses the
 /// internal container.
@@ -246,6 +338,26 @@ fn anchored_physical_rect_from_cel(
     rect_from_cel(ase, layer_name, frame_index).map(|r| flip_rect_y(move_rect_origin(r, origin)))
 }
 
+/// Get a slice's bounds on a given frame, in the same inclusive pixel
+/// coordinates rect_from_cel uses. A slice key lasts until the next one, and
+/// there's nothing before the first key.
+fn slice_rect(slice: &Slice, frame_index: u32) -> Option<Rect> {
+    let key = slice
+        .keys
+        .iter()
+        .filter(|k| k.from_frame <= frame_index)
+        .max_by_key(|k| k.from_frame)?;
+    if key.size.0 == 0 || key.size.1 == 0 {
+        return None;
+    }
+    let min = Vec2::new(key.origin.0 as f32, key.origin.1 as f32);
+    let size = Vec2::new(key.size.0 as f32, key.size.1 as f32);
+    Some(Rect {
+        min,
+        max: min + size - Vec2::ONE,
+    })
+}
+
 /// Get the bounding Rect for a cel's non-transparent pixels.
 fn rect_from_cel(ase: &AsepriteFile, layer_name: &str, frame_index: u32) -> Option<Rect> {
     ase.layer_by_name(layer_name).and_then(|layer| {
@@ -319,3 +431,94 @@ fn copy_texture_to_atlas(
         atlas_texture.data[begin..end].copy_from_slice(&texture.data[texture_begin..texture_end]);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charged_attack_art_has_a_hitbox() {
+        use crate::{debug_settings::NumbersSettings, entity_states::PlayerState};
+        let read = |state: PlayerState| {
+            let (ases, ..) = state.animation_data(&NumbersSettings::default());
+            let path = std::path::Path::new("assets").join(ases.path());
+            let ase = AsepriteFile::read_file(&path).unwrap();
+            let names = ase
+                .layers()
+                .map(|l| l.name().to_string())
+                .collect::<Vec<_>>();
+            assert!(check_layer_names(names.iter().map(String::as_str))
+                .unwrap()
+                .is_empty());
+            ase
+        };
+        let charge = read(PlayerState::Charging);
+        assert_eq!(charge.num_tags(), 4);
+        // The spin's only got the one orientation, and swings all the way around.
+        let spin = read(PlayerState::ChargedAttack);
+        assert_eq!(spin.num_tags(), 0);
+        let hitboxes: Vec<Rect> = (0..spin.num_frames())
+            .filter_map(|i| {
+                anchored_physical_rect_from_cel(&spin, "hitbox", i, Vec2::new(38.0, 23.0))
+            })
+            .collect();
+        assert_eq!(hitboxes.len(), 4);
+        assert!(hitboxes.iter().any(|r| r.min.x > 0.0));
+        assert!(hitboxes.iter().any(|r| r.max.x < 0.0));
+        assert!(hitboxes.iter().any(|r| r.min.y > 0.0));
+        assert!(hitboxes.iter().any(|r| r.max.y < 0.0));
+    }
+
+    #[test]
+    fn every_sprite_has_a_file() {
+        use crate::assets_setup::Ases;
+        // A missing file just never loads, and nothing that waits on its
+        // animation finishing (like wall rubble) ever gets cleaned up.
+        for ases in Ases::ALL {
+            let path = std::path::Path::new("assets").join(ases.path());
+            let ase = AsepriteFile::read_file(&path);
+            assert!(ase.is_ok(), "{ases:?} at {}", path.display());
+        }
+    }
+
+    #[test]
+    fn omnibus_tags_split_into_groups() {
+        assert_eq!(split_tag_name("Run/E"), (Some("Run"), "E"));
+        assert_eq!(split_tag_name("E"), (None, "E"));
+        // Only the first slash counts; the rest is the variant's problem.
+        assert_eq!(split_tag_name("Big/Run/N"), (Some("Big"), "Run/N"));
+    }
+
+    #[test]
+    fn near_miss_layer_names_get_flagged() {
+        let check = |names: &[&str]| check_layer_names(names.iter().copied());
+        assert!(check(&["walkbox", "hitbox", "Layer 1"]).unwrap().is_empty());
+        assert_eq!(check(&["walkbox", "HitBox"]).unwrap().len(), 1);
+        // Fine if the real one's there too.
+        assert!(check(&["hitbox", "Hitbox"]).unwrap().is_empty());
+        assert!(check(&["Walkbox"]).is_err());
+        assert!(check(&["walkbox "]).is_err());
+    }
+
+    #[test]
+    fn slice_keys_last_until_the_next_one() {
+        let key = |from_frame, origin, size| asefile::SliceKey {
+            from_frame,
+            origin,
+            size,
+            slice9: None,
+            pivot: None,
+        };
+        let slice = Slice {
+            name: "hitbox".to_string(),
+            keys: vec![key(1, (2, 3), (4, 2)), key(3, (0, 0), (0, 0))],
+            user_data: None,
+        };
+        assert_eq!(slice_rect(&slice, 0), None);
+        let expected = Some(Rect::new(2.0, 3.0, 5.0, 4.0));
+        assert_eq!(slice_rect(&slice, 1), expected);
+        assert_eq!(slice_rect(&slice, 2), expected);
+        // Shrunk to nothing.
+        assert_eq!(slice_rect(&slice, 3), None);
+    }
+}
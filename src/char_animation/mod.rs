@@ -17,7 +17,7 @@ fn charanm_test_set_motion_system(
     inputs: Res<crate::input::CurrentInputs>,
 ) {
     for mut motion in query.iter_mut() {
-        motion.face(inputs.movement * -1.0);
+        motion.face(inputs.player(crate::input::PlayerIndex::P1).movement * -1.0);
     }
 }
 
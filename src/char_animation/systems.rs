@@ -3,9 +3,12 @@ use bevy::sprite::{Anchor, TextureAtlas};
 
 use super::assets::*;
 use super::types::*;
-use crate::collision::{Hitbox, Hurtbox, Walkbox};
+use crate::collision::{Hitbox, HitboxActive, Hurtbox, Walkbox};
+use crate::combat::HitStop;
 use crate::compass::Dir;
+use crate::input::GamePaused;
 use crate::movement::Motion;
+use crate::status_effects::Stunned;
 use crate::toolbox::countup_timer::CountupTimer;
 use crate::toolbox::{flip_rect_x, flip_vec2_x};
 
@@ -19,8 +22,20 @@ pub struct CharAnimationPlugin;
 impl Plugin for CharAnimationPlugin {
     fn build(&self, app: &mut App) {
         app.init_asset::<CharAnimation>()
+            .register_asset_reflect::<CharAnimation>()
             .init_asset_loader::<CharAnimationLoader>()
+            // So they show up in the inspector.
+            .register_type::<CharAnimationState>()
+            .register_type::<CharAnimationVariant>()
+            .register_type::<CharAnimationFrame>()
+            .register_type::<Directionality>()
+            .register_type::<Playback>()
+            .register_type::<FrameTimeOverride>()
+            .register_type::<AnimateFinishedEvent>()
+            .register_type::<AnimateFrameEvent>()
             .add_event::<AnimateFinishedEvent>()
+            .add_event::<AnimateFrameEvent>()
+            .init_resource::<GamePaused>()
             // These systems should run after any app code that might mutate
             // CharAnimationState or Motion. And set_directions might have
             // mutated the animation state, so that should take effect before
@@ -28,8 +43,10 @@ impl Plugin for CharAnimationPlugin {
             .add_systems(
                 Update,
                 (
+                    charanm_asset_changed_system,
                     charanm_atlas_reassign_system,
                     charanm_set_directions_system,
+                    charanm_pause_system,
                     charanm_animate_system,
                     charanm_update_colliders_system,
                 )
@@ -79,6 +96,36 @@ fn charanm_set_directions_system(
     }
 }
 
+/// Freeze animations while the game's paused, or while their owner's stunned
+/// (so an attack windup hangs in the air during hitstun). Only acts on the
+/// edges, so it leaves alone anything somebody else paused on purpose. Hit
+/// stop handles its own pausing, but nothing here should resume through it.
+fn charanm_pause_system(
+    paused: Res<GamePaused>,
+    mut query: Query<(&mut CharAnimationState, Option<Ref<Stunned>>, Has<HitStop>)>,
+    mut unstunned: RemovedComponents<Stunned>,
+) {
+    let game_pause_changed = paused.is_changed();
+    for (mut state, stunned, hit_stopped) in query.iter_mut() {
+        let stun_started = stunned.as_ref().is_some_and(|s| s.is_added());
+        if game_pause_changed || stun_started {
+            if paused.0 || stunned.is_some() || hit_stopped {
+                state.pause();
+            } else {
+                state.resume();
+            }
+        }
+    }
+    for entity in unstunned.read() {
+        if paused.0 {
+            continue;
+        }
+        if let Ok((mut state, None, false)) = query.get_mut(entity) {
+            state.resume();
+        }
+    }
+}
+
 /// This system handles the main logic of progressing through an animation:
 /// tick the frame timer, then reset it and flip to the next animation frame
 /// if it's time to do that.
@@ -92,6 +139,7 @@ pub fn charanm_animate_system(
     )>,
     time: Res<Time>,
     mut finished_events: EventWriter<AnimateFinishedEvent>,
+    mut frame_events: EventWriter<AnimateFrameEvent>,
 ) {
     for (mut state, mut sprite, mut atlas, entity) in query.iter_mut() {
         let Some(animation) = animations.get(&state.animation) else {
@@ -140,6 +188,10 @@ pub fn charanm_animate_system(
 
                 // increment+loop frame, and replace the timer with the new frame's duration
                 state.frame = next_frame;
+                frame_events.send(AnimateFrameEvent {
+                    entity,
+                    frame: next_frame,
+                });
                 let duration = variant.resolved_frame_time(state.frame, state.frame_time_override);
                 let mut new_timer = CountupTimer::new(duration);
                 new_timer.tick(excess_time);
@@ -150,7 +202,15 @@ pub fn charanm_animate_system(
             // frame's duration, can start ticking on the next loop.
             updating_frame = true;
             let duration = variant.resolved_frame_time(state.frame, state.frame_time_override);
-            state.frame_timer = Some(CountupTimer::new(duration));
+            let mut timer = CountupTimer::new(duration);
+            if state.paused {
+                timer.pause();
+            }
+            state.frame_timer = Some(timer);
+            frame_events.send(AnimateFrameEvent {
+                entity,
+                frame: state.frame,
+            });
         }
 
         // ok, where was I.
@@ -193,15 +253,18 @@ fn charanm_update_colliders_system(
     animations: Res<Assets<CharAnimation>>,
     mut query: Query<
         (
+            Entity,
             &CharAnimationState,
             &mut Walkbox,
             Option<&mut Hitbox>,
             Option<&mut Hurtbox>,
+            Has<HitboxActive>,
         ),
         Changed<TextureAtlas>,
     >,
+    mut commands: Commands,
 ) {
-    for (state, mut walkbox, hitbox, hurtbox) in query.iter_mut() {
+    for (entity, state, mut walkbox, hitbox, hurtbox, was_active) in query.iter_mut() {
         let Some(animation) = animations.get(&state.animation) else {
             continue;
         };
@@ -221,6 +284,12 @@ fn charanm_update_colliders_system(
         // an optional inner value (entity is/isn't dealing damage this frame).
         if let Some(mut hit) = hitbox {
             hit.0 = frame.hitbox.map(|r| maybe_mirrored(r, state.flip_x));
+            let active = hit.0.is_some();
+            if active && !was_active {
+                commands.entity(entity).insert(HitboxActive);
+            } else if !active && was_active {
+                commands.entity(entity).remove::<HitboxActive>();
+            }
         }
         // Same for hurtbox.
         if let Some(mut hurt) = hurtbox {
@@ -238,6 +307,43 @@ fn maybe_mirrored(r: Rect, flip_x: bool) -> Rect {
     }
 }
 
+/// Hot reload support: when an animation asset gets reloaded out from under
+/// someone, make sure their spot in it still makes sense. If the variant and
+/// frame survived, stay put and just restart the frame's timer (its length
+/// might have changed); if not, go back to the start.
+fn charanm_asset_changed_system(
+    mut asset_events: EventReader<AssetEvent<CharAnimation>>,
+    animations: Res<Assets<CharAnimation>>,
+    mut query: Query<(Entity, &mut CharAnimationState)>,
+) {
+    for event in asset_events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        let Some(animation) = animations.get(*id) else {
+            continue;
+        };
+        let mut affected = Vec::new();
+        for (entity, mut state) in query.iter_mut() {
+            if state.animation.id() != *id {
+                continue;
+            }
+            let frame_survived = state
+                .variant
+                .and_then(|v| animation.variants.get(&v))
+                .is_some_and(|variant| state.frame < variant.frames.len());
+            if !frame_survived {
+                state.frame = 0;
+            }
+            state.frame_timer = None;
+            affected.push(entity);
+        }
+        if !affected.is_empty() {
+            info!("Reloaded animation {:?}, affecting {:?}", id, affected);
+        }
+    }
+}
+
 /// Texture atlas sprites require two asset Handles:
 ///
 /// - a Handle<Image> (as a loose component)
@@ -263,3 +369,113 @@ fn charanm_atlas_reassign_system(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_animation;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::utils::Duration;
+
+    fn timer_paused(app: &App, guy: Entity) -> bool {
+        let state = app.world().get::<CharAnimationState>(guy).unwrap();
+        state.frame_timer.as_ref().unwrap().paused()
+    }
+
+    #[test]
+    fn stuns_and_game_pauses_freeze_animations() {
+        let mut app = App::new();
+        app.init_resource::<GamePaused>()
+            .add_systems(Update, charanm_pause_system);
+        let mut state = CharAnimationState::new(Handle::default(), Dir::E, Playback::Loop);
+        state.frame_timer = Some(CountupTimer::new(Duration::from_millis(100)));
+        let guy = app.world_mut().spawn(state).id();
+        app.update();
+        assert!(!timer_paused(&app, guy));
+
+        app.world_mut().entity_mut(guy).insert(Stunned::new(1.0));
+        app.update();
+        assert!(timer_paused(&app, guy));
+        app.world_mut().entity_mut(guy).remove::<Stunned>();
+        app.update();
+        assert!(!timer_paused(&app, guy));
+
+        // Game pause holds through a stun ending.
+        app.world_mut().resource_mut::<GamePaused>().0 = true;
+        app.world_mut().entity_mut(guy).insert(Stunned::new(1.0));
+        app.update();
+        app.world_mut().entity_mut(guy).remove::<Stunned>();
+        app.update();
+        assert!(timer_paused(&app, guy));
+        app.world_mut().resource_mut::<GamePaused>().0 = false;
+        app.update();
+        assert!(!timer_paused(&app, guy));
+
+        // Somebody else's pause sticks, though.
+        app.world_mut()
+            .get_mut::<CharAnimationState>(guy)
+            .unwrap()
+            .pause();
+        app.update();
+        assert!(timer_paused(&app, guy));
+    }
+
+    #[test]
+    fn live_hitboxes_get_marked() {
+        let mut world = World::new();
+        let mut animation = test_animation(&[100; 2]);
+        let variant = animation.variants.get_mut(&Dir::E).unwrap();
+        variant.frames[1].hitbox = Some(Rect::new(0.0, 0.0, 4.0, 4.0));
+        let mut animations = Assets::<CharAnimation>::default();
+        let handle = animations.add(animation);
+        world.insert_resource(animations);
+        let guy = world
+            .spawn((
+                CharAnimationState::new(handle, Dir::E, Playback::Loop),
+                TextureAtlas::default(),
+                Walkbox::default(),
+                Hitbox::default(),
+            ))
+            .id();
+        let go_to_frame = |world: &mut World, frame| {
+            let mut entity = world.entity_mut(guy);
+            entity.get_mut::<CharAnimationState>().unwrap().frame = frame;
+            entity.get_mut::<TextureAtlas>().unwrap().set_changed();
+            world.run_system_once(charanm_update_colliders_system);
+            world.get::<HitboxActive>(guy).is_some()
+        };
+        assert!(!go_to_frame(&mut world, 0));
+        assert!(go_to_frame(&mut world, 1));
+        assert!(!go_to_frame(&mut world, 0));
+    }
+
+    #[test]
+    fn reloads_keep_your_place_if_they_can() {
+        let mut world = World::new();
+        world.init_resource::<Events<AssetEvent<CharAnimation>>>();
+        let mut animations = Assets::<CharAnimation>::default();
+        let handle = animations.add(test_animation(&[100; 4]));
+        world.insert_resource(animations);
+        let spawn_at = |world: &mut World, frame| {
+            let mut state = CharAnimationState::new(handle.clone(), Dir::E, Playback::Loop);
+            state.frame = frame;
+            state.frame_timer = Some(CountupTimer::new(Duration::from_millis(100)));
+            world.spawn(state).id()
+        };
+        let early = spawn_at(&mut world, 1);
+        let late = spawn_at(&mut world, 3);
+
+        // Artist trims it down to two frames.
+        world
+            .resource_mut::<Assets<CharAnimation>>()
+            .insert(&handle, test_animation(&[100; 2]));
+        world.send_event(AssetEvent::Modified { id: handle.id() });
+        world.run_system_once(charanm_asset_changed_system);
+
+        let state = |guy| world.get::<CharAnimationState>(guy).unwrap();
+        assert_eq!(state(early).frame, 1);
+        assert!(state(early).frame_timer.is_none());
+        assert_eq!(state(late).frame, 0);
+        assert!(state(late).frame_timer.is_none());
+    }
+}
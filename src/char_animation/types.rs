@@ -1,17 +1,18 @@
 use bevy::asset::Asset;
+use bevy::asset::Assets;
 use bevy::asset::Handle;
 use bevy::math::{prelude::*, Rect};
 use bevy::prelude::{Component, Entity, Event};
 use bevy::reflect::Reflect;
+use bevy::render::texture::Image;
 use bevy::sprite::TextureAtlasLayout;
 use bevy::utils::Duration;
-use bevy::{reflect::TypePath, render::texture::Image};
 use std::collections::HashMap;
 
 use crate::compass::{self};
 use crate::toolbox::countup_timer::CountupTimer;
 
-#[derive(Asset, Debug, TypePath)]
+#[derive(Asset, Debug, Reflect)]
 pub struct CharAnimation {
     pub variants: VariantsMap,
     pub directionality: Directionality,
@@ -19,7 +20,7 @@ pub struct CharAnimation {
     pub texture: Handle<Image>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Reflect)]
 pub struct CharAnimationVariant {
     pub name: VariantName,
     pub frames: Vec<CharAnimationFrame>,
@@ -49,6 +50,15 @@ impl CharAnimationVariant {
         }
     }
 
+    /// How long one full pass through the variant takes, after the override.
+    /// Sums the resolved frame times, so it picks up the same rounding the
+    /// animate system will.
+    pub fn total_duration(&self, override_type: FrameTimeOverride) -> Duration {
+        (0..self.frames.len())
+            .map(|i| self.resolved_frame_time(i, override_type))
+            .sum()
+    }
+
     #[inline]
     pub fn raw_frame_time(&self, frame_index: usize) -> Duration {
         self.frames[frame_index].duration
@@ -78,7 +88,7 @@ pub enum Directionality {
 /// some points and rectangles. The points have some particular frame of
 /// reference (described in comments), but the rectangles are all relative to
 /// the origin point and laid out in Bevy spatial coordinate space (y-up).
-#[derive(Debug)]
+#[derive(Debug, Reflect)]
 pub struct CharAnimationFrame {
     /// Index into the `TextureAtlas`.
     pub index: usize,
@@ -97,12 +107,25 @@ pub struct CharAnimationFrame {
     pub hitbox: Option<Rect>,
     /// Bbox for the damageable area of a frame.
     pub hurtbox: Option<Rect>,
+    /// Every aseprite slice present on this frame, by name. Same coordinates
+    /// as the boxes above. Includes the well-known ones, if they came from
+    /// slices instead of layers.
+    pub slices: HashMap<String, Rect>,
 }
 
-#[derive(Event)]
+#[derive(Event, Reflect)]
 pub struct AnimateFinishedEvent(pub Entity);
 
-#[derive(Component, Debug)]
+/// Event: an entity's animation just landed on a new frame (including the
+/// first frame of a fresh animation). For doing stuff at a particular point
+/// in an animation.
+#[derive(Event, Reflect)]
+pub struct AnimateFrameEvent {
+    pub entity: Entity,
+    pub frame: usize,
+}
+
+#[derive(Component, Debug, Reflect)]
 pub struct CharAnimationState {
     pub animation: Handle<CharAnimation>,
     pub variant: Option<VariantName>,
@@ -118,9 +141,12 @@ pub struct CharAnimationState {
     /// frames to a uniform duration (in ms), split a given duration among all
     /// frames, or scale all frames by some factor.
     pub frame_time_override: FrameTimeOverride,
+    /// Frozen in place. Survives animation changes, so a new animation that
+    /// starts mid-freeze comes up frozen too.
+    pub paused: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Reflect)]
 pub enum Playback {
     Loop,
     Once,
@@ -130,7 +156,7 @@ pub enum Playback {
 /// data, for things like stretching out a motion to fit it to a particular
 /// total duration.
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Reflect)]
 pub enum FrameTimeOverride {
     None,
     Ms(u64),
@@ -150,6 +176,7 @@ impl CharAnimationState {
             frame: 0,
             frame_timer: None,
             frame_time_override: FrameTimeOverride::None,
+            paused: false,
         }
     }
 
@@ -194,6 +221,57 @@ impl CharAnimationState {
         self.frame_time_override = FrameTimeOverride::TotalMs(millis);
     }
 
+    /// Freeze the animation on its current frame, partway through and all.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        if let Some(timer) = &mut self.frame_timer {
+            timer.pause();
+        }
+    }
+
+    /// Pick back up right where pause() left off.
+    pub fn resume(&mut self) {
+        self.paused = false;
+        if let Some(timer) = &mut self.frame_timer {
+            timer.unpause();
+        }
+    }
+
+    fn current_variant<'a>(
+        &self,
+        animations: &'a Assets<CharAnimation>,
+    ) -> Option<&'a CharAnimationVariant> {
+        animations
+            .get(&self.animation)?
+            .variants
+            .get(self.variant.as_ref()?)
+    }
+
+    /// How long the current animation takes to play through once, counting
+    /// any frame time override. None if the asset isn't loaded yet.
+    pub fn total_duration(&self, animations: &Assets<CharAnimation>) -> Option<Duration> {
+        self.current_variant(animations)
+            .map(|variant| variant.total_duration(self.frame_time_override))
+    }
+
+    /// How much of the current pass is left: the total, minus every frame
+    /// before this one and however far the frame timer's gotten. Bottoms out
+    /// at zero once a Once animation is parked on its last frame.
+    pub fn remaining_total(&self, animations: &Assets<CharAnimation>) -> Option<Duration> {
+        let total = self.total_duration(animations)?;
+        let variant = self.current_variant(animations)?;
+        let earlier_frames: Duration = (0..self.frame)
+            .map(|i| variant.resolved_frame_time(i, self.frame_time_override))
+            .sum();
+        // No timer means it starts on the next animate pass, so the frame's untouched.
+        let this_frame = self.frame_timer.as_ref().map_or(Duration::ZERO, |timer| {
+            variant
+                .resolved_frame_time(self.frame, self.frame_time_override)
+                .saturating_sub(timer.remaining())
+        });
+        Some(total.saturating_sub(earlier_frames + this_frame))
+    }
+
     pub fn timer_just_finished(&self) -> bool {
         matches!(
             self.frame_timer.as_ref().map(|t| t.just_finished()),
@@ -201,3 +279,46 @@ impl CharAnimationState {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{test_animation, test_variant};
+
+    #[test]
+    fn total_duration_follows_overrides() {
+        let variant = test_variant(&[100, 100, 200]);
+        let total = |o| variant.total_duration(o).as_millis();
+        assert_eq!(total(FrameTimeOverride::None), 400);
+        assert_eq!(total(FrameTimeOverride::Ms(50)), 150);
+        assert_eq!(total(FrameTimeOverride::Scale(0.5)), 200);
+        assert_eq!(total(FrameTimeOverride::TotalMs(800)), 800);
+    }
+
+    #[test]
+    fn remaining_total_counts_down_through_frames() {
+        let mut animations = Assets::<CharAnimation>::default();
+        let handle = animations.add(test_animation(&[100, 100, 200]));
+        let mut state = CharAnimationState::new(handle, compass::Dir::E, Playback::Once);
+        let remaining =
+            |state: &CharAnimationState| state.remaining_total(&animations).unwrap().as_millis();
+
+        assert_eq!(state.total_duration(&animations).unwrap().as_millis(), 400);
+        assert_eq!(remaining(&state), 400);
+
+        state.frame = 1;
+        let mut timer = CountupTimer::new(Duration::from_millis(100));
+        timer.tick(Duration::from_millis(30));
+        state.frame_timer = Some(timer);
+        assert_eq!(remaining(&state), 270);
+
+        state.frame = 2;
+        let mut timer = CountupTimer::new(Duration::from_millis(200));
+        timer.tick(Duration::from_millis(500));
+        state.frame_timer = Some(timer);
+        assert_eq!(remaining(&state), 0);
+
+        state.variant = None;
+        assert_eq!(state.remaining_total(&animations), None);
+    }
+}
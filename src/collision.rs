@@ -1,3 +1,4 @@
+use crate::toolbox::expand_rect_xy;
 use bevy::prelude::*;
 
 /// BBox defining the space an entity takes up on the ground.
@@ -11,6 +12,74 @@ pub struct Hitbox(pub Option<Rect>);
 #[derive(Component, Reflect, Default)]
 pub struct Hurtbox(pub Option<Rect>);
 
+/// Marker: this entity's Hitbox is live right now (Some, not None). Kept in
+/// sync by the animation system; projectiles get it when they spawn, since
+/// their hitbox never goes away. Combat treats it showing up as the start of
+/// a new swing. Flickers on and off a lot, hence the sparse storage.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct HitboxActive;
+
+// Collision layer bits. An entity can be on more than one layer, if it's weird.
+pub const LAYER_PLAYER: u32 = 1 << 0;
+pub const LAYER_ENEMY: u32 = 1 << 1;
+pub const LAYER_PROJECTILE: u32 = 1 << 2;
+pub const LAYER_WALL: u32 = 1 << 3;
+/// Enemy shots. Enemies mask this out, so only players get hit.
+pub const LAYER_ENEMY_PROJECTILE: u32 = 1 << 4;
+
+/// Bitmask of which collision layers an entity lives on. If an entity doesn't
+/// have one, treat it as living on every layer.
+#[derive(Component, Reflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionLayer(pub u32);
+
+impl CollisionLayer {
+    pub fn all() -> Self {
+        Self(u32::MAX)
+    }
+}
+
+impl Default for CollisionLayer {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Bitmask of which collision layers an entity is willing to be hit by. If an
+/// entity doesn't have one, it collides with everything, same as before layers
+/// existed.
+#[derive(Component, Reflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CollisionMask(pub u32);
+
+impl CollisionMask {
+    pub fn all() -> Self {
+        Self(u32::MAX)
+    }
+}
+
+impl Default for CollisionMask {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Check whether a moving subject is allowed to bump into some object, given
+/// the subject's layer and the object's mask. Missing components mean "no
+/// filtering."
+pub fn layers_collide(
+    subject_layer: Option<&CollisionLayer>,
+    object_mask: Option<&CollisionMask>,
+) -> bool {
+    let layer = subject_layer.copied().unwrap_or_default();
+    let mask = object_mask.copied().unwrap_or_default();
+    layer.0 & mask.0 != 0
+}
+
+/// How far a rect reaches from its owner's origin, in any direction.
+pub fn rect_reach(rect: Rect) -> f32 {
+    rect.min.abs().max(rect.max.abs()).length()
+}
+
 pub fn centered_rect(width: f32, height: f32) -> Rect {
     let min = Vec2::new(-width / 2., -height / 2.);
     let max = Vec2::new(width / 2., height / 2.);
@@ -37,6 +106,22 @@ enum Side {
     Right,
 }
 
+/// A circle located in absolute space. For things that reach the same
+/// distance in every direction, like aggro radii and blasts.
+#[derive(Copy, Clone, Debug)]
+pub struct AbsCircle {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+impl AbsCircle {
+    /// Whether any part of the box is inside the circle. Touching counts.
+    pub fn collide_rect(&self, other: AbsBBox) -> bool {
+        let closest = self.center.clamp(other.min, other.max);
+        closest.distance_squared(self.center) <= self.radius * self.radius
+    }
+}
+
 /// An AABB that's located in absolute space, probably produced by combining a
 /// BBox with an origin offset.
 #[derive(Copy, Clone, Debug)]
@@ -54,6 +139,56 @@ impl AbsBBox {
         }
     }
 
+    pub fn center(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Width and height.
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    fn as_rect(&self) -> Rect {
+        Rect {
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    fn from_abs_rect(rect: Rect) -> Self {
+        Self {
+            min: rect.min,
+            max: rect.max,
+        }
+    }
+
+    /// Grow all four sides outward by `amount`. Negative shrinks, down to a
+    /// zero-size box at the center. (See toolbox::expand_rect_xy.)
+    pub fn expand(&self, amount: f32) -> Self {
+        self.expand_xy(amount, amount)
+    }
+
+    /// Grow the left/right sides by `x` and top/bottom by `y`.
+    pub fn expand_xy(&self, x: f32, y: f32) -> Self {
+        Self::from_abs_rect(expand_rect_xy(self.as_rect(), x, y))
+    }
+
+    /// The part that's inside both boxes. Boxes that are only touching along
+    /// an edge don't have one.
+    pub fn overlap_rect(&self, other: Self) -> Option<Self> {
+        let min = self.min.max(other.min);
+        let max = self.max.min(other.max);
+        (min.x < max.x && min.y < max.y).then_some(Self { min, max })
+    }
+
+    /// How much area two boxes share. Zero if they're apart or just touching.
+    pub fn overlap_area(&self, other: Self) -> f32 {
+        self.overlap_rect(other).map_or(0.0, |overlap| {
+            let size = overlap.size();
+            size.x * size.y
+        })
+    }
+
     /// Learned this algorithm from https://www.youtube.com/watch?v=8JJ-4JgR7Dg
     pub fn ray_collide(&self, ray_start: Vec2, ray_displacement: Vec2) -> Option<Collision> {
         // First, we find the "normalized times" where the LINE defined by the
@@ -151,14 +286,27 @@ impl AbsBBox {
         ray_collision
     }
 
-    /// Return a new AbsBBox with each side expanded by the _opposite_ side of a
-    /// relatively-defined Rect, to enable ray-intersection collision tests
-    /// from the origin point that the provided Rect was defined against.
-    pub fn expand_for_ray_test(&self, other: &Rect) -> Self {
-        AbsBBox {
-            min: self.min - other.max, // subtract bc... draw a diagram & you'll see.
-            max: self.max - other.min,
+    /// Swept-AABB test: if self moves by `velocity`, does it hit other before
+    /// the end of the move? Under the hood it grows other by self's size, then
+    /// ray-tests from self's center. The returned contact_point is where self's
+    /// center would be at the moment of contact.
+    /// Notes:
+    /// - Zero velocity never collides; this is about moving into things.
+    /// - If the boxes already overlap and self isn't moving out, you get a
+    ///   collision with a negative normalized_time (it "happened" in the past).
+    /// - Boxes that start out touching count as colliding at time 0 if self
+    ///   moves into other, but not if it moves away or slides along the edge.
+    pub fn sweep_cast(&self, velocity: Vec2, other: Self) -> Option<Collision> {
+        if velocity == Vec2::ZERO {
+            return None;
         }
+        let half_size = self.size() / 2.0;
+        let center = self.center();
+        let expanded = AbsBBox {
+            min: other.min - half_size,
+            max: other.max + half_size,
+        };
+        expanded.segment_collide(center, velocity)
     }
 
     /// Check whether an absolutely positioned bbox overlaps with another one.
@@ -190,6 +338,32 @@ impl AbsBBox {
         )
     }
 
+    /// If two boxes overlap, return the smallest translation that would move
+    /// self back out of other (the "minimum translation vector"). Boxes that
+    /// are merely touching along an edge don't count as overlapping for this
+    /// purpose, even though `collide` says they do. Ties go to the X axis and
+    /// the positive direction.
+    pub fn penetration_depth(&self, other: Self) -> Option<Vec2> {
+        // On each axis, there's two ways out: how far you'd have to go in the
+        // positive direction to clear other's far side, and how far in the
+        // negative direction to clear its near side. If either one is zero or
+        // less, we aren't overlapping on that axis at all.
+        let push_pos = other.max - self.min;
+        let push_neg = self.max - other.min;
+        if push_pos.cmple(Vec2::ZERO).any() || push_neg.cmple(Vec2::ZERO).any() {
+            return None;
+        }
+
+        let cheapest = |pos: f32, neg: f32| if pos <= neg { pos } else { -neg };
+        let x = cheapest(push_pos.x, push_neg.x);
+        let y = cheapest(push_pos.y, push_neg.y);
+        if x.abs() <= y.abs() {
+            Some(Vec2::new(x, 0.0))
+        } else {
+            Some(Vec2::new(0.0, y))
+        }
+    }
+
     /// Return the new AbsBBox that would result from moving self by `movement`.
     pub fn translate(&self, movement: Vec2) -> Self {
         Self {
@@ -277,6 +451,13 @@ impl Toward {
 #[derive(Component)]
 pub struct Solid;
 
+/// Marker component for solids that actually move around, like platforms.
+/// These are still Solids, so the solids R* tree tracks them the same as
+/// walls (and its update_moved system keeps their entries current), but they
+/// also need a Motion to get moved by the move_solids system.
+#[derive(Component)]
+pub struct MovingSolid;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -316,4 +497,241 @@ mod tests {
         assert!(reference_square.collide(onesie_at_xy(0., -0.8)));
         assert!(reference_square.collide(onesie_at_xy(0., -1.0)));
     }
+
+    #[test]
+    fn absbbox_penetration_depth() {
+        let reference_square = onesie_at_xy(0., 0.);
+
+        // No overlap at all
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(3.0, 0.)),
+            None
+        );
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(0., -3.0)),
+            None
+        );
+        // Touching edges and corners isn't overlapping
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(1.0, 0.)),
+            None
+        );
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(0., -1.0)),
+            None
+        );
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(1.0, 1.0)),
+            None
+        );
+        // Overlapping on only one axis isn't overlapping
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(0.5, 2.0)),
+            None
+        );
+
+        // Shallow on X: push left, away from a box to the right
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(0.75, 0.1)),
+            Some(Vec2::new(-0.25, 0.0))
+        );
+        // ...and right, away from a box to the left
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(-0.75, -0.1)),
+            Some(Vec2::new(0.25, 0.0))
+        );
+        // Shallow on Y: push down, away from a box above
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(0.1, 0.75)),
+            Some(Vec2::new(0.0, -0.25))
+        );
+        // ...and up, away from a box below
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(-0.1, -0.75)),
+            Some(Vec2::new(0.0, 0.25))
+        );
+        // Identical boxes: X wins the tie, positive direction
+        assert_eq!(
+            reference_square.penetration_depth(onesie_at_xy(0., 0.)),
+            Some(Vec2::new(1.0, 0.0))
+        );
+        // Small box fully inside a big one: shortest way out
+        let big = AbsBBox {
+            min: Vec2::new(-5.0, -5.0),
+            max: Vec2::new(5.0, 5.0),
+        };
+        assert_eq!(
+            onesie_at_xy(3.0, 0.0).penetration_depth(big),
+            Some(Vec2::new(2.0, 0.0))
+        );
+        // Result actually separates the boxes
+        let stuck = onesie_at_xy(0.5, 0.25);
+        let fix = stuck.penetration_depth(reference_square).unwrap();
+        assert_eq!(
+            stuck.translate(fix).penetration_depth(reference_square),
+            None
+        );
+    }
+
+    #[test]
+    fn absbbox_sweep_cast_axis_aligned() {
+        let mover = onesie_at_xy(0., 0.);
+
+        // Straight right into a box
+        let hit = mover
+            .sweep_cast(Vec2::new(4.0, 0.0), onesie_at_xy(3.0, 0.))
+            .unwrap();
+        assert_eq!(hit.normal, Vec2::NEG_X);
+        assert_eq!(hit.normalized_time, 0.5);
+        assert_eq!(hit.contact_point, Vec2::new(2.5, 0.5));
+        // Straight down into a box, partially offset on the other axis
+        let hit = mover
+            .sweep_cast(Vec2::new(0.0, -2.0), onesie_at_xy(0.5, -2.0))
+            .unwrap();
+        assert_eq!(hit.normal, Vec2::Y);
+        assert_eq!(hit.normalized_time, 0.5);
+        // Left and up, for completeness
+        let hit = mover
+            .sweep_cast(Vec2::new(-2.0, 0.0), onesie_at_xy(-2.0, 0.))
+            .unwrap();
+        assert_eq!(hit.normal, Vec2::X);
+        let hit = mover
+            .sweep_cast(Vec2::new(0.0, 2.0), onesie_at_xy(0., 2.0))
+            .unwrap();
+        assert_eq!(hit.normal, Vec2::NEG_Y);
+
+        // Stops short
+        assert!(mover
+            .sweep_cast(Vec2::new(1.5, 0.0), onesie_at_xy(3.0, 0.))
+            .is_none());
+        // Wrong way
+        assert!(mover
+            .sweep_cast(Vec2::new(-4.0, 0.0), onesie_at_xy(3.0, 0.))
+            .is_none());
+        // Not moving
+        assert!(mover
+            .sweep_cast(Vec2::ZERO, onesie_at_xy(0.5, 0.))
+            .is_none());
+    }
+
+    #[test]
+    fn absbbox_sweep_cast_diagonal() {
+        let mover = onesie_at_xy(0., 0.);
+
+        // Hits the left side first
+        let hit = mover
+            .sweep_cast(Vec2::new(4.0, 4.0), onesie_at_xy(2.0, 1.5))
+            .unwrap();
+        assert_eq!(hit.normal, Vec2::NEG_X);
+        assert_eq!(hit.normalized_time, 0.25);
+        assert_eq!(hit.contact_point, Vec2::new(1.5, 1.5));
+        // Hits the bottom side first
+        let hit = mover
+            .sweep_cast(Vec2::new(4.0, 4.0), onesie_at_xy(1.5, 2.0))
+            .unwrap();
+        assert_eq!(hit.normal, Vec2::NEG_Y);
+        assert_eq!(hit.normalized_time, 0.25);
+
+        // Near misses: sails past the corner
+        assert!(mover
+            .sweep_cast(Vec2::new(4.0, 4.0), onesie_at_xy(3.0, 0.))
+            .is_none());
+        assert!(mover
+            .sweep_cast(Vec2::new(4.0, 0.0), onesie_at_xy(2.0, 3.0))
+            .is_none());
+    }
+
+    #[test]
+    fn absbbox_sweep_cast_touching_and_overlapping() {
+        let mover = onesie_at_xy(0., 0.);
+
+        // Touching, moving in: immediate hit
+        let hit = mover
+            .sweep_cast(Vec2::new(1.0, 0.0), onesie_at_xy(1.0, 0.))
+            .unwrap();
+        assert_eq!(hit.normalized_time, 0.0);
+        assert_eq!(hit.normal, Vec2::NEG_X);
+        // Touching, moving away: nothing
+        assert!(mover
+            .sweep_cast(Vec2::new(-1.0, 0.0), onesie_at_xy(1.0, 0.))
+            .is_none());
+        // Already overlapping: collision in the past
+        let hit = mover
+            .sweep_cast(Vec2::new(1.0, 0.0), onesie_at_xy(0.5, 0.))
+            .unwrap();
+        assert!(hit.normalized_time < 0.0);
+    }
+
+    #[test]
+    fn collision_layers() {
+        let player = CollisionLayer(LAYER_PLAYER);
+        let ignores_player = CollisionMask(LAYER_ENEMY | LAYER_WALL);
+
+        assert!(!layers_collide(Some(&player), Some(&ignores_player)));
+        assert!(layers_collide(Some(&player), Some(&CollisionMask::all())));
+        assert!(!layers_collide(Some(&player), Some(&CollisionMask(0))));
+        assert!(layers_collide(
+            Some(&CollisionLayer(LAYER_ENEMY)),
+            Some(&ignores_player)
+        ));
+        // Missing components don't filter anything
+        assert!(layers_collide(None, None));
+        assert!(layers_collide(Some(&player), None));
+        assert!(layers_collide(None, Some(&ignores_player)));
+        assert!(!layers_collide(None, Some(&CollisionMask(0))));
+    }
+
+    #[test]
+    fn bbox_center_size_and_expand() {
+        let bbox = AbsBBox::from_rect(centered_rect(8.0, 4.0), Vec2::new(10.0, -20.0));
+        assert_eq!(bbox.center(), Vec2::new(10.0, -20.0));
+        assert_eq!(bbox.size(), Vec2::new(8.0, 4.0));
+        let lopsided = AbsBBox {
+            min: Vec2::new(2.0, 3.0),
+            max: Vec2::new(6.0, 11.0),
+        };
+        assert_eq!(lopsided.center(), Vec2::new(4.0, 7.0));
+        let bigger = lopsided.expand(1.0);
+        assert_eq!(bigger.min, Vec2::new(1.0, 2.0));
+        assert_eq!(bigger.max, Vec2::new(7.0, 12.0));
+        assert_eq!(bigger.center(), lopsided.center());
+        assert_eq!(bigger.size(), Vec2::new(6.0, 10.0));
+        let squashed = lopsided.expand_xy(1.0, -2.0);
+        assert_eq!(squashed.min, Vec2::new(1.0, 5.0));
+        assert_eq!(squashed.max, Vec2::new(7.0, 9.0));
+    }
+
+    #[test]
+    fn circles_vs_boxes() {
+        let circle = AbsCircle {
+            center: Vec2::ZERO,
+            radius: 5.0,
+        };
+        let box_at = |x: f32, y: f32| AbsBBox::from_rect(centered_rect(2.0, 2.0), Vec2::new(x, y));
+        // Edge pokes in, even though the center's outside.
+        assert!(circle.collide_rect(box_at(5.5, 0.0)));
+        assert!(!circle.collide_rect(box_at(6.5, 0.0)));
+        // Corners are farther than edges: (4,4) to the origin is ~5.66.
+        assert!(!circle.collide_rect(box_at(5.0, 5.0)));
+        assert!(circle.collide_rect(box_at(4.0, 4.0)));
+        // Circle entirely inside a box.
+        assert!(circle.collide_rect(AbsBBox::from_rect(centered_rect(100.0, 100.0), Vec2::ZERO)));
+    }
+
+    #[test]
+    fn overlap_areas() {
+        let at = |x: f32, y: f32| AbsBBox::from_rect(centered_rect(4.0, 4.0), Vec2::new(x, y));
+        // Apart, and just touching.
+        assert_eq!(at(0.0, 0.0).overlap_area(at(10.0, 0.0)), 0.0);
+        assert_eq!(at(0.0, 0.0).overlap_area(at(4.0, 0.0)), 0.0);
+        assert!(at(0.0, 0.0).overlap_rect(at(4.0, 0.0)).is_none());
+        // Same box.
+        assert_eq!(at(1.0, 1.0).overlap_area(at(1.0, 1.0)), 16.0);
+        // Partway: a 3x2 corner.
+        let overlap = at(0.0, 0.0).overlap_rect(at(1.0, 2.0)).unwrap();
+        assert_eq!(overlap.min, Vec2::new(-1.0, 0.0));
+        assert_eq!(overlap.max, Vec2::new(2.0, 2.0));
+        assert_eq!(at(0.0, 0.0).overlap_area(at(1.0, 2.0)), 6.0);
+        assert_eq!(at(1.0, 2.0).overlap_area(at(0.0, 0.0)), 6.0);
+    }
 }
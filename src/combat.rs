@@ -0,0 +1,534 @@
+//! Hitting things. Hitboxes and hurtboxes get updated per-frame by the
+//! char_animation systems; this module checks them against each other and
+//! turns overlaps into damage events. Landing a hit also freezes both
+//! parties' animations for a moment (hit stop), for some crunch.
+
+use crate::{
+    behaviors::{Invincible, KnockbackImpulse},
+    char_animation::{CharAnimation, CharAnimationState},
+    collision::{
+        layers_collide, rect_reach, AbsBBox, CollisionLayer, CollisionMask, Hitbox, HitboxActive,
+        Hurtbox,
+    },
+    debug_settings::NumbersSettings,
+    health::{Dying, TakeDamage},
+    input::GamePaused,
+    phys_space::PhysTransform,
+    space_lookup::RstarAccess,
+    status_effects::Stunned,
+    toolbox::countup_timer::CountupTimer,
+};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+
+/// Which side an entity is on. Hitboxes don't hurt their own team. Entities
+/// with no team are fair game for everyone.
+#[derive(Component, Reflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Team(pub u8);
+
+impl Team {
+    pub const PLAYER: Self = Self(0);
+    pub const ENEMY: Self = Self(1);
+}
+
+/// How much damage an entity's hitbox does. Defaults to 1 if absent.
+#[derive(Component, Reflect, Clone, Copy, Debug)]
+pub struct Damage(pub i32);
+
+/// Guard against hitting the same target on every frame of a single swing.
+/// Remembers who got hit during which animation, and forgets them once the
+/// attacker switches animations or its hitbox comes back out.
+#[derive(Component, Default)]
+pub struct HitThisSwing {
+    pub swing: Option<AssetId<CharAnimation>>,
+    pub victims: HashSet<Entity>,
+}
+
+/// Component: this entity's animation is frozen for a beat, because it just
+/// hit something or got hit. Only the animation; it can still move, and its
+/// state timers keep running.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct HitStop {
+    pub duration: CountupTimer,
+}
+
+pub const HIT_STOP_MS: u64 = 80;
+
+type HurtboxTree = RstarAccess<Hurtbox>;
+
+/// The hurtbox tree only knows where entities' origins are, not how big their
+/// hurtboxes are, so we scan this much farther than the hitbox's reach to
+/// catch hurtboxes that stick out from their owners.
+const HURTBOX_SCANNING_MARGIN: f32 = 32.0;
+
+/// How hard a hit shoves its target, as one frame of velocity (so it's big).
+const HIT_KNOCKBACK: f32 = 360.0;
+
+/// Check every active hitbox against nearby active hurtboxes, and send damage
+/// for overlaps. Expects to run in PostUpdate, once everything's done moving.
+pub fn hitbox_hurtbox_collision_system(
+    mut attacker_q: Query<(
+        Entity,
+        &Hitbox,
+        &PhysTransform,
+        Option<&CharAnimationState>,
+        &mut HitThisSwing,
+        Option<&Team>,
+        Option<&Damage>,
+        Option<&CollisionLayer>,
+        Option<Ref<HitboxActive>>,
+    )>,
+    target_q: Query<(
+        &Hurtbox,
+        &PhysTransform,
+        Option<&Team>,
+        Option<&CollisionMask>,
+    )>,
+    hurtbox_tree: Res<HurtboxTree>,
+    mut damage_events: EventWriter<TakeDamage>,
+    mut knockback_events: EventWriter<KnockbackImpulse>,
+) {
+    for (
+        attacker,
+        hitbox,
+        a_transform,
+        anim_state,
+        mut hit_this_swing,
+        a_team,
+        damage,
+        a_layer,
+        active,
+    ) in attacker_q.iter_mut()
+    {
+        // New animation, new swing. (Things without animations, like
+        // projectiles, only get the one swing.)
+        if let Some(anim_state) = anim_state {
+            let swing = anim_state.animation.id();
+            if hit_this_swing.swing != Some(swing) {
+                hit_this_swing.swing = Some(swing);
+                hit_this_swing.victims.clear();
+            }
+            // Hitbox just came out: also a new swing, even if it's the same
+            // animation starting over before the last swing's hitbox cleared.
+            if active.is_some_and(|a| a.is_added()) {
+                hit_this_swing.victims.clear();
+            }
+        }
+
+        let Some(hit_rect) = hitbox.0 else {
+            continue;
+        };
+        let a_loc = a_transform.translation.truncate();
+        let abs_hitbox = AbsBBox::from_rect(hit_rect, a_loc);
+        let amount = damage.map_or(1, |d| d.0);
+        let scan_distance = rect_reach(hit_rect) + HURTBOX_SCANNING_MARGIN;
+
+        for (_, target) in hurtbox_tree.within_distance(a_loc, scan_distance) {
+            if target == attacker || hit_this_swing.victims.contains(&target) {
+                continue;
+            }
+            let Ok((hurtbox, t_transform, t_team, t_mask)) = target_q.get(target) else {
+                continue;
+            };
+            if a_team.is_some() && a_team == t_team {
+                continue;
+            }
+            if !layers_collide(a_layer, t_mask) {
+                continue;
+            }
+            let Some(hurt_rect) = hurtbox.0 else {
+                continue;
+            };
+            let abs_hurtbox = AbsBBox::from_rect(hurt_rect, t_transform.translation.truncate());
+            if abs_hitbox.collide(abs_hurtbox) {
+                hit_this_swing.victims.insert(target);
+                damage_events.send(TakeDamage {
+                    target,
+                    amount,
+                    source: Some(attacker),
+                });
+                // Shove em directly away from the attacker.
+                let away = (t_transform.translation.truncate() - a_loc).normalize_or_zero();
+                knockback_events.send(KnockbackImpulse {
+                    entity: target,
+                    vector: away * HIT_KNOCKBACK,
+                    z_velocity: 0.0,
+                });
+            }
+        }
+    }
+}
+
+/// Start hit stop on both ends of every hit that lands. Only hits with a
+/// source count; poison ticks and traps aren't impacts.
+pub fn hit_stop_start_system(
+    mut damage_events: EventReader<TakeDamage>,
+    vulnerable_q: Query<(), (Without<Invincible>, Without<Dying>)>,
+    mut anim_q: Query<&mut CharAnimationState>,
+    numbers: Res<NumbersSettings>,
+    mut commands: Commands,
+) {
+    if numbers.hit_stop_ms == 0 {
+        return;
+    }
+    let duration = bevy::utils::Duration::from_millis(numbers.hit_stop_ms);
+    for damage in damage_events.read() {
+        for entity in hit_stop_ends(damage, &vulnerable_q) {
+            if let Ok(mut state) = anim_q.get_mut(entity) {
+                state.pause();
+                // A fresh hit starts the freeze over.
+                commands.entity(entity).try_insert(HitStop {
+                    duration: CountupTimer::new(duration),
+                });
+            }
+        }
+    }
+}
+
+/// Who freezes for a hit: the attacker and the target, or nobody for a
+/// sourceless hit or one that won't land (same rules as health_drain_system).
+fn hit_stop_ends(
+    damage: &TakeDamage,
+    vulnerable_q: &Query<(), (Without<Invincible>, Without<Dying>)>,
+) -> impl Iterator<Item = Entity> {
+    let lands = damage.amount > 0 && vulnerable_q.contains(damage.target);
+    damage
+        .source
+        .filter(|_| lands)
+        .map(|source| [source, damage.target])
+        .into_iter()
+        .flatten()
+}
+
+/// Let hit stop run out. Anything that's also stunned or game-paused stays
+/// frozen; charanm_pause_system gets it moving again when that's over.
+/// Anything that just got hit again is left alone, since hit_stop_start_system
+/// is handing it a fresh HitStop this frame and a remove would eat it.
+pub fn hit_stop_system(
+    time: Res<Time>,
+    paused: Res<GamePaused>,
+    mut damage_events: EventReader<TakeDamage>,
+    vulnerable_q: Query<(), (Without<Invincible>, Without<Dying>)>,
+    mut stopped_q: Query<(Entity, &mut HitStop, &mut CharAnimationState, Has<Stunned>)>,
+    mut commands: Commands,
+) {
+    let rehit: Vec<Entity> = damage_events
+        .read()
+        .flat_map(|damage| hit_stop_ends(damage, &vulnerable_q))
+        .collect();
+    for (entity, mut hit_stop, mut state, stunned) in stopped_q.iter_mut() {
+        if rehit.contains(&entity) {
+            continue;
+        }
+        hit_stop.duration.tick(time.delta());
+        if hit_stop.duration.finished() {
+            if !paused.0 && !stunned {
+                state.resume();
+            }
+            commands.entity(entity).remove::<HitStop>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        char_animation::Playback, collision::centered_rect, compass::Dir,
+        health::HealthEventsPlugin, space_lookup::RstarPlugin,
+    };
+
+    fn test_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((HealthEventsPlugin, RstarPlugin::<Hurtbox>::new()))
+            .add_event::<KnockbackImpulse>()
+            .add_systems(PostUpdate, hitbox_hurtbox_collision_system);
+        app
+    }
+
+    fn spawn_attacker(app: &mut App, loc: Vec2) -> Entity {
+        app.world_mut()
+            .spawn((
+                PhysTransform {
+                    translation: loc.extend(0.0),
+                },
+                Hitbox(Some(centered_rect(8.0, 8.0))),
+                CharAnimationState::new(Handle::default(), Dir::E, Playback::Once),
+                HitThisSwing::default(),
+                Team::PLAYER,
+            ))
+            .id()
+    }
+
+    fn spawn_target(app: &mut App, loc: Vec2, hurt_rect: Rect, team: Team) -> Entity {
+        app.world_mut()
+            .spawn((
+                PhysTransform {
+                    translation: loc.extend(0.0),
+                },
+                Hurtbox(Some(hurt_rect)),
+                team,
+            ))
+            .id()
+    }
+
+    fn damaged_targets(app: &App) -> Vec<Entity> {
+        let events = app.world().resource::<Events<TakeDamage>>();
+        events.get_reader().read(events).map(|d| d.target).collect()
+    }
+
+    #[test]
+    fn hits_nearby_enemies_once_per_swing() {
+        let mut app = test_app();
+        spawn_attacker(&mut app, Vec2::ZERO);
+        let enemy = spawn_target(
+            &mut app,
+            Vec2::new(6.0, 0.0),
+            centered_rect(8.0, 8.0),
+            Team::ENEMY,
+        );
+        // Same team, overlapping: no friendly fire.
+        spawn_target(
+            &mut app,
+            Vec2::new(-6.0, 0.0),
+            centered_rect(8.0, 8.0),
+            Team::PLAYER,
+        );
+        // Enemy, but not overlapping.
+        spawn_target(
+            &mut app,
+            Vec2::new(20.0, 0.0),
+            centered_rect(8.0, 8.0),
+            Team::ENEMY,
+        );
+
+        app.update();
+        assert_eq!(damaged_targets(&app), vec![enemy]);
+        // Still overlapping next frame, same swing: no double-dip.
+        app.update();
+        assert_eq!(damaged_targets(&app), vec![enemy]);
+    }
+
+    #[test]
+    fn hitbox_coming_back_out_is_a_new_swing() {
+        let mut app = test_app();
+        let attacker = spawn_attacker(&mut app, Vec2::ZERO);
+        app.world_mut().entity_mut(attacker).insert(HitboxActive);
+        let enemy = spawn_target(
+            &mut app,
+            Vec2::new(6.0, 0.0),
+            centered_rect(8.0, 8.0),
+            Team::ENEMY,
+        );
+        app.update();
+        app.update();
+        assert_eq!(damaged_targets(&app), vec![enemy]);
+
+        // Same animation again, hitbox goes away and comes back: hits again.
+        app.world_mut()
+            .entity_mut(attacker)
+            .remove::<HitboxActive>();
+        app.update();
+        app.world_mut().resource_mut::<Events<TakeDamage>>().clear();
+        app.world_mut().entity_mut(attacker).insert(HitboxActive);
+        app.update();
+        assert_eq!(damaged_targets(&app), vec![enemy]);
+    }
+
+    #[test]
+    fn projectiles_skip_their_shooter() {
+        let mut app = test_app();
+        // No team to fall back on, just the swing's victim list.
+        let shooter = app
+            .world_mut()
+            .spawn((
+                PhysTransform {
+                    translation: Vec3::ZERO,
+                },
+                Hurtbox(Some(centered_rect(8.0, 8.0))),
+            ))
+            .id();
+        app.world_mut().spawn((
+            PhysTransform {
+                translation: Vec3::ZERO,
+            },
+            Hitbox(Some(centered_rect(4.0, 4.0))),
+            HitboxActive,
+            HitThisSwing {
+                swing: None,
+                victims: [shooter].into_iter().collect(),
+            },
+        ));
+        app.update();
+        assert!(damaged_targets(&app).is_empty());
+    }
+
+    #[test]
+    fn far_away_hurtboxes_are_never_tested() {
+        let mut app = test_app();
+        spawn_attacker(&mut app, Vec2::ZERO);
+        // Origin's way out of scanning range, but its (ridiculous) hurtbox
+        // reaches all the way back to overlap the hitbox. A brute-force check
+        // would count this as a hit; the tree should never even offer it up.
+        let far = 500.0;
+        let reach_back = Rect::new(-far - 4.0, -4.0, -far + 4.0, 4.0);
+        spawn_target(&mut app, Vec2::new(far, 0.0), reach_back, Team::ENEMY);
+
+        app.update();
+        assert!(damaged_targets(&app).is_empty());
+    }
+
+    #[test]
+    fn hits_freeze_both_animations_for_a_beat() {
+        use bevy::utils::Duration;
+
+        let mut app = App::new();
+        app.add_plugins(HealthEventsPlugin)
+            .init_resource::<Time>()
+            .init_resource::<GamePaused>()
+            .init_resource::<NumbersSettings>()
+            .add_systems(Update, (hit_stop_start_system, hit_stop_system).chain());
+        let mut spawn_guy = || {
+            app.world_mut()
+                .spawn(CharAnimationState::new(
+                    Handle::default(),
+                    Dir::E,
+                    Playback::Loop,
+                ))
+                .id()
+        };
+        let attacker = spawn_guy();
+        let target = spawn_guy();
+        let stunned_target = spawn_guy();
+        let poisoned = spawn_guy();
+        app.world_mut()
+            .entity_mut(stunned_target)
+            .insert(Stunned::new(10.0));
+        for (target, source) in [
+            (target, Some(attacker)),
+            (stunned_target, Some(attacker)),
+            (poisoned, None),
+        ] {
+            app.world_mut().send_event(TakeDamage {
+                target,
+                amount: 1,
+                source,
+            });
+        }
+        app.update();
+
+        let frozen = |app: &App, e: Entity| {
+            let paused = app.world().get::<CharAnimationState>(e).unwrap().paused;
+            let stopped = app.world().get::<HitStop>(e).is_some();
+            (paused, stopped)
+        };
+        assert_eq!(frozen(&app, attacker), (true, true));
+        assert_eq!(frozen(&app, target), (true, true));
+        assert_eq!(frozen(&app, poisoned), (false, false));
+
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(HIT_STOP_MS + 1));
+        app.update();
+        assert_eq!(frozen(&app, attacker), (false, false));
+        assert_eq!(frozen(&app, target), (false, false));
+        // Hitstun's still going, so that one stays put.
+        assert_eq!(frozen(&app, stunned_target), (true, false));
+    }
+
+    #[test]
+    fn hits_that_dont_land_dont_freeze() {
+        let mut app = App::new();
+        app.add_plugins(HealthEventsPlugin)
+            .init_resource::<Time>()
+            .init_resource::<GamePaused>()
+            .init_resource::<NumbersSettings>()
+            .add_systems(Update, (hit_stop_start_system, hit_stop_system).chain());
+        let mut spawn_guy = || {
+            app.world_mut()
+                .spawn(CharAnimationState::new(
+                    Handle::default(),
+                    Dir::E,
+                    Playback::Loop,
+                ))
+                .id()
+        };
+        let attacker = spawn_guy();
+        let rolling = spawn_guy();
+        let goner = spawn_guy();
+        let grazed = spawn_guy();
+        app.world_mut().entity_mut(rolling).insert(Invincible);
+        app.world_mut().entity_mut(goner).insert(Dying);
+        for (target, amount) in [(rolling, 1), (goner, 1), (grazed, 0)] {
+            app.world_mut().send_event(TakeDamage {
+                target,
+                amount,
+                source: Some(attacker),
+            });
+        }
+        app.update();
+
+        for entity in [attacker, rolling, goner, grazed] {
+            assert!(
+                !app.world()
+                    .get::<CharAnimationState>(entity)
+                    .unwrap()
+                    .paused
+            );
+            assert!(app.world().get::<HitStop>(entity).is_none());
+        }
+    }
+
+    #[test]
+    fn rehits_on_the_last_frame_of_hit_stop_stick() {
+        use bevy::utils::Duration;
+
+        let mut app = App::new();
+        app.add_plugins(HealthEventsPlugin)
+            .init_resource::<Time>()
+            .init_resource::<GamePaused>()
+            .init_resource::<NumbersSettings>()
+            .add_systems(Update, (hit_stop_start_system, hit_stop_system).chain());
+        let attacker = app
+            .world_mut()
+            .spawn(CharAnimationState::new(
+                Handle::default(),
+                Dir::E,
+                Playback::Loop,
+            ))
+            .id();
+        let target = app
+            .world_mut()
+            .spawn(CharAnimationState::new(
+                Handle::default(),
+                Dir::E,
+                Playback::Loop,
+            ))
+            .id();
+        let hit = TakeDamage {
+            target,
+            amount: 1,
+            source: Some(attacker),
+        };
+        app.world_mut().send_event(hit);
+        app.update();
+
+        // Second hit lands on the frame the first freeze runs out.
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(HIT_STOP_MS + 1));
+        app.world_mut().send_event(hit);
+        app.update();
+        for entity in [attacker, target] {
+            assert!(
+                app.world()
+                    .get::<CharAnimationState>(entity)
+                    .unwrap()
+                    .paused
+            );
+            let hit_stop = app.world().get::<HitStop>(entity).unwrap();
+            assert!(!hit_stop.duration.finished());
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Special moves from input sequences, fighting game style. Combos live in
+//! `assets/combos/*.combo.ron`; each player keeps a short history of recent
+//! inputs, and when the tail end of it spells out a combo fast enough, we
+//! send a ComboActivated event. Each combo says which special move it does;
+//! the systems that own those moves listen for the event.
+
+use crate::{
+    compass::Dir,
+    input::{CurrentInputs, PlayerIndex},
+};
+use bevy::asset::AsyncReadExt;
+use bevy::asset::{io::Reader, AssetLoader, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashMap};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// How many inputs each player's history remembers. Combos longer than this
+/// can never fire.
+pub const INPUT_HISTORY_LENGTH: usize = 30;
+
+/// The combo files to load at startup.
+const COMBO_PATHS: &[&str] = &["combos/quarter_circle_forward.combo.ron"];
+
+pub struct CombosPlugin;
+impl Plugin for CombosPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ComboDefinition>()
+            .init_asset_loader::<ComboLoader>()
+            .add_event::<ComboActivated>()
+            .init_resource::<Combos>()
+            .add_systems(Startup, load_combos)
+            .add_systems(Update, combo_detection_system);
+    }
+}
+
+/// One discrete input, as far as combos care. Movement counts when the
+/// stick (or keys) settle into a new direction.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    Move(Dir),
+    Roll,
+    Attack,
+}
+
+/// What a combo does when it goes off.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpecialMove {
+    /// A beefier shot, straight ahead.
+    Fireball,
+}
+
+/// A sequence of inputs that has to happen within `window_ms`, first input to
+/// last, to count.
+#[derive(Asset, TypePath, Debug)]
+pub struct ComboDefinition {
+    pub inputs: Vec<InputEvent>,
+    pub window_ms: u64,
+    pub special: SpecialMove,
+    /// KMP failure table: for each prefix of `inputs`, the length of its
+    /// longest proper prefix that's also a suffix. Lets us keep a running
+    /// match one input at a time without ever rescanning the history.
+    failure: Vec<usize>,
+}
+
+impl ComboDefinition {
+    pub fn new(inputs: Vec<InputEvent>, window_ms: u64, special: SpecialMove) -> Self {
+        let failure = failure_table(&inputs);
+        Self {
+            inputs,
+            window_ms,
+            special,
+            failure,
+        }
+    }
+
+    /// Given how many inputs of this combo were matched so far, and a new
+    /// input, how many are matched now?
+    fn advance(&self, mut matched: usize, input: InputEvent) -> usize {
+        if self.inputs.is_empty() {
+            return 0;
+        }
+        // A full match last time: keep whatever overlaps with a new attempt.
+        if matched == self.inputs.len() {
+            matched = self.failure[matched - 1];
+        }
+        while matched > 0 && self.inputs[matched] != input {
+            matched = self.failure[matched - 1];
+        }
+        if self.inputs[matched] == input {
+            matched += 1;
+        }
+        matched
+    }
+}
+
+fn failure_table(pattern: &[InputEvent]) -> Vec<usize> {
+    let mut failure = vec![0; pattern.len()];
+    let mut k = 0;
+    for i in 1..pattern.len() {
+        while k > 0 && pattern[i] != pattern[k] {
+            k = failure[k - 1];
+        }
+        if pattern[i] == pattern[k] {
+            k += 1;
+        }
+        failure[i] = k;
+    }
+    failure
+}
+
+/// What's actually in the .combo.ron files.
+#[derive(Deserialize)]
+struct ComboFile {
+    inputs: Vec<InputEvent>,
+    window_ms: u64,
+    special: SpecialMove,
+}
+
+#[derive(Default)]
+pub struct ComboLoader;
+
+impl AssetLoader for ComboLoader {
+    type Asset = ComboDefinition;
+    type Settings = ();
+    type Error = anyhow::Error;
+
+    fn extensions(&self) -> &[&str] {
+        &["combo.ron"]
+    }
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let file: ComboFile = ron::de::from_bytes(&bytes)?;
+        Ok(ComboDefinition::new(
+            file.inputs,
+            file.window_ms,
+            file.special,
+        ))
+    }
+}
+
+/// Resource: all the combos anyone can do.
+#[derive(Resource, Default)]
+pub struct Combos(pub Vec<Handle<ComboDefinition>>);
+
+/// Event: somebody pulled off a combo.
+#[derive(Event, Debug)]
+pub struct ComboActivated(pub AssetId<ComboDefinition>, pub Entity);
+
+/// Per-player memory of recent inputs, plus how far along each combo is.
+#[derive(Component, Default)]
+pub struct InputHistory {
+    /// Oldest first, with the time each one happened.
+    events: VecDeque<(InputEvent, Duration)>,
+    /// Which direction we were last holding, so we only count changes.
+    last_dir: Option<Dir>,
+    /// KMP state: how many inputs of each combo are matched right now.
+    progress: HashMap<AssetId<ComboDefinition>, usize>,
+}
+
+impl InputHistory {
+    /// Add an input, and return the combos it finished.
+    pub fn record<'a>(
+        &mut self,
+        input: InputEvent,
+        now: Duration,
+        combos: impl Iterator<Item = (AssetId<ComboDefinition>, &'a ComboDefinition)>,
+    ) -> Vec<AssetId<ComboDefinition>> {
+        if self.events.len() == INPUT_HISTORY_LENGTH {
+            self.events.pop_front();
+        }
+        self.events.push_back((input, now));
+
+        let mut finished = Vec::new();
+        for (id, combo) in combos {
+            let matched = self.progress.entry(id).or_insert(0);
+            *matched = combo.advance(*matched, input);
+            let len = combo.inputs.len();
+            if len == 0 || *matched < len || len > self.events.len() {
+                continue;
+            }
+            // Matched inputs are the last `len` in the history; were they quick enough?
+            let (_, started) = self.events[self.events.len() - len];
+            if now - started <= Duration::from_millis(combo.window_ms) {
+                finished.push(id);
+            }
+        }
+        finished
+    }
+}
+
+// ------- Systems -------
+
+pub fn load_combos(asset_server: Res<AssetServer>, mut combos: ResMut<Combos>) {
+    combos.0 = COMBO_PATHS
+        .iter()
+        .map(|path| asset_server.load(*path))
+        .collect();
+}
+
+/// Turn each player's inputs this frame into discrete input events, feed them
+/// to their history, and announce any finished combos.
+pub fn combo_detection_system(
+    time: Res<Time>,
+    inputs: Res<CurrentInputs>,
+    combos: Res<Combos>,
+    combo_assets: Res<Assets<ComboDefinition>>,
+    mut player_q: Query<(Entity, &PlayerIndex, &mut InputHistory)>,
+    mut combo_events: EventWriter<ComboActivated>,
+) {
+    let now = time.elapsed();
+    let loaded = || {
+        combos
+            .0
+            .iter()
+            .filter_map(|h| combo_assets.get(h).map(|c| (h.id(), c)))
+    };
+    for (entity, index, mut history) in player_q.iter_mut() {
+        let inputs = inputs.player(*index);
+        let mut new_events = Vec::new();
+
+        let dir = Dir::ordinal(inputs.movement);
+        if history.last_dir != Some(dir) {
+            history.last_dir = Some(dir);
+            if dir != Dir::Neutral {
+                new_events.push(InputEvent::Move(dir));
+            }
+        }
+        if inputs.actioning {
+            new_events.push(InputEvent::Roll);
+        }
+        if inputs.attacking {
+            new_events.push(InputEvent::Attack);
+        }
+
+        for input in new_events {
+            for combo in history.record(input, now, loaded()) {
+                combo_events.send(ComboActivated(combo, entity));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use InputEvent::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn qcf() -> ComboDefinition {
+        ComboDefinition::new(
+            vec![Move(Dir::S), Move(Dir::SE), Move(Dir::E), Attack],
+            500,
+            SpecialMove::Fireball,
+        )
+    }
+
+    /// Feed a sequence of (input, time) to a history, return which inputs
+    /// (by index) finished the combo.
+    fn run(combo: &ComboDefinition, inputs: &[(InputEvent, u64)]) -> Vec<usize> {
+        let mut history = InputHistory::default();
+        let id = AssetId::<ComboDefinition>::default();
+        inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, (input, t))| {
+                !history
+                    .record(*input, ms(*t), std::iter::once((id, combo)))
+                    .is_empty()
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn failure_table_handles_repeats() {
+        let pattern = [Roll, Roll, Attack, Roll, Roll, Roll];
+        assert_eq!(failure_table(&pattern), vec![0, 1, 0, 1, 2, 2]);
+    }
+
+    #[test]
+    fn quarter_circle_fires_in_time() {
+        let combo = qcf();
+        let hits = run(
+            &combo,
+            &[
+                (Move(Dir::E), 0),
+                (Move(Dir::S), 100),
+                (Move(Dir::SE), 150),
+                (Move(Dir::E), 200),
+                (Attack, 300),
+            ],
+        );
+        assert_eq!(hits, vec![4]);
+    }
+
+    #[test]
+    fn too_slow_doesnt_count() {
+        let combo = qcf();
+        let hits = run(
+            &combo,
+            &[
+                (Move(Dir::S), 0),
+                (Move(Dir::SE), 150),
+                (Move(Dir::E), 300),
+                (Attack, 600),
+            ],
+        );
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn interrupted_sequence_recovers_via_overlap() {
+        // Double-tap roll then attack; a third roll shouldn't throw it off.
+        let combo = ComboDefinition::new(vec![Roll, Roll, Attack], 1000, SpecialMove::Fireball);
+        let hits = run(&combo, &[(Roll, 0), (Roll, 10), (Roll, 20), (Attack, 30)]);
+        assert_eq!(hits, vec![3]);
+        // But something else in the middle breaks it.
+        let hits = run(
+            &combo,
+            &[(Roll, 0), (Move(Dir::N), 5), (Roll, 10), (Attack, 30)],
+        );
+        assert!(hits.is_empty());
+    }
+}
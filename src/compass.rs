@@ -1,4 +1,5 @@
-use bevy::prelude::Vec2;
+use bevy::prelude::{Reflect, Vec2};
+use serde::{Deserialize, Serialize};
 use std::f32::consts::*;
 use std::fmt;
 
@@ -22,7 +23,7 @@ pub fn flip_angle(angle: f32) -> f32 {
 //     set flip if there's a west component.
 // - 8 -- ordinal().
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, Reflect)]
 pub enum Dir {
     E,
     N,
@@ -73,6 +74,106 @@ impl TryFrom<&str> for Dir {
 }
 
 impl Dir {
+    /// The unit vector pointing this way. Neutral is Vec2::ZERO.
+    pub fn to_vec2(self) -> Vec2 {
+        match self {
+            Self::E => Vec2::X,
+            Self::N => Vec2::Y,
+            Self::W => Vec2::NEG_X,
+            Self::S => Vec2::NEG_Y,
+            Self::NE => Vec2::new(FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            Self::NW => Vec2::new(-FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+            Self::SW => Vec2::new(-FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+            Self::SE => Vec2::new(FRAC_1_SQRT_2, -FRAC_1_SQRT_2),
+            Self::Neutral => Vec2::ZERO,
+        }
+    }
+
+    /// The other way. Neutral stays neutral.
+    pub fn opposite(&self) -> Self {
+        match self {
+            Self::E => Self::W,
+            Self::N => Self::S,
+            Self::W => Self::E,
+            Self::S => Self::N,
+            Self::NE => Self::SW,
+            Self::NW => Self::SE,
+            Self::SW => Self::NE,
+            Self::SE => Self::NW,
+            Self::Neutral => Self::Neutral,
+        }
+    }
+
+    /// Turn 45° clockwise. Neutral stays neutral.
+    pub fn rotate_cw(&self) -> Self {
+        match self {
+            Self::E => Self::SE,
+            Self::SE => Self::S,
+            Self::S => Self::SW,
+            Self::SW => Self::W,
+            Self::W => Self::NW,
+            Self::NW => Self::N,
+            Self::N => Self::NE,
+            Self::NE => Self::E,
+            Self::Neutral => Self::Neutral,
+        }
+    }
+
+    /// Turn 45° counterclockwise. Neutral stays neutral.
+    pub fn rotate_ccw(&self) -> Self {
+        match self {
+            Self::E => Self::NE,
+            Self::NE => Self::N,
+            Self::N => Self::NW,
+            Self::NW => Self::W,
+            Self::W => Self::SW,
+            Self::SW => Self::S,
+            Self::S => Self::SE,
+            Self::SE => Self::E,
+            Self::Neutral => Self::Neutral,
+        }
+    }
+
+    /// One of the big four.
+    pub fn is_cardinal(&self) -> bool {
+        matches!(self, Self::E | Self::N | Self::W | Self::S)
+    }
+
+    /// One of the in-betweens.
+    pub fn is_ordinal(&self) -> bool {
+        matches!(self, Self::NE | Self::NW | Self::SW | Self::SE)
+    }
+
+    /// Same as is_ordinal, for when you're thinking of it that way.
+    pub fn is_diagonal(&self) -> bool {
+        self.is_ordinal()
+    }
+
+    /// The angle in radians from +X, in the same -π..=π range that
+    /// Vec2::X.angle_between() gives you. Panics on Neutral, which doesn't
+    /// have one; use angle_or_zero if that might come up.
+    pub fn angle(&self) -> f32 {
+        match self {
+            Self::E => 0.0,
+            Self::NE => FRAC_PI_4,
+            Self::N => FRAC_PI_2,
+            Self::NW => 3.0 * FRAC_PI_4,
+            Self::W => PI,
+            Self::SW => -3.0 * FRAC_PI_4,
+            Self::S => -FRAC_PI_2,
+            Self::SE => -FRAC_PI_4,
+            Self::Neutral => panic!("Dir::Neutral doesn't have an angle"),
+        }
+    }
+
+    /// Like angle(), but Neutral gets 0.0 (east) instead of a panic.
+    pub fn angle_or_zero(&self) -> f32 {
+        match self {
+            Self::Neutral => 0.0,
+            _ => self.angle(),
+        }
+    }
+
     /// Given a Vec2, return east, west, or neutral. Bias towards east when
     /// given exactly north or south.
     #[allow(dead_code)]
@@ -206,6 +307,28 @@ impl Dir {
     }
 }
 
+impl From<Dir> for Vec2 {
+    fn from(dir: Dir) -> Self {
+        dir.to_vec2()
+    }
+}
+
+/// Snaps to the nearest of the eight directions. Zero, NaN, and infinite
+/// vectors don't point anywhere, so they're an error rather than Neutral.
+impl TryFrom<Vec2> for Dir {
+    type Error = CantDirError;
+
+    fn try_from(v: Vec2) -> Result<Self, Self::Error> {
+        match Self::ordinal(v) {
+            Self::Neutral => Err(CantDirError(format!(
+                "Vector {} doesn't point in any direction",
+                v
+            ))),
+            dir => Ok(dir),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -216,6 +339,138 @@ mod tests {
 
     const LIL_BIT: f32 = 0.0001;
 
+    const ALL_DIRS: [Dir; 9] = [
+        Dir::E,
+        Dir::NE,
+        Dir::N,
+        Dir::NW,
+        Dir::W,
+        Dir::SW,
+        Dir::S,
+        Dir::SE,
+        Dir::Neutral,
+    ];
+
+    #[test]
+    fn test_to_vec2() {
+        for dir in ALL_DIRS {
+            let v = dir.to_vec2();
+            let from: Vec2 = dir.into();
+            assert_eq!(v, from);
+            if dir == Dir::Neutral {
+                assert_eq!(v, Vec2::ZERO);
+            } else {
+                assert!(
+                    (v.length() - 1.0).abs() < LIL_BIT,
+                    "{:?} isn't unit length",
+                    dir
+                );
+                // ...and it points the right way.
+                assert_eq!(Dir::ordinal(v), dir);
+            }
+        }
+        assert!(Dir::NE.to_vec2().abs_diff_eq(HARD_NE.normalize(), LIL_BIT));
+        assert!(Dir::SW.to_vec2().abs_diff_eq(HARD_SW.normalize(), LIL_BIT));
+    }
+
+    #[test]
+    fn test_cardinal_ordinal_predicates() {
+        for dir in ALL_DIRS {
+            assert_eq!(dir.is_ordinal(), dir.is_diagonal());
+            if dir == Dir::Neutral {
+                assert!(!dir.is_cardinal() && !dir.is_ordinal());
+            } else {
+                assert_ne!(dir.is_cardinal(), dir.is_ordinal());
+            }
+        }
+        assert!(Dir::E.is_cardinal());
+        assert!(Dir::S.is_cardinal());
+        assert!(Dir::NE.is_ordinal());
+        assert!(Dir::SW.is_diagonal());
+    }
+
+    #[test]
+    fn test_angle_round_trip() {
+        for dir in ALL_DIRS {
+            if dir == Dir::Neutral {
+                assert_eq!(dir.angle_or_zero(), 0.0);
+                continue;
+            }
+            let angle = dir.angle();
+            assert_eq!(dir.angle_or_zero(), angle);
+            assert!((-PI..=PI).contains(&angle));
+            assert_eq!(Dir::ordinal_from_angle(angle), dir);
+            if dir.is_cardinal() {
+                assert_eq!(Dir::cardinal_from_angle(angle), dir);
+            }
+            // Agrees with the vector version
+            let from_vec = Vec2::X.angle_between(dir.to_vec2());
+            assert!((from_vec - angle).abs() < LIL_BIT || dir == Dir::W);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_neutral_angle_panics() {
+        Dir::Neutral.angle();
+    }
+
+    #[test]
+    fn test_vec2_round_trip() {
+        for dir in ALL_DIRS {
+            if dir == Dir::Neutral {
+                continue;
+            }
+            let v: Vec2 = dir.into();
+            assert_eq!(Dir::try_from(v).unwrap(), dir);
+            // Length doesn't matter
+            assert_eq!(Dir::try_from(v * 20.0).unwrap(), dir);
+        }
+        assert_eq!(Dir::try_from(Vec2::new(1.0, LIL_BIT)).unwrap(), Dir::E);
+        assert_eq!(Dir::try_from(HARD_NW).unwrap(), Dir::NW);
+
+        // Blank or bogus input:
+        assert!(Dir::try_from(Vec2::ZERO).is_err());
+        assert!(Dir::try_from(Vec2::new(f32::NAN, 1.0)).is_err());
+        assert!(Dir::try_from(Vec2::new(1.0, f32::INFINITY)).is_err());
+        // ...and Neutral goes to zero, which can't come back.
+        let neutral: Vec2 = Dir::Neutral.into();
+        assert!(Dir::try_from(neutral).is_err());
+    }
+
+    #[test]
+    fn test_opposite() {
+        for dir in ALL_DIRS {
+            assert_eq!(dir.opposite().opposite(), dir);
+            assert_eq!(dir.opposite().to_vec2(), -dir.to_vec2());
+        }
+        assert_eq!(Dir::E.opposite(), Dir::W);
+        assert_eq!(Dir::NE.opposite(), Dir::SW);
+        assert_eq!(Dir::N.opposite(), Dir::S);
+        assert_eq!(Dir::SE.opposite(), Dir::NW);
+        assert_eq!(Dir::Neutral.opposite(), Dir::Neutral);
+    }
+
+    #[test]
+    fn test_rotate() {
+        for dir in ALL_DIRS {
+            assert_eq!(dir.rotate_cw().rotate_ccw(), dir);
+            assert_eq!(dir.rotate_ccw().rotate_cw(), dir);
+            // Four eighth-turns is a half-turn.
+            let half_turn = dir.rotate_cw().rotate_cw().rotate_cw().rotate_cw();
+            assert_eq!(half_turn, dir.opposite());
+            if dir != Dir::Neutral {
+                let rotated = Vec2::from_angle(-FRAC_PI_4).rotate(dir.to_vec2());
+                assert_eq!(Dir::ordinal(rotated), dir.rotate_cw());
+            }
+        }
+        assert_eq!(Dir::E.rotate_cw(), Dir::SE);
+        assert_eq!(Dir::E.rotate_ccw(), Dir::NE);
+        assert_eq!(Dir::N.rotate_cw(), Dir::NE);
+        assert_eq!(Dir::Neutral.rotate_cw(), Dir::Neutral);
+        assert_eq!(Dir::Neutral.rotate_ccw(), Dir::Neutral);
+    }
+
     #[test]
     fn test_horizontal_from_vec2() {
         assert_eq!(Dir::horizontal(HARD_NE), Dir::E);
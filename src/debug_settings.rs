@@ -1,3 +1,4 @@
+use crate::goofy_time::TimeMode;
 use bevy::prelude::*;
 
 #[derive(Resource, Default, Reflect, PartialEq, Eq)]
@@ -6,21 +7,70 @@ pub struct DebugSettings {
     pub debug_origins: bool,
     pub debug_hitboxes: bool,
     pub debug_hurtboxes: bool,
+    pub debug_velocities: bool,
+    /// Kitty shoots a little something out of every (uncharged) slash.
+    pub player_projectiles: bool,
     pub motion_kind: MotionKind,
     pub camera_kind: CameraKind,
+    /// Let the camera sit between screen pixels, instead of snapping it to
+    /// them. Smoother, but pixel art shimmers.
+    pub smooth_camera: bool,
+    /// Which clock the camera lerp goes by.
+    pub time_mode: TimeMode,
+    /// Hold the game clock still, and step it one frame per press of F.
+    pub debug_frame_advance: bool,
 }
 
 #[derive(Resource, Reflect, PartialEq)]
 pub struct NumbersSettings {
     pub launch_gravity: f32,
+    pub player_run_speed: f32,
+    /// Rolls always cover the same distance, so faster is also shorter.
+    pub player_roll_speed: f32,
+    pub player_bonk_speed: f32,
+    pub enemy_run_speed: f32,
+    /// How close you can get to an idle or patrolling enemy before it notices you.
+    pub aggro_range: f32,
+    /// How long an enemy keeps chasing before it gives up, in seconds.
+    pub aggro_chase_timeout: f32,
+    /// How long an enemy stands around between patrols, in seconds.
+    pub patrol_idle_duration: f32,
     pub player_bonk_z_velocity: f32,
+    /// How long (in frames) to hang onto roll/attack presses the player
+    /// can't act on yet.
+    pub input_buffer_frames: u8,
+    /// How long you have to hold attack before letting go does a charged attack.
+    pub min_charge_ms: u64,
+    /// How long a landed hit freezes both parties' animations.
+    pub hit_stop_ms: u64,
+    /// How fast enemies' shots go, in px/s.
+    pub enemy_projectile_speed: f32,
+    /// How high something has to be for its shadow to fade out completely.
+    pub shadow_fade_height: f32,
+    /// Game clock speed; 1.0 is normal. +/- nudge it by a tenth. Timers and
+    /// CountupTimers all tick off Time::delta, which is virtual time in
+    /// Update, so everything slows down together.
+    pub time_scale: f32,
 }
 
 impl Default for NumbersSettings {
     fn default() -> Self {
         Self {
             launch_gravity: crate::behaviors::LAUNCH_GRAVITY,
+            player_run_speed: crate::Speed::RUN,
+            player_roll_speed: crate::Speed::ROLL,
+            player_bonk_speed: crate::Speed::BONK,
+            enemy_run_speed: crate::Speed::ENEMY_RUN,
+            aggro_range: crate::EnemyState::AGGRO_RANGE,
+            aggro_chase_timeout: crate::EnemyState::AGGRO_CHASE_TIMEOUT_SECS,
+            patrol_idle_duration: crate::EnemyState::PATROL_IDLE_SECS,
             player_bonk_z_velocity: crate::PlayerState::BONK_Z_VELOCITY,
+            input_buffer_frames: crate::input::ACTION_BUFFER_FRAMES,
+            min_charge_ms: crate::PlayerState::MIN_CHARGE_MS,
+            hit_stop_ms: crate::combat::HIT_STOP_MS,
+            enemy_projectile_speed: crate::projectiles::ENEMY_PROJECTILE_SPEED,
+            shadow_fade_height: crate::render::SHADOW_FADE_HEIGHT,
+            time_scale: 1.0,
         }
     }
 }
@@ -39,6 +89,7 @@ pub enum CameraKind {
     #[default]
     Locked,
     Lerp,
+    LerpWithDeadzone,
 }
 
 pub fn motion_is(kind: MotionKind) -> impl Fn(Res<DebugSettings>) -> bool {
@@ -47,3 +98,142 @@ pub fn motion_is(kind: MotionKind) -> impl Fn(Res<DebugSettings>) -> bool {
 pub fn camera_is(kind: CameraKind) -> impl Fn(Res<DebugSettings>) -> bool {
     move |debugs: Res<DebugSettings>| debugs.camera_kind == kind
 }
+
+const TIME_SCALE_STEP: f32 = 0.1;
+const FRAME_ADVANCE_KEY: KeyCode = KeyCode::KeyF;
+
+/// Debug keys: +/- to speed up or slow down the game clock.
+pub fn time_scale_keys_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut numbers: ResMut<NumbersSettings>,
+) {
+    let mut scale = numbers.time_scale;
+    if keys.any_just_pressed([KeyCode::Equal, KeyCode::NumpadAdd]) {
+        scale += TIME_SCALE_STEP;
+    }
+    if keys.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        scale -= TIME_SCALE_STEP;
+    }
+    // Round off the float fuzz, so you can always get back to exactly 1.0.
+    let scale = ((scale / TIME_SCALE_STEP).round() * TIME_SCALE_STEP).max(0.0);
+    if scale != numbers.time_scale {
+        numbers.time_scale = scale;
+    }
+}
+
+/// Apply the time scale to the virtual clock. In frame advance mode, the
+/// clock sits at zero except right after a press of the advance key. (Virtual
+/// time updates in First, so a speed set here takes effect on the *next*
+/// frame, and gets zeroed again before the one after that.) The inspector
+/// can set any old number, and set_relative_speed panics on a negative or
+/// non-finite one, so negatives count as a standstill and NaN or infinity
+/// leave the clock alone.
+pub fn time_scale_system(
+    numbers: Res<NumbersSettings>,
+    debugs: Res<DebugSettings>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    let speed = if debugs.debug_frame_advance && !keys.just_pressed(FRAME_ADVANCE_KEY) {
+        0.0
+    } else if numbers.time_scale.is_finite() {
+        numbers.time_scale.max(0.0)
+    } else {
+        return;
+    };
+    if time.relative_speed() != speed {
+        time.set_relative_speed(speed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn time_scale_keys_step_by_tenths() {
+        let mut world = World::new();
+        world.init_resource::<NumbersSettings>();
+        world.init_resource::<DebugSettings>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<Time<Virtual>>();
+        let press = |world: &mut World, key: KeyCode| {
+            let mut keys = world.resource_mut::<ButtonInput<KeyCode>>();
+            keys.clear();
+            keys.release(key);
+            keys.press(key);
+            world.run_system_once(time_scale_keys_system);
+            world.run_system_once(time_scale_system);
+        };
+
+        for _ in 0..3 {
+            press(&mut world, KeyCode::Minus);
+        }
+        assert_eq!(world.resource::<NumbersSettings>().time_scale, 0.7);
+        assert_eq!(world.resource::<Time<Virtual>>().relative_speed(), 0.7);
+        for _ in 0..3 {
+            press(&mut world, KeyCode::NumpadAdd);
+        }
+        assert_eq!(world.resource::<NumbersSettings>().time_scale, 1.0);
+        // Bottoms out at a standstill.
+        for _ in 0..12 {
+            press(&mut world, KeyCode::Minus);
+        }
+        assert_eq!(world.resource::<NumbersSettings>().time_scale, 0.0);
+    }
+
+    #[test]
+    fn weird_time_scales_dont_reach_the_clock() {
+        let mut world = World::new();
+        world.init_resource::<NumbersSettings>();
+        world.init_resource::<DebugSettings>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<Time<Virtual>>();
+        let apply = |world: &mut World, scale: f32| {
+            world.resource_mut::<NumbersSettings>().time_scale = scale;
+            world.run_system_once(time_scale_system);
+            world.resource::<Time<Virtual>>().relative_speed()
+        };
+        assert_eq!(apply(&mut world, 0.5), 0.5);
+        assert_eq!(apply(&mut world, f32::NAN), 0.5);
+        assert_eq!(apply(&mut world, f32::INFINITY), 0.5);
+        assert_eq!(apply(&mut world, -2.0), 0.0);
+    }
+
+    #[test]
+    fn frame_advance_steps_one_frame_per_press() {
+        let mut app = App::new();
+        app.add_plugins(bevy::time::TimePlugin)
+            .init_resource::<NumbersSettings>()
+            .insert_resource(DebugSettings {
+                debug_frame_advance: true,
+                ..default()
+            })
+            .init_resource::<ButtonInput<KeyCode>>()
+            .add_systems(PreUpdate, time_scale_system);
+        let elapsed = |app: &App| app.world().resource::<Time<Virtual>>().elapsed();
+
+        app.update();
+        app.update();
+        let before = elapsed(&app);
+        app.update();
+        assert_eq!(elapsed(&app), before);
+
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(FRAME_ADVANCE_KEY);
+        app.update();
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .clear();
+        // One frame goes by...
+        app.update();
+        let stepped = elapsed(&app);
+        assert!(stepped > before);
+        // ...and then it's stopped again.
+        app.update();
+        app.update();
+        assert_eq!(elapsed(&app), stepped);
+    }
+}
@@ -1,24 +1,60 @@
 use crate::{
-    assets_setup::*, behaviors::*, char_animation::*, compass::flip_angle, debug_settings::*,
-    input::CurrentInputs, movement::*, phys_space::PhysTransform,
+    assets_setup::*,
+    behaviors::*,
+    char_animation::*,
+    combat::Damage,
+    compass::flip_angle,
+    debug_settings::*,
+    health::{Dying, EntityDied, Health, TakeDamage},
+    input::{ActionKind, CurrentInputs, PlayerIndex},
+    movement::*,
+    phys_space::PhysTransform,
+    sounds::{play_sfx, SfxConfig, SoundCooldown},
+    status_effects::Stunned,
+    Enemy,
 };
 use bevy::ecs::system::EntityCommands;
 use bevy::prelude::*;
 use bevy::utils::Duration;
+use bevy_ecs_ldtk::{prelude::*, utils::ldtk_grid_coords_to_translation};
 use bevy_prng::Xoshiro256Plus;
 use bevy_rand::prelude::*;
 use rand::prelude::Rng;
+use std::collections::VecDeque;
 
 // ------- Types -------
 
 pub type EnemyStateMachine = EntityStateMachine<EnemyState>;
 pub type PlayerStateMachine = EntityStateMachine<PlayerState>;
 type GameRNG = GlobalEntropy<Xoshiro256Plus>;
+/// Each enemy's own RNG, forked off the global one when it spawns.
+type EnemyRNG = EntropyComponent<Xoshiro256Plus>;
 
+/// Component: timers for the state machine. `timer` is how long the current
+/// state has left (if it's timed at all); the state systems swap it out on
+/// every transition. `attack_cooldown` outlives state changes, and only
+/// matters for enemies that have a ranged attack.
 #[derive(Component, Reflect, Default)]
-pub struct StateTimer(pub Option<Timer>);
+pub struct StateTimer {
+    pub timer: Option<Timer>,
+    pub attack_cooldown: Option<Timer>,
+}
 
-#[derive(Component)]
+impl StateTimer {
+    pub fn with_attack_cooldown(secs: f32) -> Self {
+        Self {
+            timer: None,
+            attack_cooldown: Some(Timer::from_seconds(secs, TimerMode::Once)),
+        }
+    }
+
+    /// Whether the current state's time is up. Untimed states never expire.
+    pub fn is_expired(&self) -> bool {
+        self.timer.as_ref().is_some_and(Timer::finished)
+    }
+}
+
+#[derive(Component, Reflect)]
 pub struct EntityStateMachine<T>
 where
     T: Clone,
@@ -26,18 +62,38 @@ where
     // fields are private
     current: T,
     next: Option<T>,
+    /// The last few states we were in, oldest first, up to HISTORY_DEPTH.
+    /// For debugging; the inspector shows it.
+    state_history: VecDeque<T>,
+    /// Whether the first do_transition has happened yet. That one just
+    /// re-enters the starting state for setup, so it's not history.
+    started: bool,
 }
 
 impl<T: Clone> EntityStateMachine<T> {
+    /// How many past states to remember.
+    pub const HISTORY_DEPTH: usize = 8;
+
     pub fn new(current: T) -> Self {
         Self {
             current: current.clone(),
             // Make sure we run sprite/behavior/timer setup on first tick!
             next: Some(current),
+            state_history: VecDeque::with_capacity(Self::HISTORY_DEPTH),
+            started: false,
         }
     }
-    pub fn push_transition(&mut self, next: T) {
-        self.next = Some(next);
+    /// Queue up a state to switch to on the next do_transition. If something's
+    /// already queued, only replace it if the new state is at least as
+    /// important; otherwise the new one gets dropped.
+    pub fn push_transition(&mut self, next: T)
+    where
+        T: InterruptPriority,
+    {
+        match &self.next {
+            Some(queued) if next.interrupt_priority() < queued.interrupt_priority() => (),
+            _ => self.next = Some(next),
+        }
     }
     // fn has_transition(&self) -> bool {
     //     self.next.is_some()
@@ -57,50 +113,110 @@ impl<T: Clone> EntityStateMachine<T> {
     /// if there's a transition waiting to go.
     pub fn do_transition(&mut self, f: impl FnOnce(&mut Self)) {
         if let Some(next) = self.next.take() {
-            self.current = next;
+            let old = std::mem::replace(&mut self.current, next);
+            if self.started {
+                if self.state_history.len() >= Self::HISTORY_DEPTH {
+                    self.state_history.pop_front();
+                }
+                self.state_history.push_back(old);
+            }
+            self.started = true;
             f(self);
         }
     }
 }
 
-#[derive(Clone)]
+/// How badly a state wants to happen, for breaking ties when several systems
+/// queue up transitions on the same frame. Higher wins.
+pub trait InterruptPriority {
+    fn interrupt_priority(&self) -> u8;
+}
+
+#[derive(Clone, Reflect)]
 pub enum PlayerState {
     Idle,
     Run,
-    Roll { roll_input: Vec2 },
-    Bonk { bonk_input: Vec2, distance: f32 },
+    Roll {
+        roll_input: Vec2,
+    },
+    Bonk {
+        bonk_input: Vec2,
+        distance: f32,
+    },
     Attack,
+    /// Holding attack after a swing, winding up a big one.
+    Charging,
+    ChargedAttack,
+    Hurt,
+}
+
+impl InterruptPriority for PlayerState {
+    fn interrupt_priority(&self) -> u8 {
+        match self {
+            PlayerState::Idle => 0,
+            PlayerState::Run => 1,
+            PlayerState::Bonk { .. } => 2,
+            PlayerState::Roll { .. } => 3,
+            PlayerState::Attack => 4,
+            PlayerState::Charging => 4,
+            PlayerState::ChargedAttack => 4,
+            // Getting hit trumps whatever you were trying to do.
+            PlayerState::Hurt => 5,
+        }
+    }
 }
 
 impl PlayerState {
     pub const ROLL_DISTANCE: f32 = 52.0;
     pub const BONK_FROM_ROLL_DISTANCE: f32 = 18.0;
     pub const BONK_Z_VELOCITY: f32 = 65.0;
-    pub const ROLL_SPEED: f32 = Speed::ROLL;
     pub const ATTACK_DURATION_MS: u64 = 400;
+    pub const HURT_DURATION_MS: u64 = 400;
+    pub const MIN_CHARGE_MS: u64 = 600;
+    pub const CHARGED_ATTACK_DURATION_MS: u64 = 500;
+    pub const CHARGED_ATTACK_DAMAGE: i32 = 3;
 
-    pub fn timer(&self) -> Option<Timer> {
+    /// How long to stretch the roll animation to: however long it takes to
+    /// cover the distance. Rounded to whole ms, since that's what
+    /// set_total_run_time_to takes.
+    fn roll_duration(numbers: &NumbersSettings) -> Duration {
+        Duration::from_millis((Self::ROLL_DISTANCE / numbers.player_roll_speed * 1000.0) as u64)
+    }
+
+    /// Charging's timer doesn't end the state; it just marks when you're
+    /// charged up enough for letting go to count. Rolls go by their
+    /// animation instead, so player_state_changes times those.
+    pub fn timer(&self, numbers: &NumbersSettings) -> Option<Timer> {
         match self {
             PlayerState::Idle => None,
             PlayerState::Run => None,
-            PlayerState::Roll { .. } => {
-                let duration_secs = Self::ROLL_DISTANCE / Self::ROLL_SPEED;
-                Some(Timer::from_seconds(duration_secs, TimerMode::Once))
-            },
+            PlayerState::Roll { .. } => None,
             PlayerState::Bonk { .. } => None,
             PlayerState::Attack => Some(Timer::new(
                 Duration::from_millis(Self::ATTACK_DURATION_MS),
                 TimerMode::Once,
             )),
+            PlayerState::Charging => Some(Timer::new(
+                Duration::from_millis(numbers.min_charge_ms),
+                TimerMode::Once,
+            )),
+            PlayerState::ChargedAttack => Some(Timer::new(
+                Duration::from_millis(Self::CHARGED_ATTACK_DURATION_MS),
+                TimerMode::Once,
+            )),
+            PlayerState::Hurt => Some(Timer::new(
+                Duration::from_millis(Self::HURT_DURATION_MS),
+                TimerMode::Once,
+            )),
         }
     }
 
-    pub fn animation_data(&self) -> (Ases, Playback, Option<u64>) {
+    pub fn animation_data(&self, numbers: &NumbersSettings) -> (Ases, Playback, Option<u64>) {
         match self {
             PlayerState::Idle => (Ases::TkIdle, Playback::Loop, None),
             PlayerState::Run => (Ases::TkRun, Playback::Loop, None),
             PlayerState::Roll { .. } => {
-                let duration = (Self::ROLL_DISTANCE / Self::ROLL_SPEED * 1000.0) as u64;
+                let duration = Self::roll_duration(numbers).as_millis() as u64;
                 (Ases::TkRoll, Playback::Once, Some(duration))
             },
             PlayerState::Bonk { .. } => (Ases::TkHurt, Playback::Once, None), // one frame, so no duration :)
@@ -109,6 +225,13 @@ impl PlayerState {
                 Playback::Once,
                 Some(Self::ATTACK_DURATION_MS),
             ),
+            PlayerState::Charging => (Ases::TkCharge, Playback::Loop, None),
+            PlayerState::ChargedAttack => (
+                Ases::TkChargedSlash,
+                Playback::Once,
+                Some(Self::CHARGED_ATTACK_DURATION_MS),
+            ),
+            PlayerState::Hurt => (Ases::TkHurt, Playback::Once, None),
         }
     }
 
@@ -117,7 +240,7 @@ impl PlayerState {
     /// a set of behaviors, but actually that's fiendishly complicated
     /// because those types are all different, so we do it the easy way.
     pub fn set_behaviors(&self, mut cmds: EntityCommands, numbers: &NumbersSettings) {
-        cmds.remove::<AllBehaviors>();
+        cmds.remove::<(AllBehaviors, Damage)>();
         match self {
             PlayerState::Idle => {
                 cmds.insert(MobileFree);
@@ -132,6 +255,7 @@ impl PlayerState {
                         face: true,
                     },
                     Headlong,
+                    Invincible,
                 ));
             },
             PlayerState::Bonk { bonk_input, .. } => {
@@ -147,12 +271,31 @@ impl PlayerState {
                     },
                 ));
             },
-            PlayerState::Attack => {
+            PlayerState::Attack | PlayerState::Charging => {
                 cmds.insert((MobileFixed {
                     input: Vec2::ZERO,
                     face: false,
                 },));
             },
+            PlayerState::ChargedAttack => {
+                cmds.insert((
+                    MobileFixed {
+                        input: Vec2::ZERO,
+                        face: false,
+                    },
+                    Damage(Self::CHARGED_ATTACK_DAMAGE),
+                ));
+            },
+            PlayerState::Hurt => {
+                cmds.insert((
+                    MobileFixed {
+                        input: Vec2::ZERO,
+                        face: false,
+                    },
+                    Hitstun,
+                    Invincible,
+                ));
+            },
         }
     }
 
@@ -177,51 +320,121 @@ impl PlayerState {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Reflect)]
 pub enum EnemyState {
     Idle,
-    Patrol { displacement: Vec2 },
-    Chase { target: Entity },
+    Patrol {
+        displacement: Vec2,
+    },
+    Chase {
+        target: Entity,
+    },
     Attack,
+    /// Spit something at the target, then go back to chasing it.
+    RangedAttack {
+        target: Entity,
+    },
     Hurt,
+    Fleeing {
+        from: Entity,
+    },
     Dying,
 }
 
+impl InterruptPriority for EnemyState {
+    fn interrupt_priority(&self) -> u8 {
+        match self {
+            EnemyState::Idle => 0,
+            EnemyState::Patrol { .. } => 1,
+            EnemyState::Chase { .. } => 2,
+            EnemyState::Attack => 3,
+            EnemyState::RangedAttack { .. } => 3,
+            EnemyState::Hurt => 4,
+            // Same as Hurt, so it can take over from the hit that scared it.
+            EnemyState::Fleeing { .. } => 4,
+            EnemyState::Dying => 5,
+        }
+    }
+}
+
 impl EnemyState {
+    pub const HURT_DURATION_SECS: f32 = 0.4;
+    pub const DYING_DURATION_SECS: f32 = 0.6;
+    /// Stun outlasts the hurt state a bit, so it doesn't re-aggro immediately.
+    pub const HURT_STUN_SECS: f32 = 0.8;
+    pub const FLEE_DURATION_SECS: f32 = 3.0;
+    pub const FLEE_SPEED_MULTIPLIER: f32 = 1.5;
+    /// Bail out once health gets this low.
+    pub const FLEE_HEALTH_THRESHOLD: i32 = 1;
+    pub const RANGED_ATTACK_SECS: f32 = 0.6;
+    // Defaults for the NumbersSettings that tune enemies.
+    pub const AGGRO_RANGE: f32 = 50.0;
+    pub const AGGRO_CHASE_TIMEOUT_SECS: f32 = 10.0;
+    pub const PATROL_IDLE_SECS: f32 = 2.0;
+
     pub fn animation_data(&self) -> (Ases, Playback) {
         match self {
             EnemyState::Idle { .. } => (Ases::SlimeIdle, Playback::Loop),
             EnemyState::Patrol { .. } => (Ases::SlimeIdle, Playback::Loop),
             EnemyState::Chase { .. } => (Ases::SlimeIdle, Playback::Loop),
             EnemyState::Attack => (Ases::SlimeAttack, Playback::Loop),
+            EnemyState::RangedAttack { .. } => (Ases::SlimeAttack, Playback::Once),
             EnemyState::Hurt => (Ases::SlimeHurt, Playback::Once),
+            EnemyState::Fleeing { .. } => (Ases::SlimeIdle, Playback::Loop),
             EnemyState::Dying => (Ases::SlimeDie, Playback::Once),
         }
     }
 
-    pub fn timer(&self) -> Option<Timer> {
+    pub fn timer(&self, numbers: &NumbersSettings) -> Option<Timer> {
         match self {
-            EnemyState::Idle => Some(Timer::from_seconds(2.0, TimerMode::Once)),
+            // These two come straight from the inspector, which will happily
+            // let you type in a negative number.
+            EnemyState::Idle => Some(Timer::from_seconds(
+                numbers.patrol_idle_duration.max(0.0),
+                TimerMode::Once,
+            )),
             EnemyState::Patrol { displacement, .. } => {
-                let duration_secs = displacement.length() / Speed::ENEMY_RUN;
+                // A stopped (or backwards) enemy isn't getting there, so don't
+                // bother walking. (Also, dividing by zero makes an infinite
+                // timer, which panics.)
+                let duration_secs = if numbers.enemy_run_speed > 0.0 {
+                    (displacement.length() / numbers.enemy_run_speed).max(0.0)
+                } else {
+                    0.0
+                };
                 Some(Timer::from_seconds(duration_secs, TimerMode::Once))
             },
             // TBH I don't think this is correct, but it'll get things moving until I sort out
             // how to wire a limit though to set_behaviors():
-            EnemyState::Chase { .. } => Some(Timer::from_seconds(10.0, TimerMode::Once)),
+            EnemyState::Chase { .. } => Some(Timer::from_seconds(
+                numbers.aggro_chase_timeout.max(0.0),
+                TimerMode::Once,
+            )),
             EnemyState::Attack => todo!(),
-            EnemyState::Hurt => todo!(),
-            EnemyState::Dying => todo!(),
+            EnemyState::RangedAttack { .. } => Some(Timer::from_seconds(
+                Self::RANGED_ATTACK_SECS,
+                TimerMode::Once,
+            )),
+            EnemyState::Hurt => Some(Timer::from_seconds(
+                Self::HURT_DURATION_SECS,
+                TimerMode::Once,
+            )),
+            EnemyState::Fleeing { .. } => Some(Timer::from_seconds(
+                Self::FLEE_DURATION_SECS,
+                TimerMode::Once,
+            )),
+            EnemyState::Dying => Some(Timer::from_seconds(
+                Self::DYING_DURATION_SECS,
+                TimerMode::Once,
+            )),
         }
     }
 
-    const SLIME_AGGRO_RANGE: f32 = 50.0;
-
-    pub fn set_behaviors(&self, mut cmds: EntityCommands) {
+    pub fn set_behaviors(&self, mut cmds: EntityCommands, numbers: &NumbersSettings) {
         cmds.remove::<AllBehaviors>();
         match self {
             EnemyState::Idle => {
-                cmds.insert(AggroRange(Self::SLIME_AGGRO_RANGE));
+                cmds.insert(AggroRange::new(numbers.aggro_range));
             },
             EnemyState::Patrol { displacement, .. } => {
                 cmds.insert((
@@ -229,7 +442,7 @@ impl EnemyState {
                         input: displacement.normalize_or_zero(),
                         face: true,
                     },
-                    AggroRange(Self::SLIME_AGGRO_RANGE),
+                    AggroRange::new(numbers.aggro_range),
                 ));
             },
             EnemyState::Chase { target } => {
@@ -239,8 +452,18 @@ impl EnemyState {
                 });
             },
             EnemyState::Attack => todo!(),
-            EnemyState::Hurt => todo!(),
-            EnemyState::Dying => todo!(),
+            // Plant your feet; the shot goes out from projectiles.rs.
+            EnemyState::RangedAttack { .. } => (),
+            EnemyState::Hurt => {
+                cmds.insert((Hitstun, Stunned::new(Self::HURT_STUN_SECS)));
+            },
+            EnemyState::Fleeing { from } => {
+                cmds.insert(Flee {
+                    from: *from,
+                    speed_multiplier: Self::FLEE_SPEED_MULTIPLIER,
+                });
+            },
+            EnemyState::Dying => (), // just lie there
         }
     }
 }
@@ -251,22 +474,203 @@ impl Default for EnemyState {
     }
 }
 
+/// Marker component: this enemy hasn't been Idle yet. Its first Idle gets a
+/// random fraction of the usual wait, so a room full of freshly spawned
+/// enemies doesn't all set off on patrol at the same instant.
+#[derive(Component, Default)]
+pub struct FirstIdle;
+
+impl FirstIdle {
+    /// Somewhere between no wait at all and a normal Idle.
+    fn timer(rng: &mut impl Rng, numbers: &NumbersSettings) -> Timer {
+        let secs = if numbers.patrol_idle_duration > 0.0 {
+            rng.gen_range(0.0..numbers.patrol_idle_duration)
+        } else {
+            0.0
+        };
+        Timer::from_seconds(secs, TimerMode::Once)
+    }
+}
+
 #[derive(Component)]
 pub enum PatrolArea {
+    /// Wander to random spots within some distance of home.
     Patch { home: Vec2, radius: f32 },
-    _Shush, // leave me alone about my irrefutable if lets, man
+    /// Walk a fixed route. Keeps track of where it's at with a WaypointProgress
+    /// component (which starts at the first point if missing).
+    Waypoints {
+        points: Vec<Vec2>,
+        loop_mode: WaypointLoop,
+    },
+    /// Walk the edge of a circle, stopping at evenly spaced points along the
+    /// way, like a guard doing rounds. Uses WaypointProgress for which stop
+    /// is next, same as Waypoints.
+    Ring {
+        center: Vec2,
+        radius: f32,
+        clockwise: bool,
+        ring_segment_count: u8,
+    },
+}
+
+/// What to do at the end of a waypoint route.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Reflect)]
+pub enum WaypointLoop {
+    /// Go back to the start and do it again.
+    #[default]
+    Cycle,
+    /// Turn around and walk it backwards.
+    PingPong,
+}
+
+/// Where an entity's at on its waypoint route.
+#[derive(Component, Reflect, Default)]
+pub struct WaypointProgress {
+    pub current_waypoint: usize,
+    /// Only used for PingPong.
+    pub reversing: bool,
+}
+
+impl WaypointProgress {
+    /// Move on to the next waypoint in a route of `len` points.
+    fn advance(&mut self, len: usize, loop_mode: WaypointLoop) {
+        if len <= 1 {
+            self.current_waypoint = 0;
+            return;
+        }
+        match loop_mode {
+            WaypointLoop::Cycle => {
+                self.current_waypoint = (self.current_waypoint + 1) % len;
+            },
+            WaypointLoop::PingPong => {
+                if self.reversing && self.current_waypoint == 0 {
+                    self.reversing = false;
+                } else if !self.reversing && self.current_waypoint >= len - 1 {
+                    self.reversing = true;
+                }
+                if self.reversing {
+                    self.current_waypoint -= 1;
+                } else {
+                    self.current_waypoint += 1;
+                }
+            },
+        }
+    }
 }
 
 impl PatrolArea {
-    pub fn random_destination(&self, rng: &mut impl Rng) -> Vec2 {
+    /// The LDtk points field to read waypoints from.
+    const LDTK_WAYPOINTS_FIELD: &'static str = "Waypoints";
+    /// Optional LDtk bool field; if true, the route ping-pongs instead of cycling.
+    const LDTK_PING_PONG_FIELD: &'static str = "PingPong";
+    /// Optional LDtk bool field; if true, walk a ring instead of wandering.
+    const LDTK_RING_FIELD: &'static str = "patrol_ring";
+    /// Optional LDtk bool field; if true, a ring patrol goes clockwise.
+    const LDTK_CLOCKWISE_FIELD: &'static str = "patrol_clockwise";
+    /// How many stops a Ring patrol makes if you don't say otherwise.
+    pub const DEFAULT_RING_SEGMENTS: u8 = 8;
+
+    /// A Ring patrol with the default number of stops, starting due east.
+    pub fn ring(center: Vec2, radius: f32, clockwise: bool) -> Self {
+        PatrolArea::Ring {
+            center,
+            radius,
+            clockwise,
+            ring_segment_count: Self::DEFAULT_RING_SEGMENTS,
+        }
+    }
+
+    /// Where to patrol to next. For waypoint routes, this also advances the
+    /// progress tracker. Returns None if there's nowhere to go (empty route).
+    pub fn next_destination(
+        &self,
+        rng: &mut impl Rng,
+        progress: Option<&mut WaypointProgress>,
+    ) -> Option<Vec2> {
         match self {
             PatrolArea::Patch { home, radius } => {
+                // No room to wander (or a garbage radius): just go home.
+                if !(radius.is_finite() && *radius > 0.0) {
+                    return Some(*home);
+                }
                 let angle: f32 = rng.gen_range(-(std::f32::consts::PI)..=std::f32::consts::PI);
                 let distance: f32 = rng.gen_range(0.0..*radius);
-                *home + Vec2::from_angle(angle) * distance
+                Some(*home + Vec2::from_angle(angle) * distance)
             },
-            PatrolArea::_Shush => todo!(),
+            PatrolArea::Waypoints { points, loop_mode } => {
+                let Some(progress) = progress else {
+                    return points.first().copied();
+                };
+                // Route might have changed out from under us; don't go out of bounds.
+                let dest = points
+                    .get(progress.current_waypoint)
+                    .or(points.first())
+                    .copied();
+                progress.advance(points.len(), *loop_mode);
+                dest
+            },
+            PatrolArea::Ring {
+                center,
+                radius,
+                clockwise,
+                ring_segment_count,
+            } => {
+                let segments = (*ring_segment_count).max(1) as usize;
+                let stop = match progress {
+                    Some(progress) => {
+                        let stop = progress.current_waypoint % segments;
+                        progress.advance(segments, WaypointLoop::Cycle);
+                        stop
+                    },
+                    None => 0,
+                };
+                let direction = if *clockwise { -1.0 } else { 1.0 };
+                let angle = direction * std::f32::consts::TAU * stop as f32 / segments as f32;
+                Some(*center + Vec2::from_angle(angle) * *radius)
+            },
+        }
+    }
+
+    /// Build a waypoint route from an LDtk entity's "Waypoints" points field.
+    /// LDtk points are grid coords within the entity's layer, so you need to
+    /// pass that layer's height (in cells) and grid size, plus the world
+    /// position of the layer's origin. Returns None if the field's missing.
+    pub fn waypoints_from_ldtk(
+        instance: &EntityInstance,
+        layer_grid_height: i32,
+        grid_size: i32,
+        layer_origin: Vec2,
+    ) -> Option<Self> {
+        let points = instance
+            .iter_points_field(Self::LDTK_WAYPOINTS_FIELD)
+            .ok()?
+            .map(|&coords| {
+                layer_origin
+                    + ldtk_grid_coords_to_translation(
+                        coords,
+                        layer_grid_height,
+                        IVec2::splat(grid_size),
+                    )
+            })
+            .collect();
+        let loop_mode = match instance.get_bool_field(Self::LDTK_PING_PONG_FIELD) {
+            Ok(&true) => WaypointLoop::PingPong,
+            _ => WaypointLoop::Cycle,
+        };
+        Some(PatrolArea::Waypoints { points, loop_mode })
+    }
+
+    /// Build a Ring patrol around `center` if an LDtk entity's "patrol_ring"
+    /// field says to. Returns None if it's missing or off.
+    pub fn ring_from_ldtk(instance: &EntityInstance, center: Vec2, radius: f32) -> Option<Self> {
+        if !matches!(instance.get_bool_field(Self::LDTK_RING_FIELD), Ok(true)) {
+            return None;
         }
+        let clockwise = matches!(
+            instance.get_bool_field(Self::LDTK_CLOCKWISE_FIELD),
+            Ok(true)
+        );
+        Some(Self::ring(center, radius, clockwise))
     }
 }
 
@@ -275,10 +679,24 @@ impl PatrolArea {
 /// Hey, how much CAN I get away with processing at this point? I know I want to handle
 /// walk/idle transitions here, but..... action button?
 pub fn player_state_read_inputs(
-    inputs: Res<CurrentInputs>,
-    mut player_q: Query<(&mut PlayerStateMachine, &mut Motion)>,
+    mut all_inputs: ResMut<CurrentInputs>,
+    mut player_q: Query<(
+        &mut PlayerStateMachine,
+        &mut Motion,
+        &StateTimer,
+        &PlayerIndex,
+        Has<Stunned>,
+    )>,
 ) {
-    for (mut machine, mut motion) in player_q.iter_mut() {
+    for (mut machine, mut motion, state_timer, index, stunned) in player_q.iter_mut() {
+        let inputs = all_inputs.player_mut(*index);
+        // Dazed: no inputs for you. Stop running in place (or charging), tho.
+        if stunned {
+            if let PlayerState::Run | PlayerState::Charging = machine.current() {
+                machine.push_transition(PlayerState::Idle);
+            }
+            continue;
+        }
         // Moves -- ignored unless run or idle
         let move_input = inputs.movement;
         match machine.current() {
@@ -297,24 +715,28 @@ pub fn player_state_read_inputs(
             _ => (),
         }
 
-        // Action button
-        if inputs.actioning {
-            // Right now there is only roll.
-            match machine.current() {
-                PlayerState::Idle | PlayerState::Run => {
+        // Action and attack buttons: presses wait in the buffer until we're
+        // in a state that can use them.
+        if let PlayerState::Idle | PlayerState::Run = machine.current() {
+            match inputs.take_buffered_action() {
+                Some(ActionKind::Roll) => {
                     machine.push_transition(PlayerState::roll(motion.facing));
                 },
-                _ => (),
+                Some(ActionKind::Attack) => {
+                    machine.push_transition(PlayerState::attack());
+                },
+                None => (),
             }
         }
 
-        // Attack button
-        if inputs.attacking {
-            match machine.current() {
-                PlayerState::Idle | PlayerState::Run => {
-                    machine.push_transition(PlayerState::attack());
-                },
-                _ => (),
+        // Letting go of a charge: big swing if it's ready, fizzle if not.
+        if let PlayerState::Charging = machine.current() {
+            if !inputs.attacking_held {
+                if state_timer.is_expired() {
+                    machine.push_transition(PlayerState::ChargedAttack);
+                } else {
+                    machine.push_transition(PlayerState::Idle);
+                }
             }
         }
     }
@@ -323,25 +745,41 @@ pub fn player_state_read_inputs(
 pub fn player_state_read_events(
     mut rebound_events: EventReader<Rebound>,
     mut landing_events: EventReader<Landed>,
-    mut player_q: Query<&mut PlayerStateMachine>,
+    mut damage_events: EventReader<TakeDamage>,
+    mut player_q: Query<(
+        &mut PlayerStateMachine,
+        Option<&Health>,
+        Has<Invincible>,
+        Has<Dying>,
+    )>,
 ) {
     for rb in rebound_events.read() {
-        if let Ok(mut machine) = player_q.get_mut(rb.entity) {
+        if let Ok((mut machine, ..)) = player_q.get_mut(rb.entity) {
             machine.push_transition(PlayerState::bonk_from_vector(rb.vector));
         }
     }
     for ld in landing_events.read() {
-        if let Ok(mut machine) = player_q.get_mut(ld.0) {
+        if let Ok((mut machine, ..)) = player_q.get_mut(ld.0) {
             if let PlayerState::Bonk { .. } = machine.current() {
                 machine.push_transition(PlayerState::Idle);
             }
         }
     }
+    for dmg in damage_events.read() {
+        if let Ok((mut machine, health, invincible, dying)) = player_q.get_mut(dmg.target) {
+            // Same rules as health_drain_system: i-frames and corpses don't flinch.
+            if invincible || dying || health.is_some_and(|h| h.is_dead()) {
+                continue;
+            }
+            machine.push_transition(PlayerState::Hurt);
+        }
+    }
 }
 
 /// Near the start of every frame, check whether the player state machine is switching
 /// states; if so, handle any setup and housekeeping to make the new state usable on the
 /// current frame.
+#[allow(clippy::too_many_arguments)]
 pub fn player_state_changes(
     mut player_q: Query<(
         Entity,
@@ -349,36 +787,51 @@ pub fn player_state_changes(
         &mut StateTimer,
         &mut Speed,
         &mut CharAnimationState,
+        &PhysTransform,
+        &PlayerIndex,
     )>,
     animations_map: Res<AnimationsMap>,
+    animations: Res<Assets<CharAnimation>>,
     time: Res<Time>,
     numbers: Res<NumbersSettings>,
+    inputs: Res<CurrentInputs>,
+    sfx: Res<SoundEffects>,
+    sfx_config: Res<SfxConfig>,
+    mut cooldowns: ResMut<SoundCooldown>,
     mut commands: Commands,
 ) {
-    for (entity, mut machine, mut state_timer, mut speed, mut animation_state) in
+    for (entity, mut machine, mut state_timer, mut speed, mut animation_state, transform, index) in
         player_q.iter_mut()
     {
         // FIRST: if a state used up its time allotment last frame (without being interrupted),
         // this is where we queue up a transition to the next state.
-        if let Some(ref timer) = state_timer.0 {
-            if machine.next.is_none() && timer.finished() {
-                match machine.current() {
-                    PlayerState::Idle => (), // not timed
-                    PlayerState::Run => (),  // not timed
-                    PlayerState::Roll { .. } => machine.push_transition(PlayerState::Idle),
-                    PlayerState::Bonk { .. } => machine.push_transition(PlayerState::Idle),
-                    PlayerState::Attack => machine.push_transition(PlayerState::Idle),
-                }
+        if machine.next.is_none() && state_timer.is_expired() {
+            match machine.current() {
+                PlayerState::Idle => (), // not timed
+                PlayerState::Run => (),  // not timed
+                PlayerState::Roll { .. } => machine.push_transition(PlayerState::Idle),
+                PlayerState::Bonk { .. } => machine.push_transition(PlayerState::Idle),
+                PlayerState::Attack => {
+                    // Still holding the button? Start winding up.
+                    if inputs.player(*index).attacking_held {
+                        machine.push_transition(PlayerState::Charging);
+                    } else {
+                        machine.push_transition(PlayerState::Idle);
+                    }
+                },
+                PlayerState::Charging => (), // Waits for a release.
+                PlayerState::ChargedAttack => machine.push_transition(PlayerState::Idle),
+                PlayerState::Hurt => machine.push_transition(PlayerState::Idle),
             }
         }
 
         // SEVERAL-TH: maybe change states, and do setup housekeeping for the new state.
         machine.do_transition(|machine| {
             // SECOND: Set new Option<Timer>
-            state_timer.0 = machine.current().timer();
+            state_timer.timer = machine.current().timer(&numbers);
 
             // THIRD: Update sprite
-            let (name, play, time) = machine.current().animation_data();
+            let (name, play, time) = machine.current().animation_data(&numbers);
             if let Some(ani) = animations_map.get(&name) {
                 animation_state.change_animation(ani.clone(), play);
                 if let Some(run_ms) = time {
@@ -387,24 +840,46 @@ pub fn player_state_changes(
             } else {
                 warn!("Tried to set missing animation {:?} on player", name);
             }
+            // Rolls end when their animation does. If it hasn't loaded yet,
+            // go by the run time we asked it to stretch to.
+            if let PlayerState::Roll { .. } = machine.current() {
+                state_timer.timer = animation_state
+                    .remaining_total(&animations)
+                    .or(time.map(Duration::from_millis))
+                    .map(|duration| Timer::new(duration, TimerMode::Once));
+            }
 
             // FOURTH: Update speed
             speed.0 = match machine.current() {
                 PlayerState::Idle => 0.0,
-                PlayerState::Run => Speed::RUN,
-                PlayerState::Roll { .. } => Speed::ROLL,
-                PlayerState::Bonk { .. } => Speed::BONK,
+                PlayerState::Run => numbers.player_run_speed,
+                PlayerState::Roll { .. } => numbers.player_roll_speed,
+                PlayerState::Bonk { .. } => numbers.player_bonk_speed,
                 PlayerState::Attack { .. } => 0.0,
+                PlayerState::Charging => 0.0,
+                PlayerState::ChargedAttack => 0.0,
+                PlayerState::Hurt => 0.0,
             };
 
             // FIFTH: Add and remove behavioral components
             machine
                 .current()
                 .set_behaviors(commands.entity(entity), &numbers);
+
+            // SIXTH: Make some noise
+            let noise = match machine.current() {
+                PlayerState::Attack | PlayerState::ChargedAttack => Some(&sfx.player_attack_sfx),
+                PlayerState::Hurt => Some(&sfx.player_hurt_sfx),
+                _ => None,
+            };
+            if let Some(noise) = noise {
+                let loc = Some(transform.translation.truncate());
+                play_sfx(&mut commands, &mut cooldowns, &sfx_config, noise, loc);
+            }
         });
 
-        // SIXTH: If the current state has a timer, tick it forward.
-        if let Some(ref mut timer) = state_timer.0 {
+        // SEVENTH: If the current state has a timer, tick it forward.
+        if let Some(ref mut timer) = state_timer.timer {
             timer.tick(time.delta());
         }
     }
@@ -412,53 +887,137 @@ pub fn player_state_changes(
 
 pub fn enemy_state_read_events(
     mut aggroing: EventReader<AggroActivate>,
-    mut query: Query<&mut EnemyStateMachine>,
+    mut damage_events: EventReader<TakeDamage>,
+    mut died_events: EventReader<EntityDied>,
+    mut query: Query<(&mut EnemyStateMachine, Option<&Health>)>,
 ) {
     for aggro in aggroing.read() {
-        if let Ok(mut machine) = query.get_mut(aggro.subject) {
+        if let Ok((mut machine, _)) = query.get_mut(aggro.subject) {
             machine.push_transition(EnemyState::Chase {
                 target: aggro.target,
             });
         }
     }
+    for dmg in damage_events.read() {
+        if let Ok((mut machine, health)) = query.get_mut(dmg.target) {
+            if matches!(machine.current(), EnemyState::Dying) {
+                continue;
+            }
+            machine.push_transition(EnemyState::Hurt);
+            // Health's already been drained by now. Hurting and alive? Run for it.
+            let low_health = health
+                .is_some_and(|h| !h.is_dead() && h.current <= EnemyState::FLEE_HEALTH_THRESHOLD);
+            if let (true, Some(from)) = (low_health, dmg.source) {
+                machine.push_transition(EnemyState::Fleeing { from });
+            }
+        }
+    }
+    // Deaths go last, so they win over anything else queued this frame.
+    for died in died_events.read() {
+        if let Ok((mut machine, _)) = query.get_mut(died.0) {
+            machine.push_transition(EnemyState::Dying);
+        }
+    }
 }
 
+/// Give newly spawned enemies their own RNG, forked off the global one. That
+/// way they don't all have to take turns with a single ResMut, and a given
+/// global seed always plays out the same.
+pub fn enemy_setup_rng_system(
+    new_enemies_q: Query<Entity, (Added<Enemy>, Without<EnemyRNG>)>,
+    mut global_rng: ResMut<GameRNG>,
+    mut commands: Commands,
+) {
+    for enemy in new_enemies_q.iter() {
+        commands.entity(enemy).insert(global_rng.fork_rng());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn enemy_state_changes(
     mut query: Query<(
         Entity,
         &mut EnemyStateMachine,
         &mut StateTimer,
         &mut CharAnimationState,
+        &mut Speed,
         &PatrolArea,
+        Option<&mut WaypointProgress>,
         &PhysTransform,
+        &mut EnemyRNG,
+        Has<FirstIdle>,
     )>,
     time: Res<Time>,
-    mut rng: ResMut<GameRNG>,
     animations_map: Res<AnimationsMap>,
+    sfx: Res<SoundEffects>,
+    sfx_config: Res<SfxConfig>,
+    mut cooldowns: ResMut<SoundCooldown>,
+    numbers: Res<NumbersSettings>,
     mut commands: Commands,
 ) {
-    // Going in serial, because I'm using a global RNG still (instead of forking it to each enemy)
-    for (entity, mut machine, mut state_timer, mut anim, patrol, transform) in query.iter_mut() {
+    for (
+        entity,
+        mut machine,
+        mut state_timer,
+        mut anim,
+        mut speed,
+        patrol,
+        mut progress,
+        transform,
+        mut rng,
+        first_idle,
+    ) in query.iter_mut()
+    {
         // ZEROTH: if a state spent its timer, queue a transition.
-        if let Some(ref timer) = state_timer.0 {
-            if machine.next.is_none() && timer.finished() {
-                match machine.current() {
-                    EnemyState::Idle => {
-                        // Decide where we're patrolling to next
-                        let dest = patrol.random_destination(&mut *rng);
-                        let displacement = dest - transform.translation.truncate();
-                        machine.push_transition(EnemyState::Patrol { displacement });
-                    },
-                    EnemyState::Patrol { .. } => {
-                        machine.push_transition(EnemyState::Idle);
-                    },
-                    EnemyState::Chase { .. } => {
-                        machine.push_transition(EnemyState::Idle);
-                    },
-                    EnemyState::Attack => todo!(),
-                    EnemyState::Hurt => todo!(),
-                    EnemyState::Dying => todo!(),
-                }
+        if machine.next.is_none() && state_timer.is_expired() {
+            match machine.current() {
+                EnemyState::Idle if numbers.enemy_run_speed <= 0.0 => {
+                    // Can't walk anywhere, so don't use up a waypoint trying.
+                    machine.push_transition(EnemyState::Idle);
+                },
+                EnemyState::Idle => {
+                    // Decide where we're patrolling to next
+                    match patrol.next_destination(&mut *rng, progress.as_deref_mut()) {
+                        Some(dest) => {
+                            let displacement = dest - transform.translation.truncate();
+                            machine.push_transition(EnemyState::Patrol { displacement });
+                        },
+                        // Nowhere to go, so hang out some more.
+                        None => machine.push_transition(EnemyState::Idle),
+                    }
+                },
+                EnemyState::Patrol { .. } => {
+                    machine.push_transition(EnemyState::Idle);
+                },
+                EnemyState::Chase { .. } => {
+                    machine.push_transition(EnemyState::Idle);
+                },
+                EnemyState::Attack => todo!(),
+                EnemyState::RangedAttack { target } => {
+                    let target = *target;
+                    machine.push_transition(EnemyState::Chase { target });
+                },
+                EnemyState::Hurt => {
+                    machine.push_transition(EnemyState::Idle);
+                },
+                EnemyState::Fleeing { .. } => {
+                    machine.push_transition(EnemyState::Idle);
+                },
+                EnemyState::Dying => {
+                    // That's all, folks
+                    commands.entity(entity).despawn_recursive();
+                    continue;
+                },
+            }
+        }
+        // Also: if we're chasing something and we've got a shot ready, take it.
+        if let (EnemyState::Chase { target }, Some(cooldown)) =
+            (machine.current(), state_timer.attack_cooldown.as_mut())
+        {
+            if machine.next.is_none() && cooldown.finished() {
+                cooldown.reset();
+                let target = *target;
+                machine.push_transition(EnemyState::RangedAttack { target });
             }
         }
 
@@ -467,7 +1026,15 @@ pub fn enemy_state_changes(
             let current = machine.current();
 
             // Set new Option<Timer>
-            state_timer.0 = current.timer();
+            state_timer.timer = current.timer(&numbers);
+            if first_idle && matches!(current, EnemyState::Idle) {
+                state_timer.timer = Some(FirstIdle::timer(&mut *rng, &numbers));
+                commands.entity(entity).remove::<FirstIdle>();
+            }
+
+            // Pick up any speed tweaks from the inspector. (Fleeing's
+            // multiplier rides on top of this.)
+            speed.0 = numbers.enemy_run_speed;
 
             // Update sprite
             let (name, play) = current.animation_data();
@@ -481,13 +1048,28 @@ pub fn enemy_state_changes(
             }
 
             // THIRD??: add and remove behaviors
-            current.set_behaviors(commands.entity(entity));
+            current.set_behaviors(commands.entity(entity), &numbers);
+
+            if matches!(current, EnemyState::Dying) {
+                let loc = Some(transform.translation.truncate());
+                play_sfx(
+                    &mut commands,
+                    &mut cooldowns,
+                    &sfx_config,
+                    &sfx.enemy_die_sfx,
+                    loc,
+                );
+            }
         });
 
-        // Finally: if the current state has a timer, tick it.
-        if let Some(ref mut timer) = state_timer.0 {
+        // Finally: if the current state has a timer, tick it. Same for the
+        // attack cooldown.
+        if let Some(ref mut timer) = state_timer.timer {
             timer.tick(time.delta());
         }
+        if let Some(ref mut cooldown) = state_timer.attack_cooldown {
+            cooldown.tick(time.delta());
+        }
     }
 }
 
@@ -509,3 +1091,357 @@ pub fn player_queue_wall_bonk(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn higher_priority_transitions_stick() {
+        let mut machine = PlayerStateMachine::new(PlayerState::Idle);
+        machine.do_transition(|_| ());
+
+        machine.push_transition(PlayerState::roll(0.0));
+        machine.push_transition(PlayerState::bonk_from_vector(Vec2::X));
+        machine.push_transition(PlayerState::Run);
+        assert!(matches!(machine.next, Some(PlayerState::Roll { .. })));
+
+        // Equal or higher priority replaces.
+        machine.push_transition(PlayerState::roll(1.0));
+        machine.push_transition(PlayerState::Hurt);
+        assert!(matches!(machine.next, Some(PlayerState::Hurt)));
+
+        machine.do_transition(|_| ());
+        assert!(matches!(machine.current(), PlayerState::Hurt));
+        // Nothing queued, so anything goes.
+        machine.push_transition(PlayerState::Idle);
+        assert!(matches!(machine.next, Some(PlayerState::Idle)));
+    }
+
+    #[test]
+    fn history_skips_the_startup_transition() {
+        let mut machine = PlayerStateMachine::new(PlayerState::Idle);
+        machine.do_transition(|_| ());
+        assert!(machine.state_history.is_empty());
+
+        machine.push_transition(PlayerState::Run);
+        machine.do_transition(|_| ());
+        machine.push_transition(PlayerState::Attack);
+        machine.do_transition(|_| ());
+        let history: Vec<_> = machine.state_history.iter().collect();
+        assert!(matches!(history[..], [PlayerState::Idle, PlayerState::Run]));
+    }
+
+    fn route(mode: WaypointLoop, progress: &mut WaypointProgress, steps: usize) -> Vec<usize> {
+        let patrol = PatrolArea::Waypoints {
+            points: vec![Vec2::ZERO, Vec2::X, Vec2::Y],
+            loop_mode: mode,
+        };
+        let mut rng = rand::thread_rng();
+        (0..steps)
+            .map(|_| {
+                let dest = patrol
+                    .next_destination(&mut rng, Some(&mut *progress))
+                    .unwrap();
+                [Vec2::ZERO, Vec2::X, Vec2::Y]
+                    .iter()
+                    .position(|&p| p == dest)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn patches_with_no_room_stay_home() {
+        let home = Vec2::new(10.0, -4.0);
+        let mut rng = rand::thread_rng();
+        for radius in [0.0, -5.0, f32::NAN] {
+            let patrol = PatrolArea::Patch { home, radius };
+            assert_eq!(patrol.next_destination(&mut rng, None), Some(home));
+        }
+    }
+
+    #[test]
+    fn waypoints_cycle() {
+        let mut progress = WaypointProgress::default();
+        assert_eq!(
+            route(WaypointLoop::Cycle, &mut progress, 7),
+            vec![0, 1, 2, 0, 1, 2, 0]
+        );
+    }
+
+    #[test]
+    fn waypoints_ping_pong() {
+        let mut progress = WaypointProgress::default();
+        assert_eq!(
+            route(WaypointLoop::PingPong, &mut progress, 9),
+            vec![0, 1, 2, 1, 0, 1, 2, 1, 0]
+        );
+    }
+
+    #[test]
+    fn rings_go_around_the_edge() {
+        let mut rng = rand::thread_rng();
+        let mut progress = WaypointProgress::default();
+        let center = Vec2::new(100.0, 50.0);
+        let mut stops = |patrol: &PatrolArea, progress: &mut WaypointProgress| -> Vec<Vec2> {
+            (0..5)
+                .map(|_| patrol.next_destination(&mut rng, Some(progress)).unwrap())
+                .collect()
+        };
+
+        let square = PatrolArea::Ring {
+            center,
+            radius: 10.0,
+            clockwise: false,
+            ring_segment_count: 4,
+        };
+        let expected = [
+            Vec2::new(110.0, 50.0),
+            Vec2::new(100.0, 60.0),
+            Vec2::new(90.0, 50.0),
+            Vec2::new(100.0, 40.0),
+            Vec2::new(110.0, 50.0),
+        ];
+        for (got, want) in stops(&square, &mut progress).iter().zip(expected) {
+            assert!(got.abs_diff_eq(want, 0.001), "{got} != {want}");
+        }
+
+        // Clockwise goes south first; default is eight stops.
+        let mut progress = WaypointProgress::default();
+        let clockwise = PatrolArea::ring(center, 10.0, true);
+        let got = stops(&clockwise, &mut progress);
+        assert!(got[0].abs_diff_eq(Vec2::new(110.0, 50.0), 0.001));
+        assert!(got[2].abs_diff_eq(Vec2::new(100.0, 40.0), 0.001));
+        for stop in got {
+            assert!((stop.distance(center) - 10.0).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn rings_come_from_ldtk_fields() {
+        use crate::test_utils::ldtk_instance;
+        let center = Vec2::new(5.0, 5.0);
+        let plain = ldtk_instance(vec![("patrol_radius", FieldValue::Float(Some(30.0)))]);
+        assert!(PatrolArea::ring_from_ldtk(&plain, center, 30.0).is_none());
+        let off = ldtk_instance(vec![("patrol_ring", FieldValue::Bool(false))]);
+        assert!(PatrolArea::ring_from_ldtk(&off, center, 30.0).is_none());
+        let guard = ldtk_instance(vec![
+            ("patrol_ring", FieldValue::Bool(true)),
+            ("patrol_clockwise", FieldValue::Bool(true)),
+        ]);
+        assert!(matches!(
+            PatrolArea::ring_from_ldtk(&guard, center, 30.0),
+            Some(PatrolArea::Ring {
+                radius: 30.0,
+                clockwise: true,
+                ring_segment_count: PatrolArea::DEFAULT_RING_SEGMENTS,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn waypoints_degenerate_routes() {
+        let mut rng = rand::thread_rng();
+        let mut progress = WaypointProgress::default();
+        let empty = PatrolArea::Waypoints {
+            points: vec![],
+            loop_mode: WaypointLoop::PingPong,
+        };
+        assert_eq!(empty.next_destination(&mut rng, Some(&mut progress)), None);
+        let lonely = PatrolArea::Waypoints {
+            points: vec![Vec2::ONE],
+            loop_mode: WaypointLoop::PingPong,
+        };
+        for _ in 0..3 {
+            assert_eq!(
+                lonely.next_destination(&mut rng, Some(&mut progress)),
+                Some(Vec2::ONE)
+            );
+        }
+        // No progress tracker: just head for the start.
+        assert_eq!(lonely.next_destination(&mut rng, None), Some(Vec2::ONE));
+    }
+
+    #[test]
+    fn only_hittable_players_get_hurt() {
+        use bevy::ecs::system::RunSystemOnce;
+        let mut world = World::new();
+        world.init_resource::<Events<Rebound>>();
+        world.init_resource::<Events<Landed>>();
+        world.init_resource::<Events<TakeDamage>>();
+        let mut spawn = |health: Health| {
+            let mut machine = PlayerStateMachine::new(PlayerState::Idle);
+            machine.do_transition(|_| ());
+            world.spawn((machine, health)).id()
+        };
+        let plain = spawn(Health::new(3));
+        let rolling = spawn(Health::new(3));
+        let dead = spawn(Health { current: 0, max: 3 });
+        world.entity_mut(rolling).insert(Invincible);
+        for target in [plain, rolling, dead] {
+            world.send_event(TakeDamage {
+                target,
+                amount: 1,
+                source: None,
+            });
+        }
+        world.run_system_once(player_state_read_events);
+
+        let next = |e: Entity| &world.get::<PlayerStateMachine>(e).unwrap().next;
+        assert!(matches!(next(plain), Some(PlayerState::Hurt)));
+        assert!(next(rolling).is_none());
+        assert!(next(dead).is_none());
+    }
+
+    #[test]
+    fn dying_beats_everything() {
+        let mut machine = EnemyStateMachine::new(EnemyState::Idle);
+        machine.push_transition(EnemyState::Dying);
+        machine.push_transition(EnemyState::Hurt);
+        machine.push_transition(EnemyState::Patrol {
+            displacement: Vec2::ONE,
+        });
+        assert!(matches!(machine.next, Some(EnemyState::Dying)));
+    }
+
+    fn forked_enemy_rng(seed: u8) -> EnemyRNG {
+        use bevy::ecs::system::RunSystemOnce;
+        let mut app = App::new();
+        app.add_plugins(EntropyPlugin::<Xoshiro256Plus>::with_seed([seed; 32]));
+        let enemy = app.world_mut().spawn(Enemy).id();
+        app.world_mut().run_system_once(enemy_setup_rng_system);
+        app.world_mut()
+            .entity_mut(enemy)
+            .take::<EnemyRNG>()
+            .unwrap()
+    }
+
+    #[test]
+    fn same_seed_same_patrol() {
+        let patrol = PatrolArea::Patch {
+            home: Vec2::ZERO,
+            radius: 50.0,
+        };
+        let walk = |mut rng: EnemyRNG| -> Vec<Vec2> {
+            (0..5)
+                .map(|_| patrol.next_destination(&mut rng, None).unwrap())
+                .collect()
+        };
+        let first = walk(forked_enemy_rng(7));
+        assert_eq!(first, walk(forked_enemy_rng(7)));
+        assert_ne!(first, walk(forked_enemy_rng(8)));
+    }
+
+    #[test]
+    fn first_idles_are_staggered() {
+        let numbers = NumbersSettings::default();
+        let mut rng = forked_enemy_rng(3);
+        let waits: Vec<f32> = (0..5)
+            .map(|_| {
+                FirstIdle::timer(&mut rng, &numbers)
+                    .duration()
+                    .as_secs_f32()
+            })
+            .collect();
+        assert!(waits
+            .iter()
+            .all(|w| (0.0..numbers.patrol_idle_duration).contains(w)));
+        assert!(waits.windows(2).any(|pair| pair[0] != pair[1]));
+
+        let no_idling = NumbersSettings {
+            patrol_idle_duration: 0.0,
+            ..Default::default()
+        };
+        let timer = FirstIdle::timer(&mut rng, &no_idling);
+        assert_eq!(timer.duration(), Duration::ZERO);
+    }
+
+    #[test]
+    fn inspector_can_see_inside_state_machines() {
+        use bevy::reflect::{ReflectRef, TypeRegistry};
+        use std::any::TypeId;
+
+        // Registering the machines (like main does) pulls in the states.
+        let mut registry = TypeRegistry::default();
+        registry.register::<PlayerStateMachine>();
+        registry.register::<EnemyStateMachine>();
+        assert!(registry.contains(TypeId::of::<PlayerState>()));
+        assert!(registry.contains(TypeId::of::<EnemyState>()));
+
+        let machine = EnemyStateMachine::new(EnemyState::Chase {
+            target: Entity::from_raw(9),
+        });
+        let ReflectRef::Struct(fields) = machine.reflect_ref() else {
+            panic!("state machines should reflect as structs");
+        };
+        let current = fields.field("current").unwrap();
+        let ReflectRef::Enum(state) = current.reflect_ref() else {
+            panic!("states should reflect as enums");
+        };
+        assert_eq!(state.variant_name(), "Chase");
+        assert_eq!(
+            state.field("target").unwrap().downcast_ref::<Entity>(),
+            Some(&Entity::from_raw(9))
+        );
+    }
+
+    #[test]
+    fn stopped_enemies_dont_patrol() {
+        let stopped = NumbersSettings {
+            enemy_run_speed: 0.0,
+            ..Default::default()
+        };
+        let patrol = EnemyState::Patrol {
+            displacement: Vec2::new(30.0, 40.0),
+        };
+        let timer = patrol.timer(&stopped).unwrap();
+        assert_eq!(timer.duration(), Duration::ZERO);
+        let backwards = NumbersSettings {
+            enemy_run_speed: -10.0,
+            ..Default::default()
+        };
+        let timer = patrol.timer(&backwards).unwrap();
+        assert_eq!(timer.duration(), Duration::ZERO);
+
+        let timer = patrol.timer(&NumbersSettings::default()).unwrap();
+        let expected = 50.0 / NumbersSettings::default().enemy_run_speed;
+        assert!((timer.duration().as_secs_f32() - expected).abs() < 0.001);
+    }
+
+    #[test]
+    fn negative_waits_dont_panic() {
+        let impatient = NumbersSettings {
+            patrol_idle_duration: -1.0,
+            aggro_chase_timeout: -1.0,
+            ..Default::default()
+        };
+        let idle = EnemyState::Idle.timer(&impatient).unwrap();
+        assert_eq!(idle.duration(), Duration::ZERO);
+        let chase = EnemyState::Chase {
+            target: Entity::from_raw(1),
+        };
+        assert_eq!(chase.timer(&impatient).unwrap().duration(), Duration::ZERO);
+        let first = FirstIdle::timer(&mut forked_enemy_rng(1), &impatient);
+        assert_eq!(first.duration(), Duration::ZERO);
+    }
+
+    #[test]
+    fn state_timer_expiry() {
+        let mut untimed = StateTimer::default();
+        assert!(!untimed.is_expired());
+
+        untimed.timer = Some(Timer::from_seconds(2.0, TimerMode::Once));
+        let mut timed = untimed;
+        timed
+            .timer
+            .as_mut()
+            .unwrap()
+            .tick(Duration::from_millis(500));
+        assert!(!timed.is_expired());
+
+        timed.timer.as_mut().unwrap().tick(Duration::from_secs(5));
+        assert!(timed.is_expired());
+    }
+}
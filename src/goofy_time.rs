@@ -1,7 +1,18 @@
+//! Alternate clocks, for when frame timing is being weird and you want to
+//! see whether that's what's making things jittery. Which one gets used is
+//! up to DebugSettings::time_mode; only the camera listens so far. Also a
+//! plain tick counter, for stuff that cares which frame it is rather than
+//! how long it's been.
+
 use bevy::prelude::*;
 use bevy::utils::Duration;
 use std::collections::VecDeque;
 
+/// How many recent frames the smoothed clock looks at...
+const SMOOTHING_WINDOW: usize = 11;
+/// ...and how many outliers it throws out at each end.
+const SMOOTHING_TRIM: usize = 2;
+
 pub struct SmoothedTimePlugin;
 impl Plugin for SmoothedTimePlugin {
     fn build(&self, app: &mut App) {
@@ -22,6 +33,33 @@ impl Plugin for StaticTimePlugin {
     }
 }
 
+pub struct GameTickPlugin;
+impl Plugin for GameTickPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameTick>()
+            .add_systems(Last, increment_tick_system);
+    }
+}
+
+/// Resource: how many game ticks have gone by. Unlike Bevy's FrameCount, it
+/// holds still while the virtual clock's stopped (paused, or waiting on a
+/// frame advance), so it stays in step with everything that goes by Time.
+/// Starts at 0, not 1: the first frame's Update sees 0.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameTick(pub u64);
+
+/// Which clock time-sensitive debug stuff should go by.
+#[derive(Resource, Reflect, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeMode {
+    /// Plain old Time.
+    #[default]
+    Real,
+    /// SmoothedTime: recent frame times, minus the outliers.
+    Smoothed,
+    /// StaticTime: pretend every frame is exactly 1/60s.
+    Static,
+}
+
 #[derive(Resource)]
 struct RecentFrameTimes {
     buffer: VecDeque<Duration>,
@@ -32,24 +70,44 @@ pub struct SmoothedTime {
 }
 
 impl SmoothedTime {
-    pub fn _delta_seconds(&self) -> f32 {
+    pub fn delta_seconds(&self) -> f32 {
         self.delta.as_secs_f32()
     }
-    pub fn _delta(&self) -> Duration {
-        self.delta
-    }
 }
 
 #[derive(Resource)]
 pub struct StaticTime;
 
 impl StaticTime {
-    pub fn _delta_seconds(&self) -> f32 {
+    pub fn delta_seconds(&self) -> f32 {
         1. / 60.
     }
-    pub fn _delta(&self) -> Duration {
-        Duration::new(1, 0) / 60
+}
+
+/// Frame delta according to whichever clock `mode` says.
+pub fn delta_seconds_for(
+    mode: TimeMode,
+    time: &Time,
+    smoothed: &SmoothedTime,
+    static_time: &StaticTime,
+) -> f32 {
+    match mode {
+        TimeMode::Real => time.delta_seconds(),
+        TimeMode::Smoothed => smoothed.delta_seconds(),
+        TimeMode::Static => static_time.delta_seconds(),
+    }
+}
+
+/// Average of the recent frame times, ignoring the slowest and fastest few.
+/// Until the window fills up, it's just the latest frame.
+fn smoothed_delta(recent: &VecDeque<Duration>) -> Duration {
+    if recent.len() < SMOOTHING_WINDOW {
+        return recent.back().copied().unwrap_or_default();
     }
+    let mut sorted: Vec<Duration> = recent.iter().copied().collect();
+    sorted.sort_unstable();
+    let kept = &sorted[SMOOTHING_TRIM..(sorted.len() - SMOOTHING_TRIM)];
+    kept.iter().sum::<Duration>() / kept.len() as u32
 }
 
 /// Smooth out delta time before doing anything with it. This is unoptimized, but that might not matter.
@@ -58,18 +116,73 @@ fn time_smoothing_system(
     mut recent_time: ResMut<RecentFrameTimes>,
     mut smoothed_time: ResMut<SmoothedTime>,
 ) {
-    let window: usize = 11;
-    let delta = time.delta();
-    recent_time.buffer.push_back(delta);
-    if recent_time.buffer.len() > window {
+    recent_time.buffer.push_back(time.delta());
+    if recent_time.buffer.len() > SMOOTHING_WINDOW {
         recent_time.buffer.pop_front();
-        let mut sorted: Vec<Duration> = recent_time.buffer.clone().into();
-        sorted.sort_unstable();
-        let sum = &sorted[2..(window - 2)]
-            .iter()
-            .fold(Duration::new(0, 0), |acc, x| acc + *x);
-        smoothed_time.delta = *sum / (window as u32 - 4);
-    } else {
-        smoothed_time.delta = delta;
+    }
+    smoothed_time.delta = smoothed_delta(&recent_time.buffer);
+}
+
+/// Count the tick that just finished. Goes in Last instead of First, so
+/// everybody in a given frame agrees on its number, starting from 0.
+fn increment_tick_system(time: Res<Time<Virtual>>, mut tick: ResMut<GameTick>) {
+    if time.effective_speed() > 0.0 {
+        tick.0 += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn smoothing_ignores_hitches() {
+        // Steady 16ms frames, with a couple of hitches and a couple of
+        // suspiciously quick frames thrown in.
+        let mut frames: VecDeque<Duration> = [16, 16, 100, 16, 1, 16, 16, 250, 16, 2, 16]
+            .into_iter()
+            .map(ms)
+            .collect();
+        assert_eq!(smoothed_delta(&frames), ms(16));
+        // One more hitch is one too many to throw out.
+        frames.pop_front();
+        frames.push_back(ms(86));
+        assert_eq!(smoothed_delta(&frames), ms(26));
+    }
+
+    #[test]
+    fn smoothing_passes_through_until_the_window_fills() {
+        let frames: VecDeque<Duration> = [16, 33, 8].into_iter().map(ms).collect();
+        assert_eq!(smoothed_delta(&frames), ms(8));
+        assert_eq!(smoothed_delta(&VecDeque::new()), Duration::ZERO);
+    }
+
+    #[test]
+    fn ticks_count_up_from_zero_and_stop_when_time_does() {
+        #[derive(Resource, Default)]
+        struct Seen(Vec<u64>);
+        fn look(tick: Res<GameTick>, mut seen: ResMut<Seen>) {
+            seen.0.push(tick.0);
+        }
+
+        let mut app = App::new();
+        app.add_plugins((bevy::time::TimePlugin, GameTickPlugin))
+            .init_resource::<Seen>()
+            .add_systems(Update, look);
+        app.update();
+        app.update();
+        app.update();
+        assert_eq!(app.world().resource::<Seen>().0, vec![0, 1, 2]);
+
+        app.world_mut()
+            .resource_mut::<Time<Virtual>>()
+            .set_relative_speed(0.0);
+        app.update();
+        app.update();
+        assert_eq!(app.world().resource::<GameTick>().0, 3);
     }
 }
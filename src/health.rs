@@ -0,0 +1,175 @@
+//! Hit points, and the events for losing them.
+
+use crate::behaviors::Invincible;
+use bevy::prelude::*;
+
+/// Hit points. Signed, so overkill doesn't have to be special-cased anywhere
+/// except the display.
+#[derive(Component, Reflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Health {
+    pub fn new(max: i32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0
+    }
+
+    /// Subtract some damage, bottoming out at zero. Negative damage is
+    /// ignored; healing should be its own thing.
+    pub fn apply_damage(&mut self, amount: i32) {
+        if amount > 0 {
+            self.current = (self.current - amount).max(0);
+        }
+    }
+}
+
+/// Resource: how many hit points everybody starts with.
+#[derive(Resource, Reflect, Clone, Debug, PartialEq)]
+#[reflect(Resource)]
+pub struct ActorStats {
+    pub player_max_health: i32,
+    pub enemy_max_health: i32,
+}
+
+impl Default for ActorStats {
+    fn default() -> Self {
+        Self {
+            player_max_health: 6,
+            enemy_max_health: 3,
+        }
+    }
+}
+
+/// Marker component for entities whose health has run out. They might stick
+/// around for a bit (death animation, etc.), but they're done taking damage.
+#[derive(Component)]
+pub struct Dying;
+
+pub struct HealthEventsPlugin;
+impl Plugin for HealthEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TakeDamage>().add_event::<EntityDied>();
+    }
+}
+
+/// Event: ow
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TakeDamage {
+    pub target: Entity,
+    pub amount: i32,
+    /// Who dunnit, if anyone.
+    pub source: Option<Entity>,
+}
+
+/// Event: OWWWWW
+#[derive(Event, Debug)]
+pub struct EntityDied(pub Entity);
+
+// ------- Systems -------
+
+/// Apply incoming damage to health, unless the target is invincible or
+/// already on its way out.
+pub fn health_drain_system(
+    mut damage_events: EventReader<TakeDamage>,
+    mut health_q: Query<&mut Health, (Without<Invincible>, Without<Dying>)>,
+) {
+    for damage in damage_events.read() {
+        if damage.amount == 0 {
+            // Don't trip change detection for nothing.
+            continue;
+        }
+        if let Ok(mut health) = health_q.get_mut(damage.target) {
+            health.apply_damage(damage.amount);
+        }
+    }
+}
+
+/// Notice when something's health has run out, and mark it as dying.
+pub fn death_system(
+    health_q: Query<(Entity, &Health), (Changed<Health>, Without<Dying>)>,
+    mut died_events: EventWriter<EntityDied>,
+    mut commands: Commands,
+) {
+    for (entity, health) in health_q.iter() {
+        if health.is_dead() {
+            died_events.send(EntityDied(entity));
+            commands.entity(entity).insert(Dying);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn world_with_events() -> World {
+        let mut world = World::new();
+        world.init_resource::<Events<TakeDamage>>();
+        world.init_resource::<Events<EntityDied>>();
+        world
+    }
+
+    fn hit(world: &mut World, target: Entity, amount: i32) {
+        world.send_event(TakeDamage {
+            target,
+            amount,
+            source: None,
+        });
+        world.run_system_once(health_drain_system);
+        world.run_system_once(death_system);
+        // Fresh systems read from the start of the buffer, so don't leave old hits lying around.
+        world.resource_mut::<Events<TakeDamage>>().clear();
+    }
+
+    #[test]
+    fn plain_damage() {
+        let mut world = world_with_events();
+        let guy = world.spawn(Health::new(5)).id();
+        hit(&mut world, guy, 2);
+        assert_eq!(world.get::<Health>(guy).unwrap().current, 3);
+        assert!(world.get::<Dying>(guy).is_none());
+    }
+
+    #[test]
+    fn overkill() {
+        let mut world = world_with_events();
+        let guy = world.spawn(Health::new(5)).id();
+        hit(&mut world, guy, 50);
+        assert_eq!(world.get::<Health>(guy).unwrap().current, 0);
+        assert!(world.get::<Dying>(guy).is_some());
+        assert_eq!(world.resource::<Events<EntityDied>>().len(), 1);
+        // Beating a dead horse doesn't kill it twice.
+        hit(&mut world, guy, 50);
+        assert_eq!(world.resource::<Events<EntityDied>>().len(), 1);
+    }
+
+    #[test]
+    fn zero_damage_is_a_noop() {
+        let mut world = world_with_events();
+        let guy = world.spawn(Health::new(5)).id();
+        hit(&mut world, guy, 0);
+        assert_eq!(world.get::<Health>(guy).unwrap().current, 5);
+        // ...and so is negative damage.
+        hit(&mut world, guy, -3);
+        assert_eq!(world.get::<Health>(guy).unwrap().current, 5);
+    }
+
+    #[test]
+    fn invincible_skips_damage() {
+        let mut world = world_with_events();
+        let guy = world.spawn((Health::new(5), Invincible)).id();
+        hit(&mut world, guy, 3);
+        assert_eq!(world.get::<Health>(guy).unwrap().current, 5);
+        // I-frames over
+        world.entity_mut(guy).remove::<Invincible>();
+        hit(&mut world, guy, 3);
+        assert_eq!(world.get::<Health>(guy).unwrap().current, 2);
+    }
+}
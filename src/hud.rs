@@ -0,0 +1,413 @@
+//! Heads-up display stuff: health bars, for now. It all lives in screen
+//! space and gets drawn by its own camera, so it doesn't care about zoom,
+//! screenshake, or anything else the gameplay camera's up to. Also floating
+//! text (damage numbers, item names), which is the exception: it lives out
+//! in the world.
+
+use crate::{
+    behaviors::Invincible,
+    health::{Dying, Health, TakeDamage},
+    input::PlayerIndex,
+    items::ItemCollected,
+    phys_space::PhysTransform,
+    render::DEPTH_OVERLAY,
+    toolbox::countup_timer::CountupTimer,
+    ui_utils::world_to_screen,
+};
+use bevy::{prelude::*, render::view::RenderLayers, ui::UiSystem, window::PrimaryWindow};
+
+pub struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_hud_camera)
+            .add_systems(
+                Update,
+                (
+                    spawn_health_bars_system,
+                    health_bar_flash_system,
+                    health_bar_system,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                PostUpdate,
+                floating_health_bar_system.before(UiSystem::Layout),
+            )
+            .add_systems(
+                Update,
+                (
+                    spawn_damage_numbers_system,
+                    spawn_item_names_system,
+                    update_floating_text_system,
+                ),
+            );
+    }
+}
+
+/// Marker for the screen-space camera that draws the HUD.
+#[derive(Component)]
+pub struct HudCamera;
+
+/// Draws after the gameplay camera.
+const HUD_CAMERA_ORDER: isize = 1;
+/// Nothing else lives on this layer, so the HUD camera doesn't draw the world
+/// a second time.
+const HUD_RENDER_LAYER: usize = 1;
+
+const SEGMENT_WIDTH: f32 = 6.0;
+const SEGMENT_HEIGHT: f32 = 8.0;
+const SEGMENT_GAP: f32 = 1.0;
+const MAX_SEGMENTS: i32 = 12;
+const FILLED_COLOR: Color = Color::srgb(0.85, 0.15, 0.2);
+const EMPTY_COLOR: Color = Color::srgb(0.25, 0.1, 0.12);
+const FLASH_COLOR: Color = Color::WHITE;
+const BORDER_COLOR: Color = Color::BLACK;
+const FLASH_SECS: f32 = 0.5;
+/// How long each on/off blink lasts during a flash.
+const BLINK_SECS: f32 = 0.08;
+/// Player bars stack down from the top left corner.
+const PLAYER_BAR_CORNER: Vec2 = Vec2::new(8.0, 8.0);
+const PLAYER_BAR_SPACING: f32 = 14.0;
+/// How far above an enemy's origin its bar floats, in world pixels.
+const FLOATING_BAR_HEIGHT: f32 = 18.0;
+
+/// A segmented HP bar showing some entity's Health. The bar's children are
+/// the segments.
+#[derive(Component)]
+pub struct HealthBar {
+    pub entity: Entity,
+    pub segments: u8,
+    flash: Timer,
+}
+
+/// Marker for health bars that hover over their entity instead of sitting
+/// in a corner.
+#[derive(Component)]
+pub struct FloatingHealthBar;
+
+#[derive(Component)]
+pub struct HealthBarSegment(u8);
+
+pub fn setup_hud_camera(mut commands: Commands) {
+    commands.spawn((
+        HudCamera,
+        Camera2dBundle {
+            camera: Camera {
+                order: HUD_CAMERA_ORDER,
+                clear_color: ClearColorConfig::None,
+                ..default()
+            },
+            ..default()
+        },
+        RenderLayers::layer(HUD_RENDER_LAYER),
+    ));
+}
+
+fn bar_width(segments: u8) -> f32 {
+    segments as f32 * (SEGMENT_WIDTH + SEGMENT_GAP) + SEGMENT_GAP
+}
+
+/// Give everything that just got Health a bar to go with it. Players get one
+/// in the corner, everyone else gets a floating one.
+pub fn spawn_health_bars_system(
+    new_health_q: Query<(Entity, &Health, Option<&PlayerIndex>), Added<Health>>,
+    hud_camera_q: Query<Entity, With<HudCamera>>,
+    mut commands: Commands,
+) {
+    let Ok(hud_camera) = hud_camera_q.get_single() else {
+        return;
+    };
+    for (entity, health, player_index) in new_health_q.iter() {
+        let segments = health.max.clamp(1, MAX_SEGMENTS) as u8;
+        let position = match player_index {
+            Some(index) => PLAYER_BAR_CORNER + Vec2::Y * PLAYER_BAR_SPACING * index.0 as f32,
+            // Floating bars get put in place before they're ever drawn.
+            None => Vec2::ZERO,
+        };
+        let mut bar = commands.spawn((
+            HealthBar {
+                entity,
+                segments,
+                flash: Timer::from_seconds(0.0, TimerMode::Once),
+            },
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(position.x),
+                    top: Val::Px(position.y),
+                    padding: UiRect::all(Val::Px(SEGMENT_GAP)),
+                    column_gap: Val::Px(SEGMENT_GAP),
+                    ..default()
+                },
+                background_color: BORDER_COLOR.into(),
+                ..default()
+            },
+            TargetCamera(hud_camera),
+            Name::new("Health bar"),
+        ));
+        if player_index.is_none() {
+            bar.insert((FloatingHealthBar, Visibility::Hidden));
+        }
+        bar.with_children(|parent| {
+            for i in 0..segments {
+                parent.spawn((
+                    HealthBarSegment(i),
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Px(SEGMENT_WIDTH),
+                            height: Val::Px(SEGMENT_HEIGHT),
+                            ..default()
+                        },
+                        background_color: FILLED_COLOR.into(),
+                        ..default()
+                    },
+                ));
+            }
+        });
+    }
+}
+
+/// Start a bar flashing when its entity takes a hit.
+pub fn health_bar_flash_system(
+    mut damage_events: EventReader<TakeDamage>,
+    mut bar_q: Query<&mut HealthBar>,
+) {
+    for damage in damage_events.read() {
+        for mut bar in bar_q.iter_mut().filter(|b| b.entity == damage.target) {
+            bar.flash = Timer::from_seconds(FLASH_SECS, TimerMode::Once);
+        }
+    }
+}
+
+/// Color in each bar's segments to match its entity's health, and clean up
+/// bars whose entity is gone.
+pub fn health_bar_system(
+    time: Res<Time>,
+    mut bar_q: Query<(Entity, &mut HealthBar, &Children)>,
+    health_q: Query<&Health>,
+    mut segment_q: Query<(&HealthBarSegment, &mut BackgroundColor)>,
+    mut commands: Commands,
+) {
+    for (bar_entity, mut bar, children) in bar_q.iter_mut() {
+        let Ok(health) = health_q.get(bar.entity) else {
+            commands.entity(bar_entity).despawn_recursive();
+            continue;
+        };
+        bar.flash.tick(time.delta());
+        let blink_on = !bar.flash.finished() && (bar.flash.elapsed_secs() / BLINK_SECS) % 2.0 < 1.0;
+
+        // Round up, so you're not showing an empty bar with HP left.
+        let segments = bar.segments as i32;
+        let filled = (health.current.max(0) * segments + health.max - 1) / health.max.max(1);
+
+        for &child in children.iter() {
+            if let Ok((segment, mut color)) = segment_q.get_mut(child) {
+                let new_color = match ((segment.0 as i32) < filled, blink_on) {
+                    (true, true) => FLASH_COLOR,
+                    (true, false) => FILLED_COLOR,
+                    (false, _) => EMPTY_COLOR,
+                };
+                if color.0 != new_color {
+                    color.0 = new_color;
+                }
+            }
+        }
+    }
+}
+
+/// Keep floating bars hovering over their entities. Goes by the gameplay
+/// camera's Transform and the entity's PhysTransform, since both are current
+/// by PostUpdate but GlobalTransforms won't be until after UI layout.
+pub fn floating_health_bar_system(
+    camera_q: Query<
+        (&OrthographicProjection, &Transform),
+        (With<Camera>, With<PhysTransform>, Without<HudCamera>),
+    >,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    target_q: Query<&PhysTransform>,
+    mut bar_q: Query<(&HealthBar, &mut Style, &mut Visibility), With<FloatingHealthBar>>,
+) {
+    let (Ok((projection, camera_transform)), Ok(window)) =
+        (camera_q.get_single(), window_q.get_single())
+    else {
+        return;
+    };
+    let camera_global = GlobalTransform::from(*camera_transform);
+    for (bar, mut style, mut visibility) in bar_q.iter_mut() {
+        let screen_pos = target_q.get(bar.entity).ok().and_then(|t| {
+            // Sprites get drawn with their height added to Y, so match that.
+            let world_pos =
+                t.translation.truncate() + Vec2::Y * (t.translation.z + FLOATING_BAR_HEIGHT);
+            world_to_screen(world_pos, projection, &camera_global, window)
+        });
+        match screen_pos {
+            Some(pos) => {
+                style.left = Val::Px(pos.x - bar_width(bar.segments) / 2.0);
+                style.top = Val::Px(pos.y);
+                *visibility = Visibility::Inherited;
+            },
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+// -- FLOATING TEXT --
+
+const FLOATING_TEXT_FONT: &str = "fonts/m5x7.ttf";
+/// The font's native size, so it stays crisp.
+const FLOATING_TEXT_FONT_SIZE: f32 = 16.0;
+const FLOATING_TEXT_SECS: f32 = 0.8;
+/// Starting rise speed, in world pixels per second.
+const FLOATING_TEXT_RISE: f32 = 30.0;
+/// How fast the rise slows down, in world pixels per second per second.
+const FLOATING_TEXT_DRAG: f32 = 30.0;
+/// Start the text up around the target's head instead of at its feet.
+const FLOATING_TEXT_OFFSET: f32 = 12.0;
+const DAMAGE_NUMBER_COLOR: Color = Color::srgb(1.0, 0.95, 0.6);
+const ITEM_NAME_COLOR: Color = Color::srgb(0.6, 0.95, 1.0);
+
+/// Text that pops up over somebody, then drifts up and fades out.
+#[derive(Component)]
+pub struct FloatingText {
+    pub lifetime: CountupTimer,
+    pub velocity: Vec2,
+}
+
+/// Spawn some floating text over `target`'s head.
+fn spawn_floating_text(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    target: &PhysTransform,
+    label: String,
+    color: Color,
+    name: &'static str,
+) {
+    // Same Y-plus-height trick the sprites get, done by hand since text
+    // doesn't go through the flattening pass.
+    let loc =
+        target.translation.truncate() + Vec2::Y * (target.translation.z + FLOATING_TEXT_OFFSET);
+    commands.spawn((
+        FloatingText {
+            lifetime: CountupTimer::from_seconds(FLOATING_TEXT_SECS),
+            velocity: Vec2::Y * FLOATING_TEXT_RISE,
+        },
+        Text2dBundle {
+            text: Text::from_section(
+                label,
+                TextStyle {
+                    font: asset_server.load(FLOATING_TEXT_FONT),
+                    font_size: FLOATING_TEXT_FONT_SIZE,
+                    color,
+                },
+            ),
+            transform: Transform::from_translation(loc.extend(DEPTH_OVERLAY)),
+            ..default()
+        },
+        Name::new(name),
+    ));
+}
+
+/// Pop up a number for every hit that's actually going to land.
+pub fn spawn_damage_numbers_system(
+    mut damage_events: EventReader<TakeDamage>,
+    target_q: Query<&PhysTransform, (Without<Invincible>, Without<Dying>)>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for damage in damage_events.read() {
+        if damage.amount <= 0 {
+            continue;
+        }
+        let Ok(target) = target_q.get(damage.target) else {
+            continue;
+        };
+        spawn_floating_text(
+            &mut commands,
+            &asset_server,
+            target,
+            damage.amount.to_string(),
+            DAMAGE_NUMBER_COLOR,
+            "Damage number",
+        );
+    }
+}
+
+/// Pop up the name of whatever somebody just picked up.
+pub fn spawn_item_names_system(
+    mut collected_events: EventReader<ItemCollected>,
+    collector_q: Query<&PhysTransform>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for collected in collected_events.read() {
+        let Ok(collector) = collector_q.get(collected.collector) else {
+            continue;
+        };
+        spawn_floating_text(
+            &mut commands,
+            &asset_server,
+            collector,
+            collected.kind.display_name().to_string(),
+            ITEM_NAME_COLOR,
+            "Item name",
+        );
+    }
+}
+
+/// Float text upward and fade it out, then get rid of it.
+pub fn update_floating_text_system(
+    time: Res<Time>,
+    mut text_q: Query<(Entity, &mut FloatingText, &mut Transform, &mut Text)>,
+    mut commands: Commands,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut floating, mut transform, mut text) in text_q.iter_mut() {
+        if floating.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        transform.translation += (floating.velocity * delta).extend(0.0);
+        floating.velocity.y = (floating.velocity.y - FLOATING_TEXT_DRAG * delta).max(0.0);
+        let alpha = floating.lifetime.percent_left();
+        for section in text.sections.iter_mut() {
+            section.style.color.set_alpha(alpha);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn segment_colors(world: &World, bar: Entity) -> Vec<Color> {
+        let children: Vec<Entity> = world.get::<Children>(bar).unwrap().to_vec();
+        children
+            .iter()
+            .map(|c| world.get::<BackgroundColor>(*c).unwrap().0)
+            .collect()
+    }
+
+    #[test]
+    fn segments_track_health_and_bars_clean_up() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<Events<TakeDamage>>();
+        let hud_camera = world.spawn(HudCamera).id();
+        let guy = world
+            .spawn((Health { current: 3, max: 6 }, PlayerIndex::P1))
+            .id();
+        world.run_system_once(spawn_health_bars_system);
+        world.run_system_once(health_bar_system);
+
+        let (bar, target_camera) = world.query::<(Entity, &TargetCamera)>().single(&world);
+        assert_eq!(target_camera.0, hud_camera);
+        let colors = segment_colors(&world, bar);
+        assert_eq!(colors.len(), 6);
+        assert_eq!(colors.iter().filter(|c| **c == FILLED_COLOR).count(), 3);
+
+        world.despawn(guy);
+        world.run_system_once(health_bar_system);
+        assert!(world.get_entity(bar).is_none());
+    }
+}
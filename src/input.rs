@@ -1,59 +1,203 @@
+use crate::{
+    debug_settings::NumbersSettings,
+    key_bindings::{KeyboardBindings, KeyboardConfig},
+    pause::PauseMenu,
+    toolbox::deadband,
+};
 use bevy::{
     input::gamepad::{GamepadButtonChangedEvent, GamepadConnection, GamepadConnectionEvent},
     prelude::*,
 };
 
-/// Resource for stashing the current frame's inputs. Expect this'll expand as I
-/// add more input intent types. Also, might just switch to leafwing input or
-/// something, which would be much smarter! But in the meantime, at least it's
-/// centralized.
+/// How many frames a button press sticks around waiting for the player to be
+/// able to act on it, by default.
+pub const ACTION_BUFFER_FRAMES: u8 = 6;
+
+/// How many players can play at once, i.e. how many input slots there are.
+pub const MAX_PLAYERS: usize = 2;
+
+/// Which input slot a player entity listens to. 0 is player 1.
+#[derive(Component, Reflect, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlayerIndex(pub u8);
+
+impl PlayerIndex {
+    pub const P1: Self = Self(0);
+    pub const P2: Self = Self(1);
+}
+
+/// Resource for stashing the current frame's inputs, one slot per player.
+/// Expect this'll expand as I add more input intent types. Also, might just
+/// switch to leafwing input or something, which would be much smarter! But in
+/// the meantime, at least it's centralized.
+#[derive(Resource, Default)]
+pub struct CurrentInputs(pub [InputSet; MAX_PLAYERS]);
+
+/// Resource: while true, nobody's inputs count (e.g. during level
+/// transitions). accept_input_system just reports everyone as idle. Same
+/// goes while the PauseMenu's up.
 #[derive(Resource, Default)]
-pub struct CurrentInputs {
+pub struct GamePaused(pub bool);
+
+impl CurrentInputs {
+    pub fn player(&self, index: PlayerIndex) -> &InputSet {
+        &self.0[index.0 as usize]
+    }
+
+    pub fn player_mut(&mut self, index: PlayerIndex) -> &mut InputSet {
+        &mut self.0[index.0 as usize]
+    }
+}
+
+/// One player's input intents for the current frame.
+#[derive(Default)]
+pub struct InputSet {
     pub movement: Vec2,
     pub actioning: bool,
     pub attacking: bool,
+    /// Whether the attack button is down right now, as opposed to just pressed.
+    pub attacking_held: bool,
+    /// The most recent action press, and how many more frames it's good for.
+    /// Lets you mash roll a hair before an attack ends and still get a roll.
+    pub action_buffer: Option<(ActionKind, u8)>,
+}
+
+/// The kinds of button-press actions that can wait in the buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionKind {
+    Roll,
+    Attack,
+}
+
+impl InputSet {
+    /// Remember a press for a few frames. Newer presses replace older ones.
+    pub fn buffer_action(&mut self, kind: ActionKind, frames: u8) {
+        self.action_buffer = (frames > 0).then_some((kind, frames));
+    }
+
+    /// Count down a frame on the buffered press, and drop it once it's stale.
+    pub fn age_action_buffer(&mut self) {
+        if let Some((_, ref mut frames)) = self.action_buffer {
+            *frames = frames.saturating_sub(1);
+            if *frames == 0 {
+                self.action_buffer = None;
+            }
+        }
+    }
+
+    /// Per-frame buffer housekeeping, once the raw presses are in: last
+    /// frame's leftover press gets a little older, and fresh presses replace it.
+    pub fn update_action_buffer(&mut self, frames: u8) {
+        self.age_action_buffer();
+        if self.actioning {
+            self.buffer_action(ActionKind::Roll, frames);
+        }
+        if self.attacking {
+            self.buffer_action(ActionKind::Attack, frames);
+        }
+    }
+
+    /// Peek at the buffered action, if any.
+    pub fn buffered_action(&self) -> Option<ActionKind> {
+        self.action_buffer.map(|(kind, _)| kind)
+    }
+
+    /// Use up the buffered action.
+    pub fn take_buffered_action(&mut self) -> Option<ActionKind> {
+        self.action_buffer.take().map(|(kind, _)| kind)
+    }
+}
+
+/// Resource for analog stick dead zone settings, as fractions of full tilt.
+/// Anything under `inner` counts as centered (so stick drift doesn't walk you
+/// around), anything over `outer` counts as all the way, and the stuff in
+/// between gets stretched to cover the whole 0..1 range.
+#[derive(Resource, Reflect, Clone, Copy, Debug)]
+#[reflect(Resource)]
+pub struct DeadZone {
+    pub inner: f32,
+    pub outer: f32,
+}
+
+impl Default for DeadZone {
+    fn default() -> Self {
+        Self {
+            inner: 0.15,
+            outer: 0.95,
+        }
+    }
 }
 
-/// Resource for storing the active gamepad
-#[derive(Resource)]
-pub struct ActiveGamepad(Gamepad);
+impl DeadZone {
+    /// Apply a radial dead zone to a raw stick vector. Keeps the direction,
+    /// remaps the magnitude.
+    pub fn apply(&self, raw: Vec2) -> Vec2 {
+        let live = deadband(raw, self.inner);
+        let magnitude = live.length();
+        if magnitude == 0.0 {
+            return Vec2::ZERO;
+        }
+        let scaled = if magnitude >= self.outer {
+            1.0
+        } else {
+            (magnitude - self.inner) / (self.outer - self.inner)
+        };
+        live / magnitude * scaled
+    }
+}
+
+/// Resource for storing which gamepad drives which player slot.
+#[derive(Resource, Default)]
+pub struct ActiveGamepads(pub [Option<Gamepad>; MAX_PLAYERS]);
+
+impl ActiveGamepads {
+    fn slot_of(&self, gamepad: Gamepad) -> Option<usize> {
+        self.0.iter().position(|g| *g == Some(gamepad))
+    }
+
+    fn first_empty_slot(&self) -> Option<usize> {
+        self.0.iter().position(|g| g.is_none())
+    }
+}
 
 // Input time!
 
 /// helper function: forward the axes resource (and a gamepad id) to it, get a vec back.
 /// Note: `gilrs`, Bevy's gamepad library, only supports Xinput on windows. boo.
-pub fn get_gamepad_movement_vector(gamepad: Gamepad, axes: Res<Axis<GamepadAxis>>) -> Option<Vec2> {
+pub fn get_gamepad_movement_vector(
+    gamepad: Gamepad,
+    axes: &Axis<GamepadAxis>,
+    dead_zone: &DeadZone,
+) -> Option<Vec2> {
     let x_axis = GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX);
     let y_axis = GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY);
     let x = axes.get(x_axis)?;
     let y = axes.get(y_axis)?;
-    Some(Vec2::new(x, y))
+    Some(dead_zone.apply(Vec2::new(x, y)))
 }
 
-/// helper function: forward keycodes to it, get a vec back.
-pub fn get_kb_movement_vector(keys: &Res<ButtonInput<KeyCode>>) -> Vec2 {
+/// helper function: forward keycodes (and bindings) to it, get a vec back.
+pub fn get_kb_movement_vector(keys: &ButtonInput<KeyCode>, bindings: &KeyboardBindings) -> Vec2 {
     let mut x = 0f32;
     let mut y = 0f32;
-    if keys.pressed(KeyCode::ArrowLeft) {
+    if keys.pressed(bindings.move_left) {
         x -= 1.0;
     }
-    if keys.pressed(KeyCode::ArrowRight) {
+    if keys.pressed(bindings.move_right) {
         x += 1.0;
     }
-    if keys.pressed(KeyCode::ArrowUp) {
+    if keys.pressed(bindings.move_up) {
         y += 1.0; // bc, opposite of other engines so far
     }
-    if keys.pressed(KeyCode::ArrowDown) {
+    if keys.pressed(bindings.move_down) {
         y -= 1.0;
     }
     Vec2::new(x, y).normalize_or_zero()
 }
 
-/// System for noticing when gamepads are added/removed and marking which
-/// gamepad is active for the player.
+/// System for noticing when gamepads are added/removed and deciding which
+/// gamepad drives which player.
 pub fn connect_gamepads_system(
-    mut commands: Commands,
-    active_gamepad: Option<Res<ActiveGamepad>>,
+    mut active_gamepads: ResMut<ActiveGamepads>,
     mut connection_events: EventReader<GamepadConnectionEvent>,
     mut button_events: EventReader<GamepadButtonChangedEvent>,
     // ^^ eventreader params have to be mutable because reading events immutably
@@ -67,21 +211,19 @@ pub fn connect_gamepads_system(
         match connection {
             GamepadConnection::Connected(_) => {
                 info!("pad in: {:?}", gamepad);
-                // let's see, I de-focused the cookbook tab, so what do *I* want to have happen?
-                // First pad in gets it, but if another pad hits start, it'll take over. Nice.
-                if active_gamepad.is_none() {
-                    commands.insert_resource(ActiveGamepad(*gamepad));
+                // First pad in is P1, second is P2. Any more have to wait
+                // for a seat to open up (or hit start).
+                if active_gamepads.slot_of(*gamepad).is_none() {
+                    if let Some(slot) = active_gamepads.first_empty_slot() {
+                        active_gamepads.0[slot] = Some(*gamepad);
+                    }
                 }
             },
             GamepadConnection::Disconnected => {
                 info!("pad out: {:?}", gamepad);
                 // byeeee
-                // ok, I'm back to the example code, what's going on here:
-                if let Some(ActiveGamepad(old_id)) = active_gamepad.as_deref() {
-                    if old_id == gamepad {
-                        commands.remove_resource::<ActiveGamepad>();
-                        // haven't really had to turbofish before now. zoom zoom glub glub.
-                    }
+                if let Some(slot) = active_gamepads.slot_of(*gamepad) {
+                    active_gamepads.0[slot] = None;
                 }
             },
         }
@@ -95,15 +237,11 @@ pub fn connect_gamepads_system(
     {
         if *button_type == GamepadButtonType::Start && *value == 1.0 {
             info!("Pressed start: {:?}", gamepad);
-            // If there's an active gamepad...
-            if let Some(ActiveGamepad(old_id)) = active_gamepad.as_deref() {
-                // ...but it's not the one you just pressed start on...
-                if old_id != gamepad {
-                    // ...then let it take over.
-                    commands.insert_resource(ActiveGamepad(*gamepad));
-                    // per the cheatbook: "If you insert a resource of a
-                    // type that already exists, it will be overwritten."
-                }
+            // If it's not driving anyone yet, it takes the first open seat,
+            // or takes over P1 if everyone's already got a pad.
+            if active_gamepads.slot_of(*gamepad).is_none() {
+                let slot = active_gamepads.first_empty_slot().unwrap_or(0);
+                active_gamepads.0[slot] = Some(*gamepad);
             }
         }
     }
@@ -116,33 +254,93 @@ pub fn connect_gamepads_system(
 
 /// System for getting the current frame's input intents and stashing them in
 /// the CurrentInputs resource. Expects to run in the PreUpdate stage.
+#[allow(clippy::too_many_arguments)]
 pub fn accept_input_system(
-    active_gamepad: Option<Res<ActiveGamepad>>,
+    active_gamepads: Res<ActiveGamepads>,
     mut inputs: ResMut<CurrentInputs>,
     axes: Res<Axis<GamepadAxis>>,
     keys: Res<ButtonInput<KeyCode>>,
+    key_config: Res<KeyboardConfig>,
+    numbers: Res<NumbersSettings>,
+    dead_zone: Res<DeadZone>,
+    paused: Res<GamePaused>,
+    pause_menu: Res<PauseMenu>,
 ) {
-    // get movement intent
-    let mut gamepad_movement = None;
-    if let Some(ActiveGamepad(pad_id)) = active_gamepad.as_deref() {
-        gamepad_movement = get_gamepad_movement_vector(*pad_id, axes);
-    }
-    let movement = match gamepad_movement {
-        Some(mvmt) => {
-            if mvmt.length() > 0.0 {
-                mvmt
-            } else {
-                get_kb_movement_vector(&keys)
-            }
-        },
-        None => get_kb_movement_vector(&keys),
-    };
-    // ok cool
-    inputs.movement = movement;
-
-    // How about action button? Start w/ just keyboard to get basics working.
-    inputs.actioning = keys.just_pressed(KeyCode::Space);
-    // Uhhhhhh okay it is WAY past time to improve this gamepad / kb input fight
-    // situation, but, I'm in the middle of something rn, so,,,
-    inputs.attacking = keys.just_pressed(KeyCode::ShiftLeft);
+    if paused.0 || pause_menu.0 {
+        inputs.0 = Default::default();
+        return;
+    }
+    for (slot, inputs) in inputs.0.iter_mut().enumerate() {
+        let bindings = &key_config.players[slot];
+
+        // get movement intent
+        let gamepad_movement = active_gamepads.0[slot]
+            .and_then(|pad_id| get_gamepad_movement_vector(pad_id, &axes, &dead_zone));
+        let movement = match gamepad_movement {
+            Some(mvmt) => {
+                if mvmt.length() > 0.0 {
+                    mvmt
+                } else {
+                    get_kb_movement_vector(&keys, bindings)
+                }
+            },
+            None => get_kb_movement_vector(&keys, bindings),
+        };
+        // ok cool
+        inputs.movement = movement;
+
+        // How about action button? Start w/ just keyboard to get basics working.
+        inputs.actioning = keys.just_pressed(bindings.action);
+        // Uhhhhhh okay it is WAY past time to improve this gamepad / kb input fight
+        // situation, but, I'm in the middle of something rn, so,,,
+        inputs.attacking = keys.just_pressed(bindings.attack);
+        inputs.attacking_held = keys.pressed(bindings.attack);
+
+        inputs.update_action_buffer(numbers.input_buffer_frames);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_zone_remaps_magnitude() {
+        let dz = DeadZone::default();
+        // Drift gets squashed.
+        assert_eq!(dz.apply(Vec2::new(0.1, 0.05)), Vec2::ZERO);
+        // Nearly-all-the-way counts as all the way.
+        assert_eq!(dz.apply(Vec2::new(0.0, -0.97)), Vec2::NEG_Y);
+        // Halfway through the live range is half speed, same direction.
+        let mid = dz.apply(Vec2::new(0.55, 0.0));
+        assert!((mid - Vec2::new(0.5, 0.0)).length() < 0.0001);
+        let diag = dz.apply(Vec2::new(0.4, 0.4));
+        assert!((diag.normalize() - Vec2::new(0.4, 0.4).normalize()).length() < 0.0001);
+    }
+
+    #[test]
+    fn buffered_press_expires() {
+        let mut inputs = InputSet::default();
+        inputs.buffer_action(ActionKind::Roll, 2);
+        inputs.age_action_buffer();
+        assert_eq!(inputs.buffered_action(), Some(ActionKind::Roll));
+        inputs.age_action_buffer();
+        assert_eq!(inputs.buffered_action(), None);
+    }
+
+    #[test]
+    fn newer_press_wins_and_taking_consumes() {
+        let mut inputs = InputSet::default();
+        inputs.buffer_action(ActionKind::Roll, 6);
+        inputs.buffer_action(ActionKind::Attack, 6);
+        assert_eq!(inputs.take_buffered_action(), Some(ActionKind::Attack));
+        assert_eq!(inputs.take_buffered_action(), None);
+    }
+
+    #[test]
+    fn zero_frame_buffer_is_off() {
+        let mut inputs = InputSet::default();
+        inputs.buffer_action(ActionKind::Roll, 0);
+        assert_eq!(inputs.buffered_action(), None);
+    }
 }
@@ -0,0 +1,228 @@
+//! Recording and replaying inputs, for reproducing bugs. Run with
+//! `--record [path]` to save every frame's inputs to a file, and with
+//! `--replay [path]` to feed them back in instead of reading the keyboard
+//! and gamepads. Frame timing isn't recorded, so a replay only comes out
+//! identical if the frame rate cooperates.
+
+use crate::debug_settings::NumbersSettings;
+use crate::input::{CurrentInputs, GamePaused, InputSet, MAX_PLAYERS};
+use crate::pause::PauseMenu;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+pub const DEFAULT_RECORDING_PATH: &str = "./input_recording.bin";
+
+/// The raw parts of one player's inputs for one frame. (Not the action
+/// buffer, since that gets rebuilt from the presses.)
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+struct RecordedInputs {
+    movement: [f32; 2],
+    actioning: bool,
+    attacking: bool,
+    attacking_held: bool,
+}
+
+impl From<&InputSet> for RecordedInputs {
+    fn from(inputs: &InputSet) -> Self {
+        Self {
+            movement: inputs.movement.to_array(),
+            actioning: inputs.actioning,
+            attacking: inputs.attacking,
+            attacking_held: inputs.attacking_held,
+        }
+    }
+}
+
+type RecordedFrame = [RecordedInputs; MAX_PLAYERS];
+
+/// Resource: when present, every frame's inputs get appended to a file.
+#[derive(Resource)]
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+/// Resource: when present, inputs come from a recording instead of the
+/// keyboard and gamepads. Removes itself when it runs out of frames.
+#[derive(Resource)]
+pub struct InputReplayer {
+    frames: Vec<RecordedFrame>,
+    next: usize,
+}
+
+impl InputReplayer {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self {
+            frames: read_frames(BufReader::new(file))?,
+            next: 0,
+        })
+    }
+}
+
+/// Frames are written back to back, so read until we run out.
+fn read_frames(mut reader: impl Read) -> anyhow::Result<Vec<RecordedFrame>> {
+    let mut frames = Vec::new();
+    loop {
+        match bincode::deserialize_from(&mut reader) {
+            Ok(frame) => frames.push(frame),
+            Err(e) => match *e {
+                bincode::ErrorKind::Io(ref io)
+                    if io.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    break;
+                },
+                _ => return Err(e.into()),
+            },
+        }
+    }
+    Ok(frames)
+}
+
+fn write_frame(mut writer: impl Write, frame: &RecordedFrame) -> anyhow::Result<()> {
+    bincode::serialize_into(&mut writer, frame)?;
+    Ok(())
+}
+
+/// Grab the value after a command line flag, or a default if the flag's
+/// there without one. None if the flag's not there at all.
+pub fn path_arg(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != flag);
+    args.next()?;
+    let path = args
+        .next()
+        .filter(|arg| !arg.starts_with("--"))
+        .unwrap_or_else(|| DEFAULT_RECORDING_PATH.to_string());
+    Some(path)
+}
+
+// ------- Systems -------
+
+/// Save this frame's inputs. Runs right after accept_input_system. Paused
+/// frames are left out, same as on replay.
+pub fn record_inputs_system(
+    inputs: Res<CurrentInputs>,
+    mut recorder: ResMut<InputRecorder>,
+    paused: Res<GamePaused>,
+    pause_menu: Res<PauseMenu>,
+    mut commands: Commands,
+) {
+    if paused.0 || pause_menu.0 {
+        return;
+    }
+    let frame: RecordedFrame = std::array::from_fn(|i| RecordedInputs::from(&inputs.0[i]));
+    // Flush every frame, so a crash (the whole point of recording) doesn't
+    // eat the tail end.
+    let writer = &mut recorder.writer;
+    let result = write_frame(&mut *writer, &frame).and_then(|_| Ok(writer.flush()?));
+    if let Err(e) = result {
+        error!("Couldn't record inputs, giving up: {}", e);
+        commands.remove_resource::<InputRecorder>();
+    }
+}
+
+/// Stand in for accept_input_system while a replay's running. Like the real
+/// thing, it goes quiet while the game's paused, and holds its place in the
+/// recording until it's unpaused.
+pub fn replay_inputs_system(
+    mut inputs: ResMut<CurrentInputs>,
+    mut replayer: ResMut<InputReplayer>,
+    numbers: Res<NumbersSettings>,
+    paused: Res<GamePaused>,
+    pause_menu: Res<PauseMenu>,
+    mut commands: Commands,
+) {
+    if paused.0 || pause_menu.0 {
+        inputs.0 = Default::default();
+        return;
+    }
+    let Some(frame) = replayer.frames.get(replayer.next).copied() else {
+        info!(
+            "Replay finished after {} frames; back to live inputs",
+            replayer.next
+        );
+        commands.remove_resource::<InputReplayer>();
+        return;
+    };
+    replayer.next += 1;
+    for (inputs, recorded) in inputs.0.iter_mut().zip(frame) {
+        inputs.movement = Vec2::from_array(recorded.movement);
+        inputs.actioning = recorded.actioning;
+        inputs.attacking = recorded.attacking;
+        inputs.attacking_held = recorded.attacking_held;
+        inputs.update_action_buffer(numbers.input_buffer_frames);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let mut p1 = RecordedInputs {
+            movement: [0.5, -1.0],
+            attacking_held: true,
+            ..Default::default()
+        };
+        let p2 = RecordedInputs {
+            actioning: true,
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        write_frame(&mut buf, &[p1, p2]).unwrap();
+        p1.attacking = true;
+        write_frame(&mut buf, &[p1, p2]).unwrap();
+
+        let frames = read_frames(buf.as_slice()).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1], [p1, p2]);
+        assert!(!frames[0][0].attacking);
+    }
+
+    #[test]
+    fn replays_hold_their_place_while_paused() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = World::new();
+        world.init_resource::<CurrentInputs>();
+        world.init_resource::<NumbersSettings>();
+        world.init_resource::<GamePaused>();
+        world.init_resource::<PauseMenu>();
+        let pressing = RecordedInputs {
+            movement: [1.0, 0.0],
+            ..Default::default()
+        };
+        world.insert_resource(InputReplayer {
+            frames: vec![[pressing; MAX_PLAYERS]; 2],
+            next: 0,
+        });
+        let movement = |world: &World| world.resource::<CurrentInputs>().0[0].movement;
+
+        world.run_system_once(replay_inputs_system);
+        assert_eq!(movement(&world), Vec2::X);
+        world.resource_mut::<PauseMenu>().0 = true;
+        world.run_system_once(replay_inputs_system);
+        assert_eq!(movement(&world), Vec2::ZERO);
+        world.resource_mut::<PauseMenu>().0 = false;
+        world.resource_mut::<GamePaused>().0 = true;
+        world.run_system_once(replay_inputs_system);
+        assert_eq!(world.resource::<InputReplayer>().next, 1);
+
+        world.resource_mut::<GamePaused>().0 = false;
+        world.run_system_once(replay_inputs_system);
+        assert_eq!(movement(&world), Vec2::X);
+        assert_eq!(world.resource::<InputReplayer>().next, 2);
+    }
+}
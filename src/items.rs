@@ -0,0 +1,384 @@
+//! Stuff you can pick up and carry around. Items sit on the ground as
+//! Pickups; walk over one and it goes into the first free slot in your
+//! Inventory. Nothing *uses* items yet, but ItemCollected goes out for
+//! anybody who wants to know (the HUD pops up the item's name). Levels place
+//! pickups with LDtk "Pickup" entities.
+
+use crate::{
+    assets_setup::SoundEffects,
+    collision::{rect_reach, Walkbox},
+    entity_states::player_state_read_inputs,
+    input::{ActionKind, CurrentInputs, PlayerIndex},
+    phys_space::PhysTransform,
+    render::TopDownMatter,
+    sounds::{play_sfx, SfxConfig, SfxSpawners, SoundCooldown},
+    space_lookup::{RstarAccess, RstarPlugin},
+    walls::{ldtk_entity_phys_location, rect_around_pivot},
+    Player,
+};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use bevy_ecs_ldtk::prelude::*;
+
+/// How many things a player can carry.
+pub const INVENTORY_SLOTS: usize = 8;
+
+const PICKUP_COLOR: Color = Color::srgb(0.95, 0.75, 0.3);
+
+pub struct ItemsPlugin;
+impl Plugin for ItemsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RstarPlugin::<Pickup>::new())
+            .add_event::<ItemCollected>()
+            .register_ldtk_entity::<PickupBundle>("Pickup")
+            // Before the player reads inputs, so a press that picks something
+            // up doesn't also roll.
+            .add_systems(
+                Update,
+                pickup_system
+                    .in_set(SfxSpawners)
+                    .before(player_state_read_inputs),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemKind {
+    HealthPotion,
+    /// Go faster, for this long.
+    SpeedBoost(Duration),
+    ExtraAttack,
+}
+
+impl ItemKind {
+    /// LDtk string field: which item. HealthPotion, SpeedBoost, or
+    /// ExtraAttack.
+    const LDTK_KIND_FIELD: &'static str = "kind";
+    /// LDtk float field: how long a speed boost lasts.
+    const LDTK_SECONDS_FIELD: &'static str = "seconds";
+
+    /// Read an item out of an LDtk entity's fields. Errs on an unknown kind.
+    pub fn from_ldtk(instance: &EntityInstance) -> Result<Self, String> {
+        let kind = instance
+            .get_string_field(Self::LDTK_KIND_FIELD)
+            .map_or("", String::as_str);
+        match kind {
+            "HealthPotion" => Ok(Self::HealthPotion),
+            "SpeedBoost" => {
+                let seconds = instance
+                    .get_float_field(Self::LDTK_SECONDS_FIELD)
+                    .copied()
+                    .unwrap_or(0.0);
+                Ok(Self::SpeedBoost(Duration::from_secs_f32(seconds.max(0.0))))
+            },
+            "ExtraAttack" => Ok(Self::ExtraAttack),
+            other => Err(format!("unknown item kind {:?}", other)),
+        }
+    }
+
+    /// What to call it on screen.
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Self::HealthPotion => "Potion",
+            Self::SpeedBoost(_) => "Speed Boost",
+            Self::ExtraAttack => "Extra Attack",
+        }
+    }
+}
+
+/// Component: an item lying on the ground. Its walkbox is what you have to
+/// step on. If it's not `auto_collect`, you have to press the action button
+/// while standing on it.
+#[derive(Component, Debug)]
+pub struct Pickup {
+    pub kind: ItemKind,
+    pub auto_collect: bool,
+}
+
+/// Pickups placed in LDtk. The walkbox is the LDtk entity's own rect.
+#[derive(Bundle)]
+pub struct PickupBundle {
+    pickup: Pickup,
+    walkbox: Walkbox,
+    phys_transform: PhysTransform,
+    sprite: Sprite,
+    texture: Handle<Image>,
+    top_down_matter: TopDownMatter,
+}
+
+impl PickupBundle {
+    /// LDtk bool field: grab it just by walking over it.
+    const LDTK_AUTO_COLLECT_FIELD: &'static str = "auto_collect";
+}
+
+impl LdtkEntity for PickupBundle {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _tileset: Option<&Handle<Image>>,
+        _tileset_definition: Option<&TilesetDefinition>,
+        _asset_server: &AssetServer,
+        _texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) -> Self {
+        let kind = ItemKind::from_ldtk(entity_instance).unwrap_or_else(|e| {
+            warn!(
+                "{} on LDtk entity {}, making it a potion",
+                e, entity_instance.iid
+            );
+            ItemKind::HealthPotion
+        });
+        let auto_collect = matches!(
+            entity_instance.get_bool_field(Self::LDTK_AUTO_COLLECT_FIELD),
+            Ok(true)
+        );
+        let size = Vec2::new(entity_instance.width as f32, entity_instance.height as f32);
+        PickupBundle {
+            pickup: Pickup { kind, auto_collect },
+            walkbox: Walkbox(rect_around_pivot(size, entity_instance.pivot)),
+            phys_transform: PhysTransform {
+                translation: ldtk_entity_phys_location(entity_instance, layer_instance).extend(0.0),
+            },
+            sprite: Sprite {
+                color: PICKUP_COLOR,
+                custom_size: Some(size),
+                ..default()
+            },
+            texture: Handle::default(),
+            top_down_matter: TopDownMatter::floor(),
+        }
+    }
+}
+
+/// Component: what a player's carrying. Empty slots are None.
+#[derive(Component, Debug)]
+pub struct Inventory {
+    pub slots: Vec<Option<ItemKind>>,
+}
+
+impl Inventory {
+    pub fn new(size: usize) -> Self {
+        Self {
+            slots: vec![None; size],
+        }
+    }
+
+    /// Stash an item in the first empty slot. Returns false (and leaves the
+    /// item where it is) if you're full up.
+    pub fn add(&mut self, item: ItemKind) -> bool {
+        match self.slots.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(item);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::new(INVENTORY_SLOTS)
+    }
+}
+
+/// Event: `collector` picked up an item.
+#[derive(Event, Debug, Clone)]
+pub struct ItemCollected {
+    pub kind: ItemKind,
+    pub collector: Entity,
+}
+
+// ------- Systems -------
+
+/// Find players standing on pickups, and move the items into their
+/// inventories. Pickups come out of the space lookup tree by origin, so the
+/// scan distance covers the biggest pickup plus the player's walkbox.
+#[allow(clippy::too_many_arguments)]
+pub fn pickup_system(
+    pickup_q: Query<(&Pickup, &Walkbox, &PhysTransform)>,
+    mut player_q: Query<
+        (
+            Entity,
+            &PlayerIndex,
+            &Walkbox,
+            &PhysTransform,
+            &mut Inventory,
+        ),
+        With<Player>,
+    >,
+    pickup_tree: Res<RstarAccess<Pickup>>,
+    mut inputs: ResMut<CurrentInputs>,
+    sfx: Option<Res<SoundEffects>>,
+    sfx_config: Res<SfxConfig>,
+    mut cooldowns: ResMut<SoundCooldown>,
+    mut collected: EventWriter<ItemCollected>,
+    mut commands: Commands,
+) {
+    let max_pickup_reach = pickup_q
+        .iter()
+        .map(|(_, walkbox, _)| rect_reach(walkbox.0))
+        .fold(0.0, f32::max);
+    if max_pickup_reach == 0.0 {
+        return;
+    }
+    // Two players on one item: first come, first served.
+    let mut taken = Vec::new();
+    for (player, index, walkbox, transform, mut inventory) in player_q.iter_mut() {
+        let loc = transform.translation.truncate();
+        let abs_walkbox = transform.to_absbbox(walkbox);
+        let scan_distance = max_pickup_reach + rect_reach(walkbox.0);
+        for (_, item_entity) in pickup_tree.within_distance(loc, scan_distance) {
+            if taken.contains(&item_entity) {
+                continue;
+            }
+            let Ok((pickup, item_walkbox, item_transform)) = pickup_q.get(item_entity) else {
+                continue;
+            };
+            if !pickup.auto_collect && !inputs.player(*index).actioning {
+                continue;
+            }
+            let abs_item = item_transform.to_absbbox(item_walkbox);
+            if !abs_item.collide(abs_walkbox) || !inventory.add(pickup.kind) {
+                continue;
+            }
+            taken.push(item_entity);
+            if !pickup.auto_collect {
+                // That press was for the pickup; don't roll on it too.
+                let input = inputs.player_mut(*index);
+                input.actioning = false;
+                if input.buffered_action() == Some(ActionKind::Roll) {
+                    input.take_buffered_action();
+                }
+            }
+            commands.entity(item_entity).despawn_recursive();
+            collected.send(ItemCollected {
+                kind: pickup.kind,
+                collector: player,
+            });
+            if let Some(sfx) = sfx.as_deref() {
+                play_sfx(
+                    &mut commands,
+                    &mut cooldowns,
+                    &sfx_config,
+                    &sfx.pickup_sfx,
+                    Some(loc),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::centered_rect;
+    use crate::test_utils::{ldtk_instance, phys_at};
+
+    #[test]
+    fn inventory_fills_first_empty_slot() {
+        let mut inventory = Inventory::new(3);
+        inventory.slots[0] = Some(ItemKind::ExtraAttack);
+        assert!(inventory.add(ItemKind::HealthPotion));
+        assert!(inventory.add(ItemKind::HealthPotion));
+        assert_eq!(
+            inventory.slots,
+            vec![
+                Some(ItemKind::ExtraAttack),
+                Some(ItemKind::HealthPotion),
+                Some(ItemKind::HealthPotion),
+            ]
+        );
+        assert!(!inventory.add(ItemKind::ExtraAttack));
+    }
+
+    #[test]
+    fn items_come_from_ldtk_fields() {
+        let kind = |k: &str| ("kind", FieldValue::String(Some(k.to_string())));
+        let boost = ldtk_instance(vec![
+            kind("SpeedBoost"),
+            ("seconds", FieldValue::Float(Some(2.5))),
+        ]);
+        assert_eq!(
+            ItemKind::from_ldtk(&boost),
+            Ok(ItemKind::SpeedBoost(Duration::from_millis(2500)))
+        );
+        assert_eq!(
+            ItemKind::from_ldtk(&ldtk_instance(vec![kind("ExtraAttack")])),
+            Ok(ItemKind::ExtraAttack)
+        );
+        assert!(ItemKind::from_ldtk(&ldtk_instance(vec![kind("Sandwich")])).is_err());
+        assert!(ItemKind::from_ldtk(&ldtk_instance(vec![])).is_err());
+    }
+
+    #[test]
+    fn walking_over_items_picks_them_up() {
+        let mut app = App::new();
+        app.add_plugins(ItemsPlugin)
+            .init_resource::<CurrentInputs>()
+            .init_resource::<SfxConfig>()
+            .init_resource::<SoundCooldown>();
+        let potion = app
+            .world_mut()
+            .spawn((
+                Pickup {
+                    kind: ItemKind::HealthPotion,
+                    auto_collect: true,
+                },
+                Walkbox(centered_rect(8.0, 8.0)),
+                phys_at(4.0, 0.0),
+            ))
+            .id();
+        // Needs a button press.
+        let sword = app
+            .world_mut()
+            .spawn((
+                Pickup {
+                    kind: ItemKind::ExtraAttack,
+                    auto_collect: false,
+                },
+                Walkbox(centered_rect(8.0, 8.0)),
+                phys_at(-4.0, 0.0),
+            ))
+            .id();
+        let player = app
+            .world_mut()
+            .spawn((
+                Player,
+                PlayerIndex::P1,
+                Walkbox(centered_rect(8.0, 4.0)),
+                phys_at(0.0, 0.0),
+                Inventory::default(),
+            ))
+            .id();
+
+        app.update();
+        assert!(app.world().get_entity(potion).is_none());
+        assert!(app.world().get_entity(sword).is_some());
+        let inventory = app.world().get::<Inventory>(player).unwrap();
+        assert_eq!(inventory.slots[0], Some(ItemKind::HealthPotion));
+        assert_eq!(inventory.slots[1], None);
+
+        let events = app.world().resource::<Events<ItemCollected>>();
+        let got: Vec<(ItemKind, Entity)> = events
+            .get_reader()
+            .read(events)
+            .map(|e| (e.kind, e.collector))
+            .collect();
+        assert_eq!(got, vec![(ItemKind::HealthPotion, player)]);
+
+        // Pressing the button grabs the sword, and eats the press.
+        {
+            let mut inputs = app.world_mut().resource_mut::<CurrentInputs>();
+            let input = inputs.player_mut(PlayerIndex::P1);
+            input.actioning = true;
+            input.buffer_action(ActionKind::Roll, 4);
+        }
+        app.update();
+        assert!(app.world().get_entity(sword).is_none());
+        let inventory = app.world().get::<Inventory>(player).unwrap();
+        assert_eq!(inventory.slots[1], Some(ItemKind::ExtraAttack));
+        let inputs = app.world().resource::<CurrentInputs>();
+        assert!(!inputs.player(PlayerIndex::P1).actioning);
+        assert_eq!(inputs.player(PlayerIndex::P1).buffered_action(), None);
+    }
+}
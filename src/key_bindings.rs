@@ -0,0 +1,150 @@
+//! Keyboard bindings, saved to a TOML file so they stick around between runs.
+//! Rebind them in the inspector window; changes get written back to disk.
+
+use crate::input::MAX_PLAYERS;
+use bevy::{
+    prelude::*,
+    reflect::{DynamicEnum, DynamicVariant, Enum, TypeInfo, Typed, VariantInfo},
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const KEY_BINDINGS_PATH: &str = "./key_bindings.toml";
+
+/// Which keys drive one player slot.
+#[derive(Reflect, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KeyboardBindings {
+    #[serde(with = "key_name")]
+    pub move_up: KeyCode,
+    #[serde(with = "key_name")]
+    pub move_down: KeyCode,
+    #[serde(with = "key_name")]
+    pub move_left: KeyCode,
+    #[serde(with = "key_name")]
+    pub move_right: KeyCode,
+    #[serde(with = "key_name")]
+    pub action: KeyCode,
+    #[serde(with = "key_name")]
+    pub attack: KeyCode,
+}
+
+/// Resource: keyboard bindings for every player slot. In the file, each
+/// player's a `[[players]]` table, in order.
+#[derive(Resource, Reflect, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[reflect(Resource)]
+pub struct KeyboardConfig {
+    pub players: [KeyboardBindings; MAX_PLAYERS],
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        Self {
+            players: [
+                KeyboardBindings {
+                    move_up: KeyCode::KeyW,
+                    move_down: KeyCode::KeyS,
+                    move_left: KeyCode::KeyA,
+                    move_right: KeyCode::KeyD,
+                    action: KeyCode::Space,
+                    attack: KeyCode::ShiftLeft,
+                },
+                KeyboardBindings {
+                    move_up: KeyCode::ArrowUp,
+                    move_down: KeyCode::ArrowDown,
+                    move_left: KeyCode::ArrowLeft,
+                    move_right: KeyCode::ArrowRight,
+                    action: KeyCode::Enter,
+                    attack: KeyCode::ShiftRight,
+                },
+            ],
+        }
+    }
+}
+
+impl KeyboardConfig {
+    /// Load bindings from disk. If there's no file yet, write out the
+    /// defaults so there's something to edit. If the file's busted, complain
+    /// and use the defaults, but leave the file alone so nobody loses their
+    /// work to a typo.
+    pub fn load_or_create(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).unwrap_or_else(|e| {
+                warn!(
+                    "Couldn't parse {}, using default keys: {}",
+                    path.display(),
+                    e
+                );
+                Self::default()
+            }),
+            Err(_) => {
+                let config = Self::default();
+                if let Err(e) = config.save(path) {
+                    warn!("Couldn't write default keys to {}: {}", path.display(), e);
+                }
+                config
+            },
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Serde helpers for KeyCode. Bevy's own serde impls are behind a feature
+/// flag that'd mean rebuilding the whole engine, so go by the reflected
+/// variant name instead. Conveniently, that's also what you'd want to type.
+mod key_name {
+    use super::*;
+    use serde::{de::Error, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(key: &KeyCode, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(key.variant_name())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<KeyCode, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        let unknown = || D::Error::custom(format!("unknown key name: {}", name));
+        // from_reflect panics on variants that don't exist, so check first.
+        let TypeInfo::Enum(info) = KeyCode::type_info() else {
+            return Err(unknown());
+        };
+        if !matches!(info.variant(&name), Some(VariantInfo::Unit(_))) {
+            return Err(unknown());
+        }
+        KeyCode::from_reflect(&DynamicEnum::new(name.as_str(), DynamicVariant::Unit))
+            .ok_or_else(unknown)
+    }
+}
+
+/// Write bindings back to disk whenever they change (e.g. in the inspector).
+pub fn save_key_bindings_system(config: Res<KeyboardConfig>) {
+    if let Err(e) = config.save(KEY_BINDINGS_PATH) {
+        warn!("Couldn't save key bindings: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_round_trip_through_toml() {
+        let mut config = KeyboardConfig::default();
+        config.players[1].attack = KeyCode::Slash;
+        let text = toml::to_string_pretty(&config).unwrap();
+        assert!(text.contains("attack = \"Slash\""));
+        let parsed: KeyboardConfig = toml::from_str(&text).unwrap();
+        assert_eq!(parsed, config);
+    }
+
+    #[test]
+    fn unknown_keys_dont_parse() {
+        let text = toml::to_string_pretty(&KeyboardConfig::default())
+            .unwrap()
+            .replace("\"KeyW\"", "\"KeyWhoops\"");
+        assert!(toml::from_str::<KeyboardConfig>(&text).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! Moving between levels. A LevelTransitionEvent fades the screen to black,
+//! swaps the LevelSelection while nobody can see, moves the players to the
+//! new level's spawn point, and fades back in. Player input is frozen the
+//! whole time. (The camera clamp takes care of itself; it follows
+//! bevy_ecs_ldtk's level events.)
+
+use crate::{
+    assets_setup::SoundEffects,
+    hud::HudCamera,
+    input::{GamePaused, PlayerIndex},
+    phys_space::TeleportEvent,
+    sounds::{play_sfx, SfxConfig, SfxSpawners, SoundCooldown},
+    Player, PlayerSpawnPoint,
+};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use bevy_ecs_ldtk::prelude::*;
+
+/// How long each half of the fade takes.
+pub const FADE_SECS: f32 = 0.3;
+
+pub struct LevelTransitionPlugin;
+impl Plugin for LevelTransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LevelTransitionEvent>()
+            .init_resource::<LevelTransition>()
+            .init_resource::<GamePaused>()
+            .add_event::<TeleportEvent>()
+            .add_systems(
+                Update,
+                level_transition_system
+                    .in_set(SfxSpawners)
+                    .after(crate::find_player_spawn_system)
+                    .before(crate::Teleports),
+            );
+    }
+}
+
+/// Event: please go to this level (by index). Ignored if a transition's
+/// already underway, or if the project doesn't have a level at that index.
+#[derive(Event, Debug)]
+pub struct LevelTransitionEvent {
+    pub target_level: usize,
+}
+
+/// Resource: where we're at in a level transition.
+#[derive(Resource, Default, Debug)]
+pub enum LevelTransition {
+    #[default]
+    Idle,
+    FadingOut {
+        target_level: usize,
+        timer: Timer,
+    },
+    /// Fully black, waiting on the new level to spawn.
+    Loading,
+    FadingIn {
+        timer: Timer,
+    },
+}
+
+/// Things the transition state machine needs the outside world to do.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransitionStep {
+    /// The screen's black; switch levels now.
+    SwapLevel(usize),
+    /// All done, hand control back.
+    Finished,
+}
+
+impl LevelTransition {
+    /// Start heading for a new level. Returns false (and does nothing) if
+    /// we're already mid-transition.
+    pub fn start(&mut self, target_level: usize) -> bool {
+        if !matches!(self, LevelTransition::Idle) {
+            return false;
+        }
+        *self = LevelTransition::FadingOut {
+            target_level,
+            timer: Timer::from_seconds(FADE_SECS, TimerMode::Once),
+        };
+        true
+    }
+
+    /// Run the fade timers.
+    pub fn tick(&mut self, delta: Duration) -> Option<TransitionStep> {
+        match self {
+            LevelTransition::FadingOut {
+                target_level,
+                timer,
+            } => {
+                if timer.tick(delta).finished() {
+                    let target_level = *target_level;
+                    *self = LevelTransition::Loading;
+                    return Some(TransitionStep::SwapLevel(target_level));
+                }
+            },
+            LevelTransition::FadingIn { timer } => {
+                if timer.tick(delta).finished() {
+                    *self = LevelTransition::Idle;
+                    return Some(TransitionStep::Finished);
+                }
+            },
+            LevelTransition::Idle | LevelTransition::Loading => (),
+        }
+        None
+    }
+
+    /// The new level's in; start fading back in. Returns false if we
+    /// weren't waiting on one.
+    pub fn level_loaded(&mut self) -> bool {
+        if !matches!(self, LevelTransition::Loading) {
+            return false;
+        }
+        *self = LevelTransition::FadingIn {
+            timer: Timer::from_seconds(FADE_SECS, TimerMode::Once),
+        };
+        true
+    }
+
+    /// How black the screen should be right now.
+    pub fn overlay_alpha(&self) -> f32 {
+        match self {
+            LevelTransition::Idle => 0.0,
+            LevelTransition::FadingOut { timer, .. } => timer.fraction(),
+            LevelTransition::Loading => 1.0,
+            LevelTransition::FadingIn { timer } => 1.0 - timer.fraction(),
+        }
+    }
+}
+
+/// Marker for the full-screen black rect we fade with.
+#[derive(Component)]
+pub struct FadeOverlay;
+
+/// Where each player lands relative to a spawn point, so co-op kitties
+/// don't start out stacked on each other.
+pub fn player_start_offset(index: PlayerIndex) -> Vec2 {
+    Vec2::new(24.0 * index.0 as f32, 0.0)
+}
+
+// ------- Systems -------
+
+/// Drive level transitions: kick them off, fade, swap levels, move the
+/// players, fade back, unpause. A bad level index gets turned away before
+/// the fade starts; otherwise the screen would sit there black forever,
+/// waiting on a level that's never going to spawn.
+#[allow(clippy::too_many_arguments)]
+pub fn level_transition_system(
+    time: Res<Time>,
+    mut requests: EventReader<LevelTransitionEvent>,
+    mut level_events: EventReader<LevelEvent>,
+    mut transition: ResMut<LevelTransition>,
+    mut level_selection: ResMut<LevelSelection>,
+    projects: Res<Assets<LdtkProject>>,
+    mut paused: ResMut<GamePaused>,
+    spawn_point: Res<PlayerSpawnPoint>,
+    player_q: Query<(Entity, &PlayerIndex), With<Player>>,
+    mut teleports: EventWriter<TeleportEvent>,
+    mut overlay_q: Query<(Entity, &mut BackgroundColor), With<FadeOverlay>>,
+    hud_camera_q: Query<Entity, With<HudCamera>>,
+    sfx: Res<SoundEffects>,
+    sfx_config: Res<SfxConfig>,
+    mut cooldowns: ResMut<SoundCooldown>,
+    mut commands: Commands,
+) {
+    for request in requests.read() {
+        let destination = LevelSelection::index(request.target_level);
+        let level_exists = projects.iter().any(|(_, project)| {
+            project
+                .find_raw_level_by_level_selection(&destination)
+                .is_some()
+        });
+        if !level_exists {
+            warn!("No level at index {}, staying put", request.target_level);
+            continue;
+        }
+        if !transition.start(request.target_level) {
+            continue;
+        }
+        paused.0 = true;
+        play_sfx(
+            &mut commands,
+            &mut cooldowns,
+            &sfx_config,
+            &sfx.level_transition_sfx,
+            None,
+        );
+        let mut overlay = commands.spawn((
+            FadeOverlay,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                background_color: Color::BLACK.with_alpha(0.0).into(),
+                z_index: ZIndex::Global(i32::MAX),
+                ..default()
+            },
+        ));
+        if let Ok(hud_camera) = hud_camera_q.get_single() {
+            overlay.insert(TargetCamera(hud_camera));
+        }
+    }
+
+    let mut level_spawned = level_events
+        .read()
+        .any(|event| matches!(event, LevelEvent::Spawned(_)));
+    match transition.tick(time.delta()) {
+        Some(TransitionStep::SwapLevel(target_level)) => {
+            let destination = LevelSelection::index(target_level);
+            if *level_selection != destination {
+                *level_selection = destination;
+            } else {
+                // Already there; nothing's going to spawn.
+                level_spawned = true;
+            }
+        },
+        Some(TransitionStep::Finished) => {
+            paused.0 = false;
+            for (overlay, _) in overlay_q.iter() {
+                commands.entity(overlay).despawn_recursive();
+            }
+        },
+        None => (),
+    }
+    if level_spawned && transition.level_loaded() {
+        if let Some(start) = spawn_point.0 {
+            for (entity, index) in player_q.iter() {
+                teleports.send(TeleportEvent {
+                    entity,
+                    target: (start + player_start_offset(*index)).extend(0.0),
+                });
+            }
+        }
+    }
+
+    let alpha = transition.overlay_alpha();
+    for (_, mut color) in overlay_q.iter_mut() {
+        color.0.set_alpha(alpha);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn transitions_run_start_to_finish() {
+        let mut transition = LevelTransition::default();
+        assert!(transition.start(2));
+        assert_eq!(transition.tick(ms(150)), None);
+        assert!((transition.overlay_alpha() - 0.5).abs() < 0.01);
+        assert_eq!(transition.tick(ms(200)), Some(TransitionStep::SwapLevel(2)));
+        assert_eq!(transition.overlay_alpha(), 1.0);
+        // Stays black as long as the level takes.
+        assert_eq!(transition.tick(ms(1000)), None);
+        assert!(transition.level_loaded());
+        assert_eq!(transition.tick(ms(400)), Some(TransitionStep::Finished));
+        assert!(matches!(transition, LevelTransition::Idle));
+        assert_eq!(transition.overlay_alpha(), 0.0);
+    }
+
+    #[test]
+    fn transitions_dont_stack() {
+        let mut transition = LevelTransition::default();
+        assert!(transition.start(2));
+        // Standing in a transition zone asks again every frame.
+        assert!(!transition.start(3));
+        assert_eq!(transition.tick(ms(400)), Some(TransitionStep::SwapLevel(2)));
+        assert!(!transition.start(3));
+        // Stray level loads only count while we're waiting on one.
+        assert!(transition.level_loaded());
+        assert!(!transition.level_loaded());
+        assert!(!transition.start(3));
+        assert_eq!(transition.tick(ms(400)), Some(TransitionStep::Finished));
+        assert!(!transition.level_loaded());
+        // And once it's over, you can go again.
+        assert!(transition.start(3));
+    }
+}
@@ -1,9 +1,11 @@
 #![allow(clippy::type_complexity)] // it's just impossible
 
 use crate::{
-    assets_setup::*, behaviors::*, camera::*, char_animation::*, collision::*, collision_debug::*,
-    compass::*, debug_settings::*, entity_states::*, input::*, movement::*, phys_space::*,
-    render::*, sounds::*, space_lookup::RstarPlugin, walls::*,
+    assets_setup::*, behaviors::*, boss::*, camera::*, char_animation::*, collision::*,
+    collision_debug::*, combat::*, combos::*, compass::*, debug_settings::*, entity_states::*,
+    goofy_time::*, health::*, hud::*, input::*, input_replay::*, items::*, key_bindings::*,
+    levels::*, movement::*, pause::*, phys_space::*, projectiles::*, render::*, save_game::*,
+    sounds::*, space_lookup::RstarPlugin, status_effects::*, triggers::*, walls::*,
 };
 use bevy::{
     // ecs::schedule::{LogLevel, ScheduleBuildSettings},
@@ -22,22 +24,39 @@ use std::io::Write;
 
 mod assets_setup;
 mod behaviors;
+mod boss;
 mod camera;
 mod char_animation;
 mod collision;
 mod collision_debug;
+mod combat;
+mod combos;
 mod compass;
 mod debug_settings;
 mod entity_states;
 mod goofy_time;
+mod health;
+mod hud;
 mod input;
+mod input_replay;
+mod items;
 mod junkbox;
+mod key_bindings;
+mod levels;
 mod movement;
+mod pause;
 mod phys_space;
+mod projectiles;
 mod render;
+mod save_game;
 mod sounds;
 mod space_lookup;
+mod status_effects;
+#[cfg(test)]
+mod test_utils;
 mod toolbox;
+mod triggers;
+mod ui_utils;
 mod walls;
 
 fn main() {
@@ -78,10 +97,10 @@ fn main() {
         .add_plugins(LdtkPlugin)
         .add_plugins(EntropyPlugin::<Xoshiro256Plus>::default())
         // DEBUG STUFF
-        .add_systems(Startup, setup_debug_assets.before(setup_player))
-        .add_systems(Update, spawn_collider_debugs)
         .insert_resource(DebugSettings::default())
         .insert_resource(NumbersSettings::default())
+        .add_plugins((SmoothedTimePlugin, StaticTimePlugin, GameTickPlugin))
+        .add_systems(PreUpdate, (time_scale_keys_system, time_scale_system).chain())
         // INSPECTOR STUFF
         .add_plugins(WorldInspectorPlugin::new())
         .register_type::<PhysTransform>()
@@ -89,46 +108,103 @@ fn main() {
         .register_type::<Speed>()
         .register_type::<Walkbox>()
         .register_type::<Hitbox>()
+        .register_type::<Hurtbox>()
+        .register_type::<CollisionLayer>()
+        .register_type::<CollisionMask>()
         .register_type::<TopDownMatter>()
         .register_type::<Motion>()
+        .register_type::<PlatformVelocity>()
+        .register_type::<Health>()
+        .register_type::<Team>()
+        .register_type::<Damage>()
+        .register_type::<PlayerStateMachine>()
+        .register_type::<EnemyStateMachine>()
+        .register_type::<StateTimer>()
+        .register_type::<WaypointProgress>()
         .add_plugins(ResourceInspectorPlugin::<DebugSettings>::new())
         .add_plugins(ResourceInspectorPlugin::<NumbersSettings>::new())
-        .add_systems(Update, (
-            debug_collider_boxes_system,
-            debug_origins_system,
-        ))
+        .add_systems(PostUpdate, debug_colliders_system.after(TransformSystem::TransformPropagate))
+        .add_systems(Update, debug_velocities_system.after(MoveModifiers).before(Movers))
         // LDTK STUFF
         .add_systems(Startup, setup_level)
         .insert_resource(LevelSelection::index(1))
         .register_ldtk_int_cell_for_layer::<Wall>("StructureKind", 1)
+        .register_ldtk_int_cell_for_layer::<DestructibleWall>("StructureKind", 4)
         .register_ldtk_int_cell_for_layer::<Wall>("TerrainKind", 3)
+        .register_ldtk_entity::<MovingPlatformBundle>("MovingPlatform")
+        .register_type::<Destructible>()
+        .add_systems(Update, (destructible_wall_system, wall_break_effect_system))
+        .add_plugins(TriggersPlugin)
+        .add_plugins(LevelTransitionPlugin)
+        .add_plugins(PausePlugin)
+        .add_plugins(SaveGamePlugin)
+        .add_plugins(ItemsPlugin)
         // SPATIAL PARTITIONING STUFF
-        .add_plugins(RstarPlugin::<Solid>::new())
+        .add_plugins(RstarPlugin::<Solid>::new().rebuild_on_level_load())
+        .add_plugins(RstarPlugin::<Hurtbox>::new())
+        .add_plugins(RstarPlugin::<PushPriority>::new())
         // CAMERA
         .add_systems(Startup, setup_camera)
+        .add_plugins(HudPlugin)
         // INPUT STUFF
         .add_systems(Update, connect_gamepads_system)
         .insert_resource(CurrentInputs::default())
-        .add_systems(PreUpdate, accept_input_system
-            .after(InputSystem)
-        )
+        .init_resource::<ActiveGamepads>()
+        .register_type::<PlayerIndex>()
+        .add_plugins(CombosPlugin)
+        .insert_resource(KeyboardConfig::load_or_create(KEY_BINDINGS_PATH))
+        .register_type::<KeyboardConfig>()
+        .add_systems(Update, save_key_bindings_system.run_if(
+            // Not on startup, or a busted file would get clobbered with defaults.
+            resource_changed::<KeyboardConfig>.and_then(not(resource_added::<KeyboardConfig>))
+        ))
+        .insert_resource(DeadZone::default())
+        .register_type::<DeadZone>()
+        .add_systems(PreUpdate, (
+            accept_input_system.run_if(not(resource_exists::<InputReplayer>)),
+            replay_inputs_system.run_if(resource_exists::<InputReplayer>),
+            record_inputs_system.run_if(resource_exists::<InputRecorder>),
+        ).chain().after(InputSystem))
         // SPRITE ASSET STUFF
         .insert_resource(AnimationsMap::default())
         .add_systems(Startup, load_sprite_assets)
         // SOUND STUFF
         .add_systems(Startup, load_sound_effects)
-        .add_systems(Update, sounds_thumps)
+        .init_resource::<SfxConfig>()
+        .register_type::<SfxConfig>()
+        .init_resource::<SoundCooldown>()
+        .add_systems(Update, (sound_cooldown_system, (sounds_thumps, footstep_system).in_set(SfxSpawners)).chain())
+        .add_systems(PostUpdate, spatial_audio_system.after(SfxSpawners).before(TransformSystem::TransformPropagate))
         // BODY STUFF
-        .add_systems(Update, shadow_stitcher_system)
+        .add_systems(Update, (shadow_stitcher_system, (shadow_scale_system, shadow_opacity_system).after(Movers)))
         // BEHAVIOR STUFF
         .add_plugins(BehaviorEventsPlugin)
+        // HEALTH STUFF
+        .add_plugins(HealthEventsPlugin)
+        .init_resource::<ActorStats>()
+        .register_type::<ActorStats>()
+        .add_systems(PreUpdate, enemy_stats_system)
+        .add_systems(Update, (
+            health_drain_system,
+            death_system,
+        ).chain().before(SpriteChangers))
+        .add_systems(Update, (damage_flash_start_system, damage_flash_system).chain().after(health_drain_system))
+        .add_systems(Update, (hit_stop_start_system, hit_stop_system).chain().before(CharAnimationSystems))
+        .add_systems(Update, status_effect_system.before(health_drain_system))
+        .add_systems(Update, slowed_velocity_system.after(MovePlanners).before(MoveModifiers))
+        // COMBAT STUFF
+        .add_systems(PostUpdate, hitbox_hurtbox_collision_system)
+        .add_plugins(ProjectilesPlugin)
         // ENEMY STUFF
-        .add_systems(Startup, temp_setup_enemy.after(load_sprite_assets))
+        .register_ldtk_entity::<EnemyBundle>("Slime")
+        .register_ldtk_entity::<SlimeBossBundle>("SlimeBoss")
+        .add_plugins(BossPlugin)
         .add_systems(
             Update,
             (
+                enemy_setup_rng_system,
                 enemy_state_read_events,
-                enemy_state_changes
+                enemy_state_changes.in_set(SfxSpawners)
             ).chain().in_set(SpriteChangers))
         .add_systems(Update, acquire_aggro.after(Movers).after(CameraMovers))
         // SHARED MOVEMENT STUFF
@@ -148,10 +224,21 @@ fn main() {
         .add_systems(
             Update,
             (
-                mobile_free_velocity,
-                mobile_fixed_velocity,
+                (
+                    (
+                        mobile_free_velocity,
+                        mobile_fixed_velocity,
+                        mobile_chase_entity,
+                        mobile_flee_velocity,
+                    ),
+                    velocity_cap_system,
+                    (
+                        mobile_orbit_velocity,
+                        apply_knockback_system,
+                        follow_path_system,
+                    ),
+                ).chain(),
                 launch_and_fall,
-                mobile_chase_entity,
             ).in_set(MovePlanners),
         )
         .add_systems(
@@ -174,15 +261,31 @@ fn main() {
                 move_continuous_ray_test.run_if(motion_is(MotionKind::RayTest)),
             ).in_set(Movers).ambiguous_with(Movers).before(move_z_axis)
         )
+        .configure_sets(Update, Teleports.before(MovePlanners))
+        .add_systems(Update, teleport_system.in_set(Teleports))
         .add_systems(Update, move_z_axis.in_set(Movers))
+        .add_systems(Update, move_solids.in_set(Movers).after(move_z_axis))
+        .add_systems(Update, depenetrate_entities_system.in_set(Movers).after(move_solids))
+        .add_systems(PostUpdate, depenetrate_characters_system)
         // PLAYER STUFF
-        .add_systems(Startup, setup_player.after(load_sprite_assets))
+        .register_ldtk_entity::<PlayerSpawnBundle>("PlayerSpawn")
+        .init_resource::<PlayerSpawnPoint>()
+        .add_systems(
+            Update,
+            (
+                find_player_spawn_system,
+                setup_player.run_if(
+                    resource_exists_and_changed::<PlayerSpawnPoint>
+                        .and_then(not(any_with_component::<Player>)),
+                ),
+            ).chain()
+        )
         .add_systems(
             Update,
             (
                 player_state_read_inputs,
                 player_state_read_events,
-                player_state_changes,
+                player_state_changes.in_set(SfxSpawners),
                 apply_deferred
             ).chain().in_set(SpriteChangers)
         )
@@ -191,12 +294,44 @@ fn main() {
             Update,
             (
                 camera_locked_system.run_if(camera_is(CameraKind::Locked)),
-                camera_lerp_system.run_if(camera_is(CameraKind::Lerp)),
+                camera_lerp_system.run_if(
+                    camera_is(CameraKind::Lerp).or_else(camera_is(CameraKind::LerpWithDeadzone)),
+                ),
             ).in_set(CameraMovers).ambiguous_with(CameraMovers)
         )
+        .add_systems(
+            Update,
+            camera_clamp_system
+                .in_set(CameraMovers)
+                .after(camera_locked_system)
+                .after(camera_lerp_system)
+        )
+        .add_systems(Update, camera_pixel_snap_system.in_set(CameraMovers).after(camera_clamp_system))
+        .register_type::<ParallaxLayer>()
+        .add_systems(Update, parallax_system.in_set(CameraMovers).after(camera_pixel_snap_system))
+        .init_resource::<CameraZoom>()
+        .register_type::<CameraZoom>()
+        .add_systems(Update, (
+            camera_zoom_events_system,
+            camera_zoom_system.in_set(CameraMovers).before(camera_clamp_system),
+        ).chain())
+        .init_resource::<CameraClamp>()
+        .register_type::<CameraClamp>()
+        .add_systems(Update, camera_clamp_level_bounds_system.before(CameraMovers))
+        .init_resource::<CameraConfig>()
+        .register_type::<CameraConfig>()
+        // Shake goes on top of whatever the camera movers did, after phys sync.
+        .init_resource::<CameraShake>()
+        .register_type::<CameraShake>()
+        .add_systems(Update, (
+            camera_trauma_system,
+            camera_shake_system.after(sync_phys_transforms),
+        ).chain())
         // PHYSICS SPACE STUFF
         .add_systems(Update, add_new_phys_transforms.before(MovePlanners))
         .add_systems(Update, sync_phys_transforms.after(CameraMovers))
+        .register_type::<SnapToGrid>()
+        .add_systems(PostUpdate, snap_to_grid_system.before(TransformSystem::TransformPropagate))
         // OK BYE!!!
         ;
 
@@ -209,6 +344,18 @@ fn main() {
             );
     }
 
+    // Input recording, for reproducing bugs.
+    if let Some(path) = input_replay::path_arg("--record") {
+        let recorder = InputRecorder::create(&path).expect("couldn't create input recording file");
+        info!("Recording inputs to {}", path);
+        app.insert_resource(recorder);
+    }
+    if let Some(path) = input_replay::path_arg("--replay") {
+        let replayer = InputReplayer::open(&path).expect("couldn't read input recording file");
+        info!("Replaying inputs from {}", path);
+        app.insert_resource(replayer);
+    }
+
     if std::env::args().any(|arg| &arg == "--graph") {
         // Write the debug dump to a file and exit. (not sure why it exits, though??? oh well!)
         let settings = bevy_mod_debugdump::schedule_graph::Settings::default();
@@ -223,6 +370,11 @@ fn main() {
 #[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct MovePlanners;
 
+/// TeleportEvents get carried out here, both on PhysTransforms and in every
+/// space lookup tree, before anybody plans a move off the old positions.
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Teleports;
+
 #[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct MoveModifiers;
 
@@ -243,80 +395,107 @@ fn setup_level(mut commands: Commands, asset_server: Res<AssetServer>) {
     ));
 }
 
-// Obviously this is wack, and we should be spawning from ldtk entities, but bear with me here.
-fn temp_setup_enemy(mut commands: Commands, animations: Res<AnimationsMap>) {
-    let initial_animation = animations.get(&Ases::SlimeIdle).unwrap().clone();
-    let whence = Vec3::new(220., 200., 0.); // empirically 🤷🏽
-
-    commands.spawn((EnemyBundle {
-        identity: Enemy,
-        name: Name::new("Sloom"),
-        state_machine: EnemyStateMachine::new(EnemyState::default()),
-        state_timer: StateTimer::default(),
-        sprite: SpriteBundle::default(),
-        texture_atlas: TextureAtlas::default(),
-        char_animation_state: CharAnimationState::new(initial_animation, Dir::E, Playback::Loop),
-        phys_transform: PhysTransform {
-            translation: whence,
-        },
-        phys_offset: PhysOffset(Vec2::ZERO),
-        walkbox: Walkbox(Rect::default()),
-        hitbox: Hitbox(None),
-        hurtbox: Hurtbox(None),
-        shadow: HasShadow,
-        top_down_matter: TopDownMatter::character(),
-        speed: Speed(Speed::ENEMY_RUN), // ???
-        motion: Motion::new(Vec2::ZERO),
-        push_priority: PushPriority::enemy(),
-
-        patrol: PatrolArea::Patch {
-            home: whence.truncate(),
-            radius: 140.0,
-        },
-    },));
+/// Note where the level wants players to start. Runs when a level finishes
+/// spawning; if it didn't come with a PlayerSpawn, fall back to the origin.
+fn find_player_spawn_system(
+    mut level_events: EventReader<LevelEvent>,
+    spawn_q: Query<&PlayerSpawn, Added<PlayerSpawn>>,
+    mut spawn_point: ResMut<PlayerSpawnPoint>,
+) {
+    for event in level_events.read() {
+        let LevelEvent::Spawned(level) = event else {
+            continue;
+        };
+        spawn_point.0 = Some(match spawn_q.iter().next() {
+            Some(spawn) => spawn.0,
+            None => {
+                warn!("Level {} has no PlayerSpawn entity, starting at 0,0", level);
+                Vec2::ZERO
+            },
+        });
+    }
 }
 
-fn setup_player(mut commands: Commands, animations: Res<AnimationsMap>) {
-    let initial_animation = animations.get(&Ases::TkIdle).unwrap().clone();
-
-    // IT'S THE PLAYER, GIVE IT UP!!
-    commands.spawn((PlayerBundle {
-        // Remember who u are
-        identity: Player,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(0.0, 0.0, 3.0)),
-            ..Default::default()
-        },
-        texture_atlas: TextureAtlas::default(),
-        phys_transform: PhysTransform {
-            translation: Vec3::ZERO,
-        },
-        phys_offset: PhysOffset(Vec2::ZERO),
-        speed: Speed(Speed::RUN),
-        walkbox: Walkbox(Rect::default()),
-        hitbox: Hitbox(None),
-        hurtbox: Hurtbox(None),
-        // --- New animation system
-        char_animation_state: CharAnimationState::new(initial_animation, Dir::E, Playback::Loop),
-        motion: Motion::new(Vec2::ZERO),
-        push_priority: PushPriority::player(),
-        // Initial gameplay state
-        state_machine: PlayerStateMachine::new(PlayerState::Idle),
-        state_timer: StateTimer::default(),
-        // Shadow marker
-        shadow: HasShadow,
-        // Draw-depth manager
-        top_down_matter: TopDownMatter::character(),
-        // Inspector?
-        name: Name::new("Kittybuddy"),
-    },));
+/// Spawn the players, once we know where. Waits on PlayerSpawnPoint, which
+/// doesn't get filled in until the level's loaded. If we're picking up from
+/// a save, that overrides the level's spawn point (and your health).
+fn setup_player(
+    mut commands: Commands,
+    animations: Res<AnimationsMap>,
+    spawn_point: Res<PlayerSpawnPoint>,
+    stats: Res<ActorStats>,
+    saved: Option<Res<LoadedSaveData>>,
+) {
+    let Some(mut start) = spawn_point.0 else {
+        return;
+    };
+    let mut health = Health::new(stats.player_max_health);
+    if let Some(saved) = saved {
+        start = saved.0.respawn_point();
+        health = saved.0.restore_health(stats.player_max_health);
+    }
+    // Two kitties, side by side. (P2's name is a work in progress.)
+    let players = [
+        (PlayerIndex::P1, "Kittybuddy"),
+        (PlayerIndex::P2, "Kittybuddy 2"),
+    ];
+    for (index, name) in players {
+        let location = start + player_start_offset(index);
+        let initial_animation = animations.get(&Ases::TkIdle).unwrap().clone();
+
+        // IT'S THE PLAYER, GIVE IT UP!!
+        commands.spawn((PlayerBundle {
+            // Remember who u are
+            identity: Player,
+            index,
+            input_history: InputHistory::default(),
+            inventory: Inventory::default(),
+            // Initial gameplay state
+            state_machine: PlayerStateMachine::new(PlayerState::Idle),
+            state_timer: StateTimer::default(),
+            // Inspector?
+            name: Name::new(name),
+            actor: CommonActorBundle {
+                sprite: SpriteBundle {
+                    transform: Transform::from_translation(location.extend(3.0)),
+                    ..Default::default()
+                },
+                texture_atlas: TextureAtlas::default(),
+                // --- New animation system
+                char_animation_state: CharAnimationState::new(
+                    initial_animation,
+                    Dir::E,
+                    Playback::Loop,
+                ),
+                phys_transform: PhysTransform {
+                    translation: location.extend(0.0),
+                },
+                phys_offset: PhysOffset(Vec2::ZERO),
+                snap_to_grid: SnapToGrid::default(),
+                walkbox: Walkbox(Rect::default()),
+                hitbox: Hitbox(None),
+                hurtbox: Hurtbox(None),
+                collision_layer: CollisionLayer(LAYER_PLAYER),
+                health,
+                team: Team::PLAYER,
+                hit_this_swing: HitThisSwing::default(),
+                // Shadow marker
+                shadow: HasShadow,
+                // Draw-depth manager
+                top_down_matter: TopDownMatter::character(),
+                speed: Speed(Speed::RUN),
+                motion: Motion::new(Vec2::ZERO),
+                push_priority: PushPriority::player(),
+            },
+        },));
+    }
 }
 
 // Structs and crap!
 
 /// Marker component for enemies
 #[derive(Component)]
-struct Enemy;
+pub struct Enemy;
 
 #[derive(Bundle)]
 struct EnemyBundle {
@@ -324,46 +503,190 @@ struct EnemyBundle {
     name: Name,
     state_machine: EnemyStateMachine,
     state_timer: StateTimer,
+    first_idle: FirstIdle,
+    actor: CommonActorBundle,
+    collision_mask: CollisionMask,
 
-    // .......oh nice, everything below here is same as player. Ripe for future consolidation!
-    sprite: SpriteBundle,
-    texture_atlas: TextureAtlas,
-    char_animation_state: CharAnimationState,
+    patrol: PatrolArea,
+    waypoint_progress: WaypointProgress,
+    line_of_sight: HasLineOfSight,
+}
 
-    phys_transform: PhysTransform,
-    phys_offset: PhysOffset,
+impl EnemyBundle {
+    /// LDtk string field: what kind of guy. Only "Slime" so far.
+    const LDTK_ENEMY_TYPE_FIELD: &'static str = "enemy_type";
+    /// LDtk float field: how far it wanders from where it was placed.
+    const LDTK_PATROL_RADIUS_FIELD: &'static str = "patrol_radius";
+    const DEFAULT_PATROL_RADIUS: f32 = 140.0;
+}
 
-    walkbox: Walkbox,
-    hitbox: Hitbox,
-    hurtbox: Hurtbox,
+impl LdtkEntity for EnemyBundle {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _tileset: Option<&Handle<Image>>,
+        _tileset_definition: Option<&TilesetDefinition>,
+        asset_server: &AssetServer,
+        _texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) -> Self {
+        let enemy_type = entity_instance
+            .get_string_field(Self::LDTK_ENEMY_TYPE_FIELD)
+            .map_or("Slime", String::as_str);
+        if enemy_type != "Slime" {
+            warn!(
+                "Unknown enemy_type {:?} on LDtk entity {}, making a slime",
+                enemy_type, entity_instance.iid
+            );
+        }
+
+        let offset = ldtk_entity_phys_offset(layer_instance);
+        let home = ldtk_entity_phys_location(entity_instance, layer_instance);
+        let radius = match entity_instance
+            .get_float_field(Self::LDTK_PATROL_RADIUS_FIELD)
+            .copied()
+        {
+            Ok(radius) if radius.is_finite() && radius > 0.0 => radius,
+            Ok(radius) => {
+                warn!(
+                    "Bad patrol_radius {} on LDtk entity {}, using {}",
+                    radius,
+                    entity_instance.iid,
+                    Self::DEFAULT_PATROL_RADIUS
+                );
+                Self::DEFAULT_PATROL_RADIUS
+            },
+            Err(_) => Self::DEFAULT_PATROL_RADIUS,
+        };
+        let patrol = PatrolArea::waypoints_from_ldtk(
+            entity_instance,
+            layer_instance.c_hei,
+            layer_instance.grid_size,
+            offset,
+        )
+        .or_else(|| PatrolArea::ring_from_ldtk(entity_instance, home, radius))
+        .unwrap_or(PatrolArea::Patch { home, radius });
+
+        EnemyBundle {
+            identity: Enemy,
+            name: Name::new("Sloom"),
+            state_machine: EnemyStateMachine::new(EnemyState::default()),
+            state_timer: StateTimer::default(),
+            first_idle: FirstIdle,
+            actor: CommonActorBundle {
+                sprite: SpriteBundle::default(),
+                texture_atlas: TextureAtlas::default(),
+                // Same handle as in AnimationsMap, since it's the same path.
+                char_animation_state: CharAnimationState::new(
+                    asset_server.load(Ases::SlimeIdle.path()),
+                    Dir::E,
+                    Playback::Loop,
+                ),
+                phys_transform: PhysTransform {
+                    translation: home.extend(0.0),
+                },
+                phys_offset: PhysOffset(offset),
+                snap_to_grid: SnapToGrid::default(),
+                walkbox: Walkbox(Rect::default()),
+                hitbox: Hitbox(None),
+                hurtbox: Hurtbox(None),
+                collision_layer: CollisionLayer(LAYER_ENEMY),
+                // Placeholder; enemy_stats_system fills in the real number.
+                health: Health::new(ActorStats::default().enemy_max_health),
+                team: Team::ENEMY,
+                hit_this_swing: HitThisSwing::default(),
+                shadow: HasShadow,
+                top_down_matter: TopDownMatter::character(),
+                speed: Speed(Speed::ENEMY_RUN), // ???
+                motion: Motion::new(Vec2::ZERO),
+                push_priority: PushPriority::enemy(),
+            },
+            // Don't get hit by each other's spit.
+            collision_mask: CollisionMask(!LAYER_ENEMY_PROJECTILE),
+
+            patrol,
+            waypoint_progress: WaypointProgress::default(),
+            // Slimes can't see through walls.
+            line_of_sight: HasLineOfSight,
+        }
+    }
+}
 
-    shadow: HasShadow,
-    top_down_matter: TopDownMatter,
+/// A big slime with two phases: once it's down to half health, it flinches
+/// and starts phase two. Mostly a demo of how bosses get put together. It
+/// also spits at you while it's chasing you.
+#[derive(Bundle)]
+struct SlimeBossBundle {
+    enemy: EnemyBundle,
+    phase: BossPhase,
+    thresholds: BossThresholds,
+}
 
-    speed: Speed,
-    motion: Motion,
-    push_priority: PushPriority,
+impl SlimeBossBundle {
+    const MAX_HEALTH: i32 = 12;
+    const SPIT_COOLDOWN_SECS: f32 = 2.5;
+}
 
-    patrol: PatrolArea,
+impl LdtkEntity for SlimeBossBundle {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        tileset: Option<&Handle<Image>>,
+        tileset_definition: Option<&TilesetDefinition>,
+        asset_server: &AssetServer,
+        texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) -> Self {
+        let mut enemy = EnemyBundle::bundle_entity(
+            entity_instance,
+            layer_instance,
+            tileset,
+            tileset_definition,
+            asset_server,
+            texture_atlases,
+        );
+        enemy.name = Name::new("Big Sloom");
+        enemy.actor.health = Health::new(Self::MAX_HEALTH);
+        enemy.state_timer = StateTimer::with_attack_cooldown(Self::SPIT_COOLDOWN_SECS);
+        SlimeBossBundle {
+            enemy,
+            phase: BossPhase::default(),
+            thresholds: BossThresholds {
+                phases: vec![(Self::MAX_HEALTH / 2, EnemyState::Hurt)],
+            },
+        }
+    }
 }
 
 #[derive(Bundle)]
 struct PlayerBundle {
     identity: Player,
+    index: PlayerIndex,
+    input_history: InputHistory,
+    inventory: Inventory,
     name: Name,
     state_machine: PlayerStateMachine,
     state_timer: StateTimer,
+    actor: CommonActorBundle,
+}
 
+/// Everything a player and an enemy both need to walk around, animate, and
+/// get hit. Add new shared stuff here, so neither one gets left out.
+#[derive(Bundle)]
+struct CommonActorBundle {
     sprite: SpriteBundle,
     texture_atlas: TextureAtlas,
     char_animation_state: CharAnimationState,
 
     phys_transform: PhysTransform,
     phys_offset: PhysOffset,
+    snap_to_grid: SnapToGrid,
 
     walkbox: Walkbox,
     hitbox: Hitbox,
     hurtbox: Hurtbox,
+    collision_layer: CollisionLayer,
+    health: Health,
+    team: Team,
+    hit_this_swing: HitThisSwing,
 
     shadow: HasShadow,
     top_down_matter: TopDownMatter,
@@ -373,6 +696,47 @@ struct PlayerBundle {
     push_priority: PushPriority,
 }
 
+/// LDtk builds enemies without access to resources, so they spawn with the
+/// default health; set it from ActorStats once they're in the world. Bosses
+/// bring their own numbers.
+fn enemy_stats_system(
+    mut enemy_q: Query<&mut Health, (Added<Enemy>, Without<BossPhase>)>,
+    stats: Res<ActorStats>,
+) {
+    for mut health in enemy_q.iter_mut() {
+        *health = Health::new(stats.enemy_max_health);
+    }
+}
+
+/// Resource: where players start, in phys space. None until the level
+/// loads and tells us.
+#[derive(Resource, Default)]
+pub struct PlayerSpawnPoint(pub Option<Vec2>);
+
+/// Component: an LDtk PlayerSpawn entity's location, in phys space.
+#[derive(Component)]
+pub struct PlayerSpawn(pub Vec2);
+
+#[derive(Bundle)]
+struct PlayerSpawnBundle {
+    spawn: PlayerSpawn,
+}
+
+impl LdtkEntity for PlayerSpawnBundle {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _tileset: Option<&Handle<Image>>,
+        _tileset_definition: Option<&TilesetDefinition>,
+        _asset_server: &AssetServer,
+        _texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) -> Self {
+        PlayerSpawnBundle {
+            spawn: PlayerSpawn(ldtk_entity_phys_location(entity_instance, layer_instance)),
+        }
+    }
+}
+
 /// Marker component for a spawned LdtkWorldBundle
 #[derive(Component)]
 pub struct LdtkWorld;
@@ -0,0 +1,151 @@
+//! Pausing the game on purpose: Escape, or Start on a pad that's driving a
+//! player. (Not to be confused with GamePaused, which only freezes inputs
+//! while a level transition plays.) Stops the virtual clock, so everything
+//! that goes by Time in Update -- Timers, CountupTimers, animations, the
+//! physics -- holds still without having to know about it.
+
+use crate::{
+    hud::HudCamera,
+    input::{accept_input_system, ActiveGamepads},
+};
+use bevy::{input::InputSystem, prelude::*};
+
+const PAUSE_KEY: KeyCode = KeyCode::Escape;
+const PAUSE_FONT: &str = "fonts/m5x7.ttf";
+/// A multiple of the font's native size, so it stays crisp.
+const PAUSE_FONT_SIZE: f32 = 48.0;
+const PAUSE_DIM_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+
+pub struct PausePlugin;
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PauseMenu>()
+            .add_systems(
+                PreUpdate,
+                toggle_pause_system
+                    .after(InputSystem)
+                    .before(accept_input_system),
+            )
+            .add_systems(
+                Update,
+                pause_overlay_system.run_if(resource_changed::<PauseMenu>),
+            );
+    }
+}
+
+/// Resource: whether the player's got the game paused.
+#[derive(Resource, Default, Debug, PartialEq, Eq)]
+pub struct PauseMenu(pub bool);
+
+/// Marker for the dimmer that covers the screen while paused.
+#[derive(Component)]
+pub struct PauseOverlay;
+
+// ------- Systems -------
+
+/// Flip the pause on and off, and stop or start the virtual clock to match.
+/// Unseated gamepads don't count, since their Start button is busy claiming
+/// a player.
+pub fn toggle_pause_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    pad_buttons: Res<ButtonInput<GamepadButton>>,
+    active_gamepads: Res<ActiveGamepads>,
+    mut pause_menu: ResMut<PauseMenu>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    let start_pressed =
+        active_gamepads.0.iter().flatten().any(|pad| {
+            pad_buttons.just_pressed(GamepadButton::new(*pad, GamepadButtonType::Start))
+        });
+    if !keys.just_pressed(PAUSE_KEY) && !start_pressed {
+        return;
+    }
+    pause_menu.0 = !pause_menu.0;
+    if pause_menu.0 {
+        time.pause();
+    } else {
+        time.unpause();
+    }
+}
+
+/// Put up (or take down) the dimmer and the big PAUSED.
+fn pause_overlay_system(
+    pause_menu: Res<PauseMenu>,
+    overlay_q: Query<Entity, With<PauseOverlay>>,
+    hud_camera_q: Query<Entity, With<HudCamera>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for overlay in overlay_q.iter() {
+        commands.entity(overlay).despawn_recursive();
+    }
+    if !pause_menu.0 {
+        return;
+    }
+    let mut overlay = commands.spawn((
+        PauseOverlay,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: PAUSE_DIM_COLOR.into(),
+            // Under the level transition fade, which owns the very top.
+            z_index: ZIndex::Global(i32::MAX - 1),
+            ..default()
+        },
+        Name::new("Pause overlay"),
+    ));
+    overlay.with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            "PAUSED",
+            TextStyle {
+                font: asset_server.load(PAUSE_FONT),
+                font_size: PAUSE_FONT_SIZE,
+                color: Color::WHITE,
+            },
+        ));
+    });
+    if let Ok(hud_camera) = hud_camera_q.get_single() {
+        overlay.insert(TargetCamera(hud_camera));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn escape_stops_and_starts_the_clock() {
+        let mut world = World::new();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<ButtonInput<GamepadButton>>();
+        world.init_resource::<ActiveGamepads>();
+        world.init_resource::<PauseMenu>();
+        world.init_resource::<Time<Virtual>>();
+        let press = |world: &mut World, key: KeyCode| {
+            let mut keys = world.resource_mut::<ButtonInput<KeyCode>>();
+            keys.clear();
+            keys.release(key);
+            keys.press(key);
+            world.run_system_once(toggle_pause_system);
+        };
+
+        press(&mut world, PAUSE_KEY);
+        assert_eq!(*world.resource::<PauseMenu>(), PauseMenu(true));
+        assert!(world.resource::<Time<Virtual>>().is_paused());
+
+        // Other keys don't do anything.
+        press(&mut world, KeyCode::KeyQ);
+        assert_eq!(*world.resource::<PauseMenu>(), PauseMenu(true));
+
+        press(&mut world, PAUSE_KEY);
+        assert_eq!(*world.resource::<PauseMenu>(), PauseMenu(false));
+        assert!(!world.resource::<Time<Virtual>>().is_paused());
+    }
+}
@@ -23,7 +23,17 @@
 //! - This module's systems sync PhysTransform to Transform every frame.
 //! - To make an entity physical, just insert its offset; we'll handle the rest
 //!   in this module.
+//! - If its sprite shouldn't land between pixels, give it a SnapToGrid. That
+//!   only rounds the Transform, so the physics keep their sub-pixel precision.
+//! - Moving something by just setting its translation is fine, but the space
+//!   lookup trees won't hear about it until PostUpdate. If anybody needs to
+//!   find it at its new spot this frame, send a TeleportEvent instead.
 
+use crate::{
+    collision::{AbsBBox, Walkbox},
+    render::TopDownMatter,
+    toolbox::snap_to_grid,
+};
 use bevy::prelude::*;
 
 /// Global offset from 0,0 for entities that particpate in physical interactions.
@@ -38,6 +48,60 @@ pub struct PhysTransform {
     pub translation: Vec3,
 }
 
+impl PhysTransform {
+    /// The point `alpha` of the way from here to `target`. Doesn't move
+    /// anything; hand the result to a move planner.
+    pub fn lerp_towards(&self, target: Vec3, alpha: f32) -> Vec3 {
+        self.translation.lerp(target, alpha)
+    }
+
+    /// Where this thing's walkbox is, in absolute space.
+    pub fn to_absbbox(&self, walkbox: &Walkbox) -> AbsBBox {
+        AbsBBox::from_rect(walkbox.0, self.translation.truncate())
+    }
+
+    /// How far apart two things are on the ground, squared. Ignores height,
+    /// since something hopping over your head is still right there.
+    pub fn distance_to_2d_sq(&self, other: &PhysTransform) -> f32 {
+        self.translation
+            .truncate()
+            .distance_squared(other.translation.truncate())
+    }
+}
+
+/// Round this entity's rendered position to a grid, in world space. Slow
+/// diagonal movement shimmers otherwise. Grid size is in world pixels.
+#[derive(Component, Reflect)]
+pub struct SnapToGrid(pub f32);
+
+impl Default for SnapToGrid {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Event: put `entity` at `target`, in phys space. Every RstarPlugin tree
+/// gets updated along with the PhysTransform, so spatial lookups see the new
+/// spot for the rest of the frame.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TeleportEvent {
+    pub entity: Entity,
+    pub target: Vec3,
+}
+
+/// System: Carry out TeleportEvents on PhysTransforms. Each RstarPlugin
+/// handles its own tree, right after this.
+pub fn teleport_system(
+    mut teleports: EventReader<TeleportEvent>,
+    mut transform_q: Query<&mut PhysTransform>,
+) {
+    for teleport in teleports.read() {
+        if let Ok(mut transform) = transform_q.get_mut(teleport.entity) {
+            transform.translation = teleport.target;
+        }
+    }
+}
+
 /// System: Add PhysTransform to entities that just received their PhysOffset.
 pub fn add_new_phys_transforms(
     mut commands: Commands,
@@ -58,3 +122,108 @@ pub fn sync_phys_transforms(mut query: Query<(&PhysTransform, &PhysOffset, &mut
         transform.translation = phys_transform.translation - offset.0.extend(0.0);
     }
 }
+
+/// System: Snap grid-bound entities' Transforms, after sync_phys_transforms
+/// has put them wherever the physics left them. Expects to run in
+/// PostUpdate, before transform propagation. Leaves Z alone.
+///
+/// What needs to land on the grid is where the sprite gets drawn, and the
+/// render world's flattening pass adds height to Y for anything that can
+/// leave the ground. So snap the flattened spot, then take the height back
+/// off; otherwise a hop of 3.3px puts the sprite 0.3px off the grid.
+pub fn snap_to_grid_system(
+    mut query: Query<(
+        &SnapToGrid,
+        &PhysOffset,
+        &mut Transform,
+        Option<&TopDownMatter>,
+    )>,
+) {
+    for (grid, offset, mut transform, matter) in query.iter_mut() {
+        if grid.0 <= 0.0 {
+            continue;
+        }
+        let lift = match matter {
+            Some(matter) if !matter.ignore_height => Vec2::new(0.0, transform.translation.z),
+            _ => Vec2::ZERO,
+        };
+        let drawn_at = transform.translation.truncate() + lift + offset.0;
+        let snapped = snap_to_grid(drawn_at, grid.0) - offset.0 - lift;
+        transform.translation = snapped.extend(transform.translation.z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn at(x: f32, y: f32, z: f32) -> PhysTransform {
+        PhysTransform {
+            translation: Vec3::new(x, y, z),
+        }
+    }
+
+    #[test]
+    fn distances_stay_on_the_ground() {
+        let a = at(1.0, 2.0, 0.0);
+        let b = at(4.0, 6.0, 0.0);
+        assert_eq!(a.distance_to_2d_sq(&b), 25.0);
+        assert_eq!(b.distance_to_2d_sq(&a), 25.0);
+        assert_eq!(a.distance_to_2d_sq(&a), 0.0);
+        // Height doesn't count.
+        let b_jumping = at(4.0, 6.0, 30.0);
+        assert_eq!(a.distance_to_2d_sq(&b_jumping), 25.0);
+        assert_eq!(
+            at(-3.0, 0.0, 0.0).distance_to_2d_sq(&at(0.0, -4.0, 0.0)),
+            25.0
+        );
+    }
+
+    #[test]
+    fn snapping_only_touches_the_transform() {
+        let mut world = World::new();
+        let snapped = world
+            .spawn((
+                at(10.4, 20.6, 3.3),
+                // Its PhysOffset is half a pixel, so its Transform has to sit
+                // at a half pixel for the offset spot to land on a whole one.
+                PhysOffset(Vec2::new(0.5, 0.0)),
+                Transform::default(),
+                SnapToGrid::default(),
+            ))
+            .id();
+        // Off the ground, so it gets drawn 3.3px higher than its Y.
+        let hopping = world
+            .spawn((
+                at(10.0, 20.0, 3.3),
+                PhysOffset(Vec2::ZERO),
+                Transform::default(),
+                SnapToGrid::default(),
+                TopDownMatter::character(),
+            ))
+            .id();
+        world.run_system_once(sync_phys_transforms);
+        world.run_system_once(snap_to_grid_system);
+
+        let transform = world.get::<Transform>(snapped).unwrap();
+        assert_eq!(transform.translation, Vec3::new(9.5, 21.0, 3.3));
+        let phys = world.get::<PhysTransform>(snapped).unwrap();
+        assert_eq!(phys.translation, Vec3::new(10.4, 20.6, 3.3));
+
+        let hop = world.get::<Transform>(hopping).unwrap().translation;
+        assert_eq!(hop.z, 3.3);
+        // Same math as the flattening pass.
+        let drawn_y = hop.y + hop.z;
+        assert!((drawn_y - 23.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn walkboxes_go_where_you_are() {
+        let walkbox = Walkbox(Rect::new(-4.0, 0.0, 4.0, 2.0));
+        let bbox = at(10.0, 20.0, 5.0).to_absbbox(&walkbox);
+        assert_eq!(bbox.min, Vec2::new(6.0, 20.0));
+        assert_eq!(bbox.max, Vec2::new(14.0, 22.0));
+        assert_eq!(bbox.center(), Vec2::new(10.0, 21.0));
+    }
+}
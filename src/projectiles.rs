@@ -0,0 +1,415 @@
+//! Stuff that flies. A projectile is just a little hitbox on a fixed heading
+//! with an expiration date; it does damage through the same hitbox/hurtbox
+//! check as everything else.
+
+use crate::{
+    behaviors::MobileFixed,
+    char_animation::{AnimateFrameEvent, CharAnimation, CharAnimationState},
+    collision::{
+        centered_rect, CollisionLayer, Hitbox, HitboxActive, Walkbox, LAYER_ENEMY_PROJECTILE,
+        LAYER_PROJECTILE,
+    },
+    combat::{Damage, HitThisSwing, Team},
+    combos::{ComboActivated, ComboDefinition, SpecialMove},
+    debug_settings::{DebugSettings, NumbersSettings},
+    entity_states::{EnemyState, EnemyStateMachine, PlayerState, PlayerStateMachine},
+    movement::{Motion, Speed},
+    phys_space::{PhysOffset, PhysTransform},
+    render::TopDownMatter,
+    toolbox::countup_timer::CountupTimer,
+    Player,
+};
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashSet};
+
+/// How fast the player's shots go, in px/s.
+const PLAYER_PROJECTILE_SPEED: f32 = 240.0;
+const PLAYER_PROJECTILE_LIFETIME_MS: u64 = 600;
+const PLAYER_PROJECTILE_DAMAGE: i32 = 1;
+/// The special move shot: slower and longer-lived than the debug one, and
+/// it hurts more.
+const FIREBALL_SPEED: f32 = 180.0;
+const FIREBALL_LIFETIME_MS: u64 = 900;
+const FIREBALL_DAMAGE: i32 = 3;
+/// Default for NumbersSettings::enemy_projectile_speed. Slower than the
+/// player's, so you can dodge.
+pub const ENEMY_PROJECTILE_SPEED: f32 = 120.0;
+const ENEMY_PROJECTILE_LIFETIME_MS: u64 = 1500;
+const ENEMY_PROJECTILE_DAMAGE: i32 = 1;
+/// Which frame of the ranged attack animation the shot leaves on: the
+/// slime's lunge. A fixed frame instead of "wherever the hitbox starts",
+/// since a spit doesn't need a hitbox and not every attack sprite has one.
+const ENEMY_SPIT_FRAME: usize = 1;
+/// Projectiles are tiny little guys, for both hitting and bumping into walls.
+const PROJECTILE_SIZE: f32 = 6.0;
+
+pub struct ProjectilesPlugin;
+impl Plugin for ProjectilesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SpawnProjectile>().add_systems(
+            Update,
+            (
+                (
+                    player_projectile_attack_system,
+                    enemy_projectile_attack_system,
+                    special_move_system,
+                ),
+                spawn_projectile_system,
+            )
+                .chain()
+                .after(crate::char_animation::CharAnimationSystems)
+                .before(crate::MovePlanners),
+        );
+        app.add_systems(Update, projectile_lifetime_system.after(crate::Movers));
+    }
+}
+
+/// Event: fire something. It flies from the shooter's location on a fixed
+/// heading until it runs out of time or hits a wall, and it never hits the
+/// shooter.
+#[derive(Event, Debug)]
+pub struct SpawnProjectile {
+    pub shooter: Entity,
+    /// px/s.
+    pub velocity: Vec2,
+    pub lifetime_ms: u64,
+    pub damage: i32,
+    /// Which collision layer it flies on; this is how enemy shots skip
+    /// other enemies.
+    pub layer: u32,
+}
+
+/// Component: a thing in flight.
+#[derive(Component)]
+pub struct Projectile {
+    pub lifetime: CountupTimer,
+}
+
+/// Whether a hitbox first shows up on this frame of an animation variant,
+/// i.e. the moment a swing actually comes out.
+fn hitbox_starts_on(animation: &CharAnimation, state: &CharAnimationState, frame: usize) -> bool {
+    let Some(variant) = state
+        .variant
+        .as_ref()
+        .and_then(|name| animation.variants.get(name))
+    else {
+        return false;
+    };
+    let has_hitbox = |i: usize| variant.frames.get(i).is_some_and(|f| f.hitbox.is_some());
+    has_hitbox(frame) && (frame == 0 || !has_hitbox(frame - 1))
+}
+
+// ------- Systems -------
+
+/// When a player's attack animation reaches the frame where the slash comes
+/// out, fire a shot the way they're facing. Off unless it's turned on in the
+/// debug settings, since the kitty's a sword kitty by default.
+pub fn player_projectile_attack_system(
+    mut frame_events: EventReader<AnimateFrameEvent>,
+    player_q: Query<(&PlayerStateMachine, &CharAnimationState, &Motion), With<Player>>,
+    animations: Res<Assets<CharAnimation>>,
+    debugs: Res<DebugSettings>,
+    mut projectile_events: EventWriter<SpawnProjectile>,
+) {
+    for event in frame_events.read() {
+        if !debugs.player_projectiles {
+            continue;
+        }
+        let Ok((machine, anim_state, motion)) = player_q.get(event.entity) else {
+            continue;
+        };
+        if !matches!(machine.current(), PlayerState::Attack) {
+            continue;
+        }
+        let Some(animation) = animations.get(&anim_state.animation) else {
+            continue;
+        };
+        if hitbox_starts_on(animation, anim_state, event.frame) {
+            projectile_events.send(SpawnProjectile {
+                shooter: event.entity,
+                velocity: Vec2::from_angle(motion.facing) * PLAYER_PROJECTILE_SPEED,
+                lifetime_ms: PLAYER_PROJECTILE_LIFETIME_MS,
+                damage: PLAYER_PROJECTILE_DAMAGE,
+                layer: LAYER_PROJECTILE,
+            });
+        }
+    }
+}
+
+/// When a player pulls off a combo, do its special move.
+pub fn special_move_system(
+    mut combo_events: EventReader<ComboActivated>,
+    combo_assets: Res<Assets<ComboDefinition>>,
+    player_q: Query<&Motion, With<Player>>,
+    mut projectile_events: EventWriter<SpawnProjectile>,
+) {
+    for ComboActivated(combo, player) in combo_events.read() {
+        let Some(combo) = combo_assets.get(*combo) else {
+            continue;
+        };
+        let Ok(motion) = player_q.get(*player) else {
+            continue;
+        };
+        match combo.special {
+            SpecialMove::Fireball => {
+                projectile_events.send(SpawnProjectile {
+                    shooter: *player,
+                    velocity: Vec2::from_angle(motion.facing) * FIREBALL_SPEED,
+                    lifetime_ms: FIREBALL_LIFETIME_MS,
+                    damage: FIREBALL_DAMAGE,
+                    layer: LAYER_PROJECTILE,
+                });
+            },
+        }
+    }
+}
+
+/// Which way to shoot to hit a target where it's standing right now. If
+/// they're right on top of you, just shoot the way you're facing.
+fn aim_velocity(from: Vec2, to: Vec2, facing: f32, speed: f32) -> Vec2 {
+    let direction = (to - from)
+        .try_normalize()
+        .unwrap_or(Vec2::from_angle(facing));
+    direction * speed
+}
+
+/// When an enemy's ranged attack animation gets to its spitting frame, spit
+/// a shot at wherever its target is right now.
+pub fn enemy_projectile_attack_system(
+    mut frame_events: EventReader<AnimateFrameEvent>,
+    enemy_q: Query<(&EnemyStateMachine, &PhysTransform, &Motion)>,
+    target_q: Query<&PhysTransform>,
+    numbers: Res<NumbersSettings>,
+    mut projectile_events: EventWriter<SpawnProjectile>,
+) {
+    for event in frame_events.read() {
+        if event.frame != ENEMY_SPIT_FRAME {
+            continue;
+        }
+        let Ok((machine, transform, motion)) = enemy_q.get(event.entity) else {
+            continue;
+        };
+        let EnemyState::RangedAttack { target } = machine.current() else {
+            continue;
+        };
+        let Ok(target_transform) = target_q.get(*target) else {
+            continue;
+        };
+        projectile_events.send(SpawnProjectile {
+            shooter: event.entity,
+            velocity: aim_velocity(
+                transform.translation.truncate(),
+                target_transform.translation.truncate(),
+                motion.facing,
+                numbers.enemy_projectile_speed,
+            ),
+            lifetime_ms: ENEMY_PROJECTILE_LIFETIME_MS,
+            damage: ENEMY_PROJECTILE_DAMAGE,
+            layer: LAYER_ENEMY_PROJECTILE,
+        });
+    }
+}
+
+/// Spawn projectiles at their shooters. They go on the projectile layer, and
+/// they're on the shooter's team, if it has one.
+pub fn spawn_projectile_system(
+    mut projectile_events: EventReader<SpawnProjectile>,
+    shooter_q: Query<(&PhysTransform, Option<&Team>)>,
+    mut commands: Commands,
+) {
+    for event in projectile_events.read() {
+        let Ok((shooter_transform, team)) = shooter_q.get(event.shooter) else {
+            continue;
+        };
+        let location = shooter_transform.translation;
+        let mut projectile = commands.spawn((
+            Name::new("Projectile"),
+            Projectile {
+                lifetime: CountupTimer::new(Duration::from_millis(event.lifetime_ms)),
+            },
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::WHITE,
+                    custom_size: Some(Vec2::splat(PROJECTILE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(location),
+                ..default()
+            },
+            TopDownMatter::character(),
+            PhysTransform {
+                translation: location,
+            },
+            PhysOffset(Vec2::ZERO),
+            MobileFixed {
+                input: event.velocity.normalize_or_zero(),
+                face: true,
+            },
+            Speed(event.velocity.length()),
+            Motion::new(event.velocity),
+            Walkbox(centered_rect(PROJECTILE_SIZE, PROJECTILE_SIZE)),
+            Hitbox(Some(centered_rect(PROJECTILE_SIZE, PROJECTILE_SIZE))),
+            HitboxActive,
+            CollisionLayer(event.layer),
+            Damage(event.damage),
+            // It starts out overlapping whoever fired it, so count them as
+            // already hit.
+            HitThisSwing {
+                swing: None,
+                victims: HashSet::from([event.shooter]),
+            },
+        ));
+        if let Some(team) = team {
+            projectile.insert(*team);
+        }
+    }
+}
+
+/// Tick projectile lifetimes, and get rid of the ones that ran out of time
+/// or smacked into something solid.
+pub fn projectile_lifetime_system(
+    time: Res<Time>,
+    mut projectile_q: Query<(Entity, &mut Projectile, &Motion)>,
+    mut commands: Commands,
+) {
+    for (entity, mut projectile, motion) in projectile_q.iter_mut() {
+        projectile.lifetime.tick(time.delta());
+        let hit_wall = motion.result.as_ref().is_some_and(|r| r.collided);
+        if projectile.lifetime.finished() || hit_wall {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn projectiles_spawn_on_the_shooters_side_and_expire() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<Events<SpawnProjectile>>();
+        let shooter = world
+            .spawn((
+                PhysTransform {
+                    translation: Vec3::new(5.0, 5.0, 0.0),
+                },
+                Team::PLAYER,
+            ))
+            .id();
+        world.send_event(SpawnProjectile {
+            shooter,
+            velocity: Vec2::new(0.0, -100.0),
+            lifetime_ms: 500,
+            damage: 2,
+            layer: LAYER_ENEMY_PROJECTILE,
+        });
+        world.run_system_once(spawn_projectile_system);
+
+        let mut projectile_q = world.query::<(
+            Entity,
+            &Team,
+            &Speed,
+            &Damage,
+            &HitThisSwing,
+            &CollisionLayer,
+            &Projectile,
+        )>();
+        let (projectile, team, speed, damage, hit, layer, _) = projectile_q.single(&world);
+        assert_eq!(*team, Team::PLAYER);
+        assert_eq!(layer.0, LAYER_ENEMY_PROJECTILE);
+        assert_eq!(speed.0, 100.0);
+        assert_eq!(damage.0, 2);
+        assert!(hit.victims.contains(&shooter));
+        assert!(world.get::<HitboxActive>(projectile).is_some());
+
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(300));
+        world.run_system_once(projectile_lifetime_system);
+        assert!(world.get_entity(projectile).is_some());
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(300));
+        world.run_system_once(projectile_lifetime_system);
+        assert!(world.get_entity(projectile).is_none());
+    }
+
+    #[test]
+    fn ranged_attacks_spit_on_their_frame() {
+        let mut world = World::new();
+        world.init_resource::<NumbersSettings>();
+        world.init_resource::<Events<AnimateFrameEvent>>();
+        world.init_resource::<Events<SpawnProjectile>>();
+        let target = world
+            .spawn(PhysTransform {
+                translation: Vec3::new(0.0, 50.0, 0.0),
+            })
+            .id();
+        let mut machine = EnemyStateMachine::new(EnemyState::RangedAttack { target });
+        machine.do_transition(|_| ());
+        let spitter = world
+            .spawn((
+                machine,
+                PhysTransform {
+                    translation: Vec3::ZERO,
+                },
+                Motion::new(Vec2::ZERO),
+                Team::ENEMY,
+            ))
+            .id();
+        let spit_on = |world: &mut World, frame: usize| {
+            world.send_event(AnimateFrameEvent {
+                entity: spitter,
+                frame,
+            });
+            world.run_system_once(enemy_projectile_attack_system);
+            world.run_system_once(spawn_projectile_system);
+            world.resource_mut::<Events<AnimateFrameEvent>>().clear();
+            world.resource_mut::<Events<SpawnProjectile>>().clear();
+        };
+        let mut projectile_q = world.query::<(&Projectile, &MobileFixed, &Speed, &Team)>();
+
+        spit_on(&mut world, 0);
+        assert_eq!(projectile_q.iter(&world).count(), 0);
+        spit_on(&mut world, ENEMY_SPIT_FRAME);
+        let (_, heading, speed, team) = projectile_q.single(&world);
+        assert_eq!(*team, Team::ENEMY);
+        assert!(heading.input.abs_diff_eq(Vec2::Y, 0.001));
+        assert_eq!(speed.0, NumbersSettings::default().enemy_projectile_speed);
+    }
+
+    #[test]
+    fn quarter_circle_throws_a_fireball() {
+        let mut world = World::new();
+        world.init_resource::<Events<ComboActivated>>();
+        world.init_resource::<Events<SpawnProjectile>>();
+        let mut combo_assets = Assets::<ComboDefinition>::default();
+        let combo = combo_assets.add(ComboDefinition::new(Vec::new(), 500, SpecialMove::Fireball));
+        world.insert_resource(combo_assets);
+        let player = world.spawn((Player, Motion::new(Vec2::Y))).id();
+        world.send_event(ComboActivated(combo.id(), player));
+        world.run_system_once(special_move_system);
+
+        let events = world.resource::<Events<SpawnProjectile>>();
+        let mut reader = events.get_reader();
+        let shots: Vec<_> = reader.read(events).collect();
+        assert_eq!(shots.len(), 1);
+        assert_eq!(shots[0].shooter, player);
+        assert_eq!(shots[0].damage, FIREBALL_DAMAGE);
+        assert!(shots[0]
+            .velocity
+            .abs_diff_eq(Vec2::Y * FIREBALL_SPEED, 0.001));
+    }
+
+    #[test]
+    fn enemies_aim_where_you_are() {
+        let shot = aim_velocity(Vec2::ZERO, Vec2::new(30.0, 40.0), 0.0, 100.0);
+        assert!(shot.abs_diff_eq(Vec2::new(60.0, 80.0), 0.001));
+        // Point blank: use facing.
+        let shot = aim_velocity(Vec2::ONE, Vec2::ONE, std::f32::consts::FRAC_PI_2, 100.0);
+        assert!(shot.abs_diff_eq(Vec2::new(0.0, 100.0), 0.001));
+    }
+}
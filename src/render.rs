@@ -1,21 +1,40 @@
+use crate::behaviors::Invincible;
 use crate::char_animation::*;
 use crate::collision::AbsBBox;
+use crate::debug_settings::NumbersSettings;
+use crate::health::{Dying, TakeDamage};
+use crate::hud::HudCamera;
+use crate::phys_space::PhysTransform;
+use crate::toolbox::countup_timer::CountupTimer;
+use bevy::color::Mix;
 use bevy::prelude::*;
 use bevy::render::Extract;
 use bevy::sprite::ExtractedSprites;
 
+// The Z stack, back to front:
+// - LDtk layers: bevy_ecs_ldtk stacks them at 0, 1, 2... (so keep it to
+//   three or four layers, or they start poking through the floor).
+// - Floor (3.8): puddles, rugs, markers painted on the ground.
+// - Shadows (3.9): on the ground, but over the floor stuff.
+// - Dudes (4.0 - 50.0): characters and props, sorted by Y so lower on
+//   screen draws in front.
+// - Overlay (60.0): damage numbers and other floaty feedback.
 const DEPTH_DUDES_MIN: f32 = 4.0;
 const DEPTH_DUDES_MAX: f32 = 50.0;
 const DEPTH_DUDES_RANGE: f32 = DEPTH_DUDES_MAX - DEPTH_DUDES_MIN;
 const DEPTH_SHADOWS: f32 = DEPTH_DUDES_MIN - 0.1;
+const DEPTH_FLOOR: f32 = DEPTH_SHADOWS - 0.1;
 const VIEW_SLOP: f32 = 64.0;
+/// In front of all the dudes, for floaty feedback stuff like damage numbers.
+pub const DEPTH_OVERLAY: f32 = DEPTH_DUDES_MAX + 10.0;
 
 fn lerp_dudes_z(t: f32) -> f32 {
     DEPTH_DUDES_MIN + DEPTH_DUDES_RANGE * t
 }
 
-/// Some spatial details about an entity.
-#[derive(Component, Reflect)]
+/// Some spatial details about an entity. Defaults to character().
+#[derive(Component, Reflect, Default, Clone, PartialEq, Debug)]
+#[reflect(Default)]
 pub struct TopDownMatter {
     /// How the global draw depth should be determined. Depth is calculated
     /// differently for different kinds of stuff. A render
@@ -28,10 +47,13 @@ pub struct TopDownMatter {
     pub ignore_height: bool,
 }
 
-#[derive(Reflect)]
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TopDownDepthClass {
+    #[default]
     Character,
     Shadow,
+    /// Flat on the ground, under even the shadows.
+    Floor,
 }
 
 impl TopDownMatter {
@@ -47,11 +69,11 @@ impl TopDownMatter {
             ignore_height: true,
         }
     }
-}
-
-impl Default for TopDownMatter {
-    fn default() -> Self {
-        Self::character()
+    pub fn floor() -> Self {
+        Self {
+            depth_class: TopDownDepthClass::Floor,
+            ignore_height: true,
+        }
     }
 }
 
@@ -92,6 +114,128 @@ impl ShadowSpriteBundle {
     }
 }
 
+/// Height at which a shadow's down to half size. About the top of a bonk
+/// (65²/2·255, from BONK_Z_VELOCITY and LAUNCH_GRAVITY).
+const SHADOW_HALF_SIZE_HEIGHT: f32 = 8.0;
+/// Shadows never get smaller than this, no matter how high you go.
+const SHADOW_MIN_SCALE: f32 = 0.2;
+
+/// How big a shadow should be when its caster is `height` off the ground.
+fn shadow_scale(height: f32) -> f32 {
+    (1.0 / (1.0 + height.max(0.0) / SHADOW_HALF_SIZE_HEIGHT)).max(SHADOW_MIN_SCALE)
+}
+
+/// Shrink shadows as their casters get higher off the ground.
+pub fn shadow_scale_system(
+    caster_q: Query<(&PhysTransform, &Children), With<HasShadow>>,
+    mut shadow_q: Query<&mut Transform, With<ShadowSprite>>,
+) {
+    for (transform, children) in caster_q.iter() {
+        let scale = shadow_scale(transform.translation.z);
+        for &child in children.iter() {
+            if let Ok(mut shadow_transform) = shadow_q.get_mut(child) {
+                shadow_transform.scale = Vec3::new(scale, scale, 1.0);
+            }
+        }
+    }
+}
+
+/// Default for NumbersSettings::shadow_fade_height. Roughly the peak of a big
+/// knockback launch; bonks only get about a quarter of the way there.
+pub const SHADOW_FADE_HEIGHT: f32 = 32.0;
+
+/// How see-through a shadow should be when its caster is `height` off the
+/// ground: solid on the floor, gone at `fade_height`.
+fn shadow_alpha(height: f32, fade_height: f32) -> f32 {
+    if fade_height <= 0.0 {
+        return 1.0;
+    }
+    (1.0 - height / fade_height).clamp(0.0, 1.0)
+}
+
+/// Fade shadows out as their casters get higher off the ground.
+pub fn shadow_opacity_system(
+    caster_q: Query<(&PhysTransform, &Children), With<HasShadow>>,
+    mut shadow_q: Query<&mut Sprite, With<ShadowSprite>>,
+    numbers: Res<NumbersSettings>,
+) {
+    for (transform, children) in caster_q.iter() {
+        let alpha = shadow_alpha(transform.translation.z, numbers.shadow_fade_height);
+        for &child in children.iter() {
+            if let Ok(mut sprite) = shadow_q.get_mut(child) {
+                sprite.color.set_alpha(alpha);
+            }
+        }
+    }
+}
+
+/// Sprite tint right when something gets hit. (Sprite colors multiply the
+/// texture, so a white flash wouldn't show up.)
+pub const DAMAGE_FLASH_COLOR: Color = Color::srgb(1.0, 0.25, 0.25);
+pub const DAMAGE_FLASH_SECS: f32 = 0.25;
+
+/// Component: tint the sprite, then fade back to its usual color. Remembers
+/// what that color was, so sprites that aren't plain white stay that way.
+#[derive(Component)]
+pub struct DamageFlash {
+    pub color: Color,
+    pub duration: CountupTimer,
+    base_color: Color,
+}
+
+impl DamageFlash {
+    pub fn new(color: Color, secs: f32, base_color: Color) -> Self {
+        Self {
+            color,
+            duration: CountupTimer::from_seconds(secs),
+            base_color,
+        }
+    }
+}
+
+/// Flash anything with a sprite when it takes a hit. A hit mid-flash starts
+/// over, but keeps the original base color.
+pub fn damage_flash_start_system(
+    mut damage_events: EventReader<TakeDamage>,
+    target_q: Query<(&Sprite, Option<&DamageFlash>), (Without<Invincible>, Without<Dying>)>,
+    mut commands: Commands,
+) {
+    for damage in damage_events.read() {
+        if damage.amount <= 0 {
+            continue;
+        }
+        let Ok((sprite, flash)) = target_q.get(damage.target) else {
+            continue;
+        };
+        let base_color = flash.map_or(sprite.color, |f| f.base_color);
+        commands.entity(damage.target).try_insert(DamageFlash::new(
+            DAMAGE_FLASH_COLOR,
+            DAMAGE_FLASH_SECS,
+            base_color,
+        ));
+    }
+}
+
+/// Fade flashing sprites back to normal, and put the real color back exactly
+/// once the timer's up.
+pub fn damage_flash_system(
+    time: Res<Time>,
+    mut flash_q: Query<(Entity, &mut Sprite, &mut DamageFlash)>,
+    mut commands: Commands,
+) {
+    for (entity, mut sprite, mut flash) in flash_q.iter_mut() {
+        flash.duration.tick(time.delta());
+        if flash.duration.finished() {
+            sprite.color = flash.base_color;
+            commands.entity(entity).remove::<DamageFlash>();
+        } else {
+            sprite.color = flash
+                .base_color
+                .mix(&flash.color, flash.duration.percent_left());
+        }
+    }
+}
+
 /// Attach shadow sprite child entities to anything new that HasShadow.
 pub fn shadow_stitcher_system(
     mut shadow_handle: Local<Option<Handle<CharAnimation>>>,
@@ -126,7 +270,9 @@ pub fn shadow_stitcher_system(
 /// Counterpoint: this is small.
 pub fn extract_and_flatten_space_system(
     has_z_query: Extract<Query<(Entity, &TopDownMatter)>>,
-    camera_query: Extract<Query<(&OrthographicProjection, &GlobalTransform), With<Camera2d>>>,
+    camera_query: Extract<
+        Query<(&OrthographicProjection, &GlobalTransform), (With<Camera2d>, Without<HudCamera>)>,
+    >,
     mut extracted_sprites: ResMut<ExtractedSprites>,
 ) {
     // ok, my theory goes like this:
@@ -136,24 +282,30 @@ pub fn extract_and_flatten_space_system(
     //   in the Y band.
     // So, first, sort out the viewport.
     let y_frac = {
-        // I'm gonna be dumb and assume there's one camera, for now. call me once there's not.
+        // I'm gonna be dumb and assume there's one gameplay camera, for now.
+        // (The HUD camera doesn't count; it's not looking at the world.)
         let Ok((projection, cam_transform)) = camera_query.get_single() else {
             warn!("camera_qurey.get_single exploded in extract_and_flatten_space");
             return;
         };
-        let viewport = AbsBBox::from_rect(projection.area, cam_transform.translation().truncate());
-        let min_y = viewport.min.y - VIEW_SLOP;
-        let max_y = viewport.max.y + VIEW_SLOP;
-        let y_size = max_y - min_y;
+        let viewport = AbsBBox::from_rect(projection.area, cam_transform.translation().truncate())
+            .expand(VIEW_SLOP);
+        let max_y = viewport.max.y;
+        let y_size = viewport.size().y;
 
         move |y: f32| (max_y - y) / y_size
     };
 
     // NICE, ExtractedSprites uses EntityHashMap now, so I only
     // need to iterate over sprites that are topdown-matter.
+    // No need to cull anything here, either: bevy_sprite already frustum-culls
+    // by each sprite's Aabb before extraction, so anything offscreen just
+    // misses the lookup below and never gets its depth worked out.
     for (entity, matter) in has_z_query.iter() {
         if let Some(ex_sprite) = extracted_sprites.sprites.get_mut(&entity) {
-            let mut translation = ex_sprite.transform.translation();
+            // Hang onto scale and rotation; shadows shrink, for one.
+            let (scale, rotation, mut translation) =
+                ex_sprite.transform.to_scale_rotation_translation();
             let depth = match matter.depth_class {
                 TopDownDepthClass::Character => {
                     // OK, I think we can just yolo this without bounds-checking,
@@ -161,12 +313,88 @@ pub fn extract_and_flatten_space_system(
                     lerp_dudes_z(y_frac(translation.y))
                 },
                 TopDownDepthClass::Shadow => DEPTH_SHADOWS,
+                TopDownDepthClass::Floor => DEPTH_FLOOR,
             };
             if !matter.ignore_height {
                 translation.y += translation.z;
             }
             translation.z = depth;
-            ex_sprite.transform = Transform::from_translation(translation).into();
+            ex_sprite.transform = Transform {
+                translation,
+                rotation,
+                scale,
+            }
+            .into();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damage_flash_fades_out_completely() {
+        use bevy::ecs::system::RunSystemOnce;
+        use bevy::utils::Duration;
+
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<Events<TakeDamage>>();
+        let base = Color::srgb(0.2, 0.4, 0.6);
+        let guy = world
+            .spawn(Sprite {
+                color: base,
+                ..default()
+            })
+            .id();
+        let step = |world: &mut World, secs: f32| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(secs));
+            world.run_system_once(damage_flash_system);
+            world.get::<Sprite>(guy).unwrap().color
+        };
+        world.send_event(TakeDamage {
+            target: guy,
+            amount: 1,
+            source: None,
+        });
+        world.run_system_once(damage_flash_start_system);
+
+        let tinted = step(&mut world, DAMAGE_FLASH_SECS / 2.0);
+        assert_ne!(tinted, base);
+        assert_ne!(tinted, DAMAGE_FLASH_COLOR);
+        assert!(world.get::<DamageFlash>(guy).is_some());
+
+        assert_eq!(step(&mut world, DAMAGE_FLASH_SECS / 2.0), base);
+        assert!(world.get::<DamageFlash>(guy).is_none());
+        // And it stays put.
+        assert_eq!(step(&mut world, 1.0), base);
+    }
+
+    #[test]
+    fn shadows_shrink_with_height() {
+        assert_eq!(shadow_scale(0.0), 1.0);
+        assert_eq!(shadow_scale(-5.0), 1.0);
+        assert_eq!(shadow_scale(SHADOW_HALF_SIZE_HEIGHT), 0.5);
+        assert_eq!(shadow_scale(10_000.0), SHADOW_MIN_SCALE);
+    }
+
+    #[test]
+    fn shadows_fade_with_height() {
+        assert_eq!(shadow_alpha(0.0, 32.0), 1.0);
+        assert_eq!(shadow_alpha(-4.0, 32.0), 1.0);
+        assert_eq!(shadow_alpha(8.0, 32.0), 0.75);
+        assert_eq!(shadow_alpha(32.0, 32.0), 0.0);
+        assert_eq!(shadow_alpha(100.0, 32.0), 0.0);
+        // Zero means fading's turned off.
+        assert_eq!(shadow_alpha(100.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn default_matter_is_a_character() {
+        assert_eq!(TopDownMatter::default(), TopDownMatter::character());
+        assert_ne!(TopDownMatter::default(), TopDownMatter::shadow());
+    }
+}
@@ -0,0 +1,401 @@
+//! Saving your progress. Walking into a checkpoint writes how player 1's
+//! doing and where the checkpoint is to a JSON file; on startup we read it
+//! back and drop the players at that checkpoint.
+
+use crate::{
+    assets_setup::SoundEffects,
+    collision::{AbsBBox, Walkbox},
+    health::Health,
+    input::PlayerIndex,
+    phys_space::PhysTransform,
+    render::TopDownMatter,
+    sounds::{play_sfx, SfxConfig, SfxSpawners, SoundCooldown},
+    walls::{ldtk_entity_phys_location, rect_around_pivot},
+    Player,
+};
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const SAVE_PATH: &str = "./savegame.json";
+
+/// Checkpoints are a faint glow on the floor until you touch one.
+const CHECKPOINT_COLOR: Color = Color::srgba(0.26, 0.77, 0.96, 0.3);
+const CHECKPOINT_LIT_COLOR: Color = Color::srgba(0.26, 0.77, 0.96, 0.8);
+
+pub struct SaveGamePlugin;
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveGame>()
+            .register_ldtk_entity::<CheckpointBundle>("Checkpoint")
+            .add_systems(Startup, load_system)
+            .add_systems(
+                Update,
+                (
+                    checkpoint_trigger_system,
+                    checkpoint_confirmation_system.in_set(SfxSpawners),
+                    save_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Everything that survives a restart.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SaveData {
+    pub level_index: usize,
+    #[serde(with = "vec2_pair")]
+    pub player_pos: Vec2,
+    pub player_health: i32,
+    /// The checkpoint we saved at, if any. Takes priority over player_pos
+    /// and level_index when loading.
+    #[serde(default)]
+    pub checkpoint: Option<SavedCheckpoint>,
+}
+
+/// Where a checkpoint is: which level (by LDtk iid, since that survives
+/// levels getting reordered), and where in it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedCheckpoint {
+    pub level_iid: String,
+    #[serde(with = "vec2_pair")]
+    pub location: Vec2,
+}
+
+impl SaveData {
+    /// Read a save file. No file just means nobody's saved yet, so that's
+    /// a quiet None; a busted file gets a complaint, but is also a None.
+    pub fn load(path: impl AsRef<Path>) -> Option<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&text)
+            .inspect_err(|e| warn!("Couldn't parse {}, starting fresh: {}", path.display(), e))
+            .ok()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Which level to load up.
+    pub fn level_selection(&self) -> LevelSelection {
+        match &self.checkpoint {
+            Some(checkpoint) => LevelSelection::Iid(LevelIid::new(checkpoint.level_iid.clone())),
+            None => LevelSelection::index(self.level_index),
+        }
+    }
+
+    /// Where to put the player once the level's in.
+    pub fn respawn_point(&self) -> Vec2 {
+        match &self.checkpoint {
+            Some(checkpoint) => checkpoint.location,
+            None => self.player_pos,
+        }
+    }
+
+    /// The player's health, as saved. If you somehow saved while dead,
+    /// you get a fresh start instead of an instant game over.
+    pub fn restore_health(&self, max: i32) -> Health {
+        if self.player_health > 0 {
+            Health {
+                current: self.player_health.min(max),
+                max,
+            }
+        } else {
+            Health::new(max)
+        }
+    }
+}
+
+/// Resource: the save we started from, if there was one. setup_player
+/// checks for it.
+#[derive(Resource, Debug)]
+pub struct LoadedSaveData(pub SaveData);
+
+/// Event: write the game to disk, remembering the checkpoint it happened at
+/// (if there was one).
+#[derive(Event, Clone, Debug, Default)]
+pub struct SaveGame {
+    pub checkpoint: Option<SavedCheckpoint>,
+}
+
+/// Component: a spot on the floor that saves the game when a player walks
+/// into it. `rect` is relative to the PhysTransform, same as a walkbox.
+#[derive(Component, Debug)]
+pub struct Checkpoint {
+    pub rect: Rect,
+}
+
+/// Component: which player set off this checkpoint. Once it's there, the
+/// checkpoint's spent (until the level gets reloaded).
+#[derive(Component, Debug)]
+pub struct TriggeredBy(pub Entity);
+
+#[derive(Bundle)]
+pub struct CheckpointBundle {
+    checkpoint: Checkpoint,
+    phys_transform: PhysTransform,
+    sprite: Sprite,
+    texture: Handle<Image>,
+    top_down_matter: TopDownMatter,
+}
+
+impl LdtkEntity for CheckpointBundle {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _tileset: Option<&Handle<Image>>,
+        _tileset_definition: Option<&TilesetDefinition>,
+        _asset_server: &AssetServer,
+        _texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) -> Self {
+        let size = Vec2::new(entity_instance.width as f32, entity_instance.height as f32);
+        CheckpointBundle {
+            checkpoint: Checkpoint {
+                rect: rect_around_pivot(size, entity_instance.pivot),
+            },
+            phys_transform: PhysTransform {
+                translation: ldtk_entity_phys_location(entity_instance, layer_instance).extend(0.0),
+            },
+            sprite: Sprite {
+                color: CHECKPOINT_COLOR,
+                custom_size: Some(size),
+                ..default()
+            },
+            texture: Handle::default(),
+            top_down_matter: TopDownMatter::floor(),
+        }
+    }
+}
+
+/// Serde helpers for Vec2, same deal as the key names: glam's serde impls
+/// are behind a feature flag we don't build with. It's an [x, y] pair.
+mod vec2_pair {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(v: &Vec2, serializer: S) -> Result<S::Ok, S::Error> {
+        [v.x, v.y].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec2, D::Error> {
+        let [x, y] = <[f32; 2]>::deserialize(deserializer)?;
+        Ok(Vec2::new(x, y))
+    }
+}
+
+// ------- Systems -------
+
+/// Pick up a save file if there is one, and head for the level it's on.
+pub fn load_system(mut level_selection: ResMut<LevelSelection>, mut commands: Commands) {
+    if let Some(data) = SaveData::load(SAVE_PATH) {
+        info!("Loaded save from {}", SAVE_PATH);
+        *level_selection = data.level_selection();
+        commands.insert_resource(LoadedSaveData(data));
+    }
+}
+
+/// When a player steps onto a fresh checkpoint, save there and mark it spent.
+pub fn checkpoint_trigger_system(
+    checkpoint_q: Query<(Entity, &Checkpoint, &PhysTransform), Without<TriggeredBy>>,
+    player_q: Query<(Entity, &Walkbox, &PhysTransform), With<Player>>,
+    parent_q: Query<&Parent>,
+    level_q: Query<&LevelIid>,
+    mut saves: EventWriter<SaveGame>,
+    mut commands: Commands,
+) {
+    for (entity, checkpoint, transform) in checkpoint_q.iter() {
+        let location = transform.translation.truncate();
+        let zone = AbsBBox::from_rect(checkpoint.rect, location);
+        let Some((player, _, _)) = player_q.iter().find(|(_, walkbox, player_transform)| {
+            zone.collide(player_transform.to_absbbox(walkbox))
+        }) else {
+            continue;
+        };
+        // Checkpoint -> entity layer -> level.
+        let level_iid = parent_q
+            .iter_ancestors(entity)
+            .find_map(|ancestor| level_q.get(ancestor).ok());
+        saves.send(SaveGame {
+            checkpoint: level_iid.map(|iid| SavedCheckpoint {
+                level_iid: iid.to_string(),
+                location,
+            }),
+        });
+        commands.entity(entity).insert(TriggeredBy(player));
+    }
+}
+
+/// Light up checkpoints when they go off, with a little jingle.
+pub fn checkpoint_confirmation_system(
+    mut checkpoint_q: Query<
+        (Entity, &TriggeredBy, &mut Sprite, &PhysTransform),
+        Added<TriggeredBy>,
+    >,
+    sfx: Res<SoundEffects>,
+    sfx_config: Res<SfxConfig>,
+    mut cooldowns: ResMut<SoundCooldown>,
+    mut commands: Commands,
+) {
+    for (entity, triggered_by, mut sprite, transform) in checkpoint_q.iter_mut() {
+        info!("{:?} lit checkpoint {:?}", triggered_by.0, entity);
+        sprite.color = CHECKPOINT_LIT_COLOR;
+        play_sfx(
+            &mut commands,
+            &mut cooldowns,
+            &sfx_config,
+            &sfx.checkpoint_sfx,
+            Some(transform.translation.truncate()),
+        );
+    }
+}
+
+/// Write player 1's state to disk when asked.
+pub fn save_system(
+    mut save_events: EventReader<SaveGame>,
+    level_selection: Res<LevelSelection>,
+    player_q: Query<(&PlayerIndex, &PhysTransform, &Health), With<Player>>,
+) {
+    let Some(save) = save_events.read().last() else {
+        return;
+    };
+    let Some((_, transform, health)) = player_q
+        .iter()
+        .find(|(index, _, _)| **index == PlayerIndex::P1)
+    else {
+        return;
+    };
+    // Only matters for saves that didn't come from a checkpoint.
+    let level_index = match *level_selection {
+        LevelSelection::Indices(LevelIndices { level, .. }) => level,
+        _ => 0,
+    };
+    let data = SaveData {
+        level_index,
+        player_pos: transform.translation.truncate(),
+        player_health: health.current,
+        checkpoint: save.checkpoint.clone(),
+    };
+    if let Err(e) = data.save(SAVE_PATH) {
+        warn!("Couldn't save game: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> SaveData {
+        SaveData {
+            level_index: 2,
+            player_pos: Vec2::new(16.5, -40.0),
+            player_health: 4,
+            checkpoint: None,
+        }
+    }
+
+    #[test]
+    fn saves_round_trip_through_disk() {
+        let path = std::env::temp_dir().join("tablestakes_save_round_trip.json");
+        data().save(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("\"player_pos\""));
+        assert_eq!(SaveData::load(&path), Some(data()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn checkpoint_saves_win_out() {
+        let mut save = data();
+        assert_eq!(save.level_selection(), LevelSelection::index(2));
+        assert_eq!(save.respawn_point(), Vec2::new(16.5, -40.0));
+        save.checkpoint = Some(SavedCheckpoint {
+            level_iid: "level-b".to_string(),
+            location: Vec2::new(100.0, 20.0),
+        });
+        assert_eq!(
+            save.level_selection(),
+            LevelSelection::Iid(LevelIid::new("level-b"))
+        );
+        assert_eq!(save.respawn_point(), Vec2::new(100.0, 20.0));
+        // Older saves without the field still load.
+        let old: SaveData = serde_json::from_str(
+            r#"{ "level_index": 1, "player_pos": [1.0, 2.0], "player_health": 3 }"#,
+        )
+        .unwrap();
+        assert_eq!(old.checkpoint, None);
+    }
+
+    #[test]
+    fn checkpoints_only_go_off_once() {
+        let mut app = App::new();
+        app.add_event::<SaveGame>()
+            .add_systems(Update, checkpoint_trigger_system);
+        let level = app.world_mut().spawn(LevelIid::new("level-a")).id();
+        app.world_mut()
+            .spawn((
+                Checkpoint {
+                    rect: rect_around_pivot(Vec2::new(32.0, 32.0), Vec2::new(0.5, 1.0)),
+                },
+                PhysTransform {
+                    translation: Vec3::new(50.0, 50.0, 0.0),
+                },
+            ))
+            .set_parent(level);
+        let player = app
+            .world_mut()
+            .spawn((
+                Player,
+                Walkbox(Rect::new(-4.0, 0.0, 4.0, 4.0)),
+                PhysTransform {
+                    translation: Vec3::new(50.0, 60.0, 0.0),
+                },
+            ))
+            .id();
+
+        // In, out, and back in again.
+        let mut reader = app.world().resource::<Events<SaveGame>>().get_reader();
+        let mut saves = Vec::new();
+        for y in [60.0, 200.0, 60.0] {
+            app.world_mut()
+                .get_mut::<PhysTransform>(player)
+                .unwrap()
+                .translation
+                .y = y;
+            app.update();
+            let events = app.world().resource::<Events<SaveGame>>();
+            saves.extend(reader.read(events).cloned());
+        }
+        assert_eq!(saves.len(), 1);
+        assert_eq!(
+            saves[0].checkpoint,
+            Some(SavedCheckpoint {
+                level_iid: "level-a".to_string(),
+                location: Vec2::new(50.0, 50.0),
+            })
+        );
+    }
+
+    #[test]
+    fn missing_or_busted_saves_load_as_nothing() {
+        let path = std::env::temp_dir().join("tablestakes_save_busted.json");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(SaveData::load(&path), None);
+        std::fs::write(&path, "{ \"level_index\": ").unwrap();
+        assert_eq!(SaveData::load(&path), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn saved_health_stays_in_bounds() {
+        let mut save = data();
+        assert_eq!(save.restore_health(6), Health { current: 4, max: 6 });
+        save.player_health = 10;
+        assert_eq!(save.restore_health(6), Health { current: 6, max: 6 });
+        save.player_health = 0;
+        assert_eq!(save.restore_health(6), Health::new(6));
+    }
+}
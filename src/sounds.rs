@@ -1,27 +1,299 @@
-use bevy::{audio::PlaybackMode, prelude::*};
+use bevy::{
+    audio::{PlaybackMode, SpatialScale, Volume},
+    prelude::*,
+    utils::HashMap,
+};
 
-use crate::{assets_setup::SoundEffects, movement::Landed};
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{
+    assets_setup::{SoundEffects, TerrainSound},
+    entity_states::{EnemyState, EnemyStateMachine, PlayerState, PlayerStateMachine},
+    movement::Landed,
+    phys_space::PhysTransform,
+    toolbox::countup_timer::CountupTimer,
+    walls::int_cell_phys_offset,
+};
+
+/// Systems that spawn sound effects (i.e. anything that calls play_sfx).
+/// spatial_audio_system runs after all of them.
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SfxSpawners;
 
 /// Marker struct for audio sink entities that play sound effects. There can be many of these.
 #[derive(Component)]
 pub struct SfxSink;
 
+/// Knobs for sound effects.
+#[derive(Resource, Reflect)]
+pub struct SfxConfig {
+    /// Sounds farther than this from the camera (in world pixels) are silent,
+    /// and they fade out linearly on the way there.
+    pub max_audible_distance: f32,
+    /// Minimum time between two plays of the same sound, in seconds.
+    pub default_cooldown_secs: f32,
+    /// Per-sound exceptions to the default cooldown.
+    #[reflect(ignore)]
+    pub cooldown_overrides: HashMap<AssetId<AudioSource>, f32>,
+}
+
+impl Default for SfxConfig {
+    fn default() -> Self {
+        Self {
+            max_audible_distance: 240.0,
+            default_cooldown_secs: 0.05,
+            cooldown_overrides: HashMap::new(),
+        }
+    }
+}
+
+impl SfxConfig {
+    pub fn cooldown_for(&self, id: AssetId<AudioSource>) -> f32 {
+        self.cooldown_overrides
+            .get(&id)
+            .copied()
+            .unwrap_or(self.default_cooldown_secs)
+    }
+}
+
+/// Which sounds have played recently, so ten enemies getting bonked on the same
+/// frame don't stack up ten copies of the same noise. Maps each sound to its
+/// cooldown timer and how many times something's tried to play it since the
+/// cooldown started.
+#[derive(Resource, Default)]
+pub struct SoundCooldown(pub HashMap<AssetId<AudioSource>, (Timer, u8)>);
+
+impl SoundCooldown {
+    /// Whether a sound's allowed to play right now. If so, starts its cooldown.
+    pub fn try_play(&mut self, id: AssetId<AudioSource>, cooldown_secs: f32) -> bool {
+        match self.0.get_mut(&id) {
+            Some((timer, count)) if !timer.finished() => {
+                *count = count.saturating_add(1);
+                false
+            },
+            _ => {
+                let timer = Timer::from_seconds(cooldown_secs, TimerMode::Once);
+                self.0.insert(id, (timer, 1));
+                true
+            },
+        }
+    }
+}
+
+/// Tick sound cooldowns, and forget the ones that are done.
+pub fn sound_cooldown_system(time: Res<Time>, mut cooldowns: ResMut<SoundCooldown>) {
+    cooldowns
+        .0
+        .retain(|_, (timer, _)| !timer.tick(time.delta()).finished());
+}
+
+/// Play a one-shot sound effect, unless it's still cooling down from the last
+/// time. If you know where it's happening, it gets positioned in the world.
+pub fn play_sfx(
+    commands: &mut Commands,
+    cooldowns: &mut SoundCooldown,
+    config: &SfxConfig,
+    source: &Handle<AudioSource>,
+    position: Option<Vec2>,
+) {
+    if !cooldowns.try_play(source.id(), config.cooldown_for(source.id())) {
+        return;
+    }
+    match position {
+        Some(loc) => {
+            commands.spawn(SpatialSfxBundle::new(source.clone(), loc));
+        },
+        None => {
+            commands.spawn(AudioSourceBundle {
+                source: source.clone(),
+                settings: PlaybackSettings {
+                    mode: PlaybackMode::Despawn,
+                    ..Default::default()
+                },
+            });
+        },
+    }
+}
+
+/// How far apart the camera's ears are, in world pixels. Wider = more
+/// dramatic panning.
+pub const SFX_EAR_GAP: f32 = 96.0;
+
+/// Where in the world a sound effect is coming from.
+#[derive(Component)]
+pub struct WorldPosition(pub Vec2);
+
+/// A one-shot sound effect that happens somewhere in particular. Gets panned
+/// (via bevy's spatial audio, with the camera as listener) and faded with
+/// distance by spatial_audio_system before it starts playing.
+#[derive(Bundle)]
+pub struct SpatialSfxBundle {
+    pub audio: AudioSourceBundle,
+    pub position: WorldPosition,
+    pub transform: TransformBundle,
+}
+
+impl SpatialSfxBundle {
+    pub fn new(source: Handle<AudioSource>, position: Vec2) -> Self {
+        Self {
+            audio: AudioSourceBundle {
+                source,
+                settings: PlaybackSettings {
+                    mode: PlaybackMode::Despawn,
+                    spatial: true,
+                    ..Default::default()
+                },
+            },
+            position: WorldPosition(position),
+            // Audio playback reads the GlobalTransform, possibly before
+            // propagation's had a chance to run, so set both up front.
+            transform: TransformBundle {
+                local: Transform::from_translation(position.extend(0.0)),
+                global: GlobalTransform::from_translation(position.extend(0.0)),
+            },
+        }
+    }
+}
+
+/// Set up newly spawned positional sounds relative to the camera: fade them
+/// with distance, and scale space so the built-in spatial audio pans them
+/// nicely without also doing its own (very steep) distance falloff. Runs in
+/// PostUpdate, after every SfxSpawners system and before transform
+/// propagation (which bevy's audio playback waits on).
+pub fn spatial_audio_system(
+    mut sfx_q: Query<(&WorldPosition, &mut PlaybackSettings), Added<WorldPosition>>,
+    camera_q: Query<&PhysTransform, With<SpatialListener>>,
+    config: Res<SfxConfig>,
+) {
+    let Ok(camera_tf) = camera_q.get_single() else {
+        return;
+    };
+    let camera_pos = camera_tf.translation.truncate();
+    let max_distance = config.max_audible_distance.max(1.0);
+    for (position, mut settings) in sfx_q.iter_mut() {
+        let distance = position.0.distance(camera_pos);
+        let falloff = (1.0 - distance / max_distance).clamp(0.0, 1.0);
+        settings.volume = Volume::new(falloff);
+        // Flatten Z (the camera's way up there) and shrink everything audible
+        // into a unit radius, where the spatial sink doesn't attenuate.
+        settings.spatial_scale = Some(SpatialScale::new_2d(1.0 / max_distance));
+    }
+}
+
 /// Thump on landings
 pub fn sounds_thumps(
     mut landings: EventReader<Landed>,
     mut commands: Commands,
     sfx: Res<SoundEffects>,
+    location_q: Query<&PhysTransform>,
+    mut cooldowns: ResMut<SoundCooldown>,
+    config: Res<SfxConfig>,
 ) {
-    // Eventually want to locate these in space maybe?? but crawl before u run.
-    // I don't care about how many landings happen this frame, so just burn em all at once.
-    if landings.read().count() > 0 {
-        commands.spawn(AudioSourceBundle {
-            source: sfx.thump.clone(),
-            settings: PlaybackSettings {
-                mode: PlaybackMode::Despawn,
-                ..Default::default()
-            },
-        });
-        // wow, hmm, that was easy.
+    for landed in landings.read() {
+        let location = location_q
+            .get(landed.0)
+            .ok()
+            .map(|t| t.translation.truncate());
+        play_sfx(&mut commands, &mut cooldowns, &config, &sfx.thump, location);
+    }
+}
+
+/// Time between footsteps while walking around.
+const FOOTSTEP_INTERVAL_SECS: f32 = 0.3;
+
+/// Pitter patter. Anything that's running (player) or patrolling (enemies)
+/// makes a footstep noise every so often, depending on what terrain it's on.
+#[allow(clippy::too_many_arguments)]
+pub fn footstep_system(
+    walker_q: Query<(
+        Entity,
+        &PhysTransform,
+        Option<&PlayerStateMachine>,
+        Option<&EnemyStateMachine>,
+    )>,
+    cell_q: Query<(&IntGridCell, &Transform, &Parent)>,
+    layer_q: Query<&LayerMetadata>,
+    mut step_timers: Local<HashMap<Entity, CountupTimer>>,
+    time: Res<Time>,
+    sfx: Res<SoundEffects>,
+    terrain_sounds: Res<TerrainSound>,
+    config: Res<SfxConfig>,
+    mut cooldowns: ResMut<SoundCooldown>,
+    mut commands: Commands,
+) {
+    let mut still_walking: Vec<Entity> = Vec::new();
+    for (entity, transform, player, enemy) in walker_q.iter() {
+        let walking = player.is_some_and(|m| matches!(m.current(), PlayerState::Run))
+            || enemy.is_some_and(|m| matches!(m.current(), EnemyState::Patrol { .. }));
+        if !walking {
+            continue;
+        }
+        still_walking.push(entity);
+
+        let timer = step_timers
+            .entry(entity)
+            .or_insert_with(|| CountupTimer::from_seconds(FOOTSTEP_INTERVAL_SECS));
+        if !timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        timer.reset();
+
+        let loc = transform.translation.truncate();
+        let sound = terrain_at(loc, &cell_q, &layer_q)
+            .and_then(|value| terrain_sounds.get(&value))
+            .unwrap_or(&sfx.footstep_sfx);
+        play_sfx(&mut commands, &mut cooldowns, &config, sound, Some(loc));
+    }
+    // Forget anyone who stopped (or despawned), so they start fresh next time.
+    step_timers.retain(|e, _| still_walking.contains(e));
+}
+
+/// Find the value of the terrain cell under a phys-space location, if any.
+/// Linear scan, but footsteps are rare enough that it's no big deal.
+fn terrain_at(
+    loc: Vec2,
+    cell_q: &Query<(&IntGridCell, &Transform, &Parent)>,
+    layer_q: &Query<&LayerMetadata>,
+) -> Option<i32> {
+    cell_q.iter().find_map(|(cell, transform, parent)| {
+        let layer = layer_q.get(parent.get()).ok()?;
+        if layer.identifier != TerrainSound::LAYER {
+            return None;
+        }
+        let offset = int_cell_phys_offset(
+            layer.grid_size,
+            layer.px_total_offset_x,
+            layer.px_total_offset_y,
+        );
+        let center = transform.translation.truncate() + offset;
+        let half_grid = layer.grid_size as f32 / 2.0;
+        let d = (loc - center).abs();
+        (d.x <= half_grid && d.y <= half_grid).then_some(cell.value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::utils::Duration;
+
+    #[test]
+    fn cooldown_blocks_spam_then_resets() {
+        let mut cooldowns = SoundCooldown::default();
+        let id = AssetId::<AudioSource>::default();
+        assert!(cooldowns.try_play(id, 0.1));
+        assert!(!cooldowns.try_play(id, 0.1));
+        assert!(!cooldowns.try_play(id, 0.1));
+        assert_eq!(cooldowns.0[&id].1, 3);
+
+        // Expire it.
+        cooldowns
+            .0
+            .get_mut(&id)
+            .unwrap()
+            .0
+            .tick(Duration::from_millis(150));
+        assert!(cooldowns.try_play(id, 0.1));
+        assert_eq!(cooldowns.0[&id].1, 1);
     }
 }
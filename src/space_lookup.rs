@@ -3,11 +3,14 @@
 
 use std::marker::PhantomData;
 
+use bevy::ecs::entity::EntityHashMap;
 use bevy::ecs::query::QueryData;
 use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::LevelEvent;
 use rstar::{DefaultParams, PointDistance, RTree, RTreeObject, AABB};
 
-use crate::phys_space::PhysTransform;
+use crate::phys_space::{teleport_system, PhysTransform, TeleportEvent};
+use crate::Teleports;
 
 /// A little Entity + position wrapper for storing in an r* tree. So the idea
 /// here is, the rtree gets a nice well-defined type with legible impls of stuff
@@ -80,14 +83,24 @@ impl<T> MovementTracked<T> {
 pub struct RstarPlugin<MarkComp> {
     #[doc(hidden)]
     component_type: PhantomData<MarkComp>,
+    rebuild_on_level_load: bool,
 }
 
 impl<MarkComp> RstarPlugin<MarkComp> {
     pub fn new() -> Self {
         Self {
             component_type: PhantomData,
+            rebuild_on_level_load: false,
         }
     }
+
+    /// Throw out the whole tree and rebuild it from scratch whenever a level
+    /// spawns or despawns, instead of trusting the incremental updates to
+    /// catch a few hundred walls coming and going at once.
+    pub fn rebuild_on_level_load(mut self) -> Self {
+        self.rebuild_on_level_load = true;
+        self
+    }
 }
 
 // Need a plugin impl... fill this in later, bc it's the meat of it.
@@ -98,7 +111,14 @@ where
     fn build(&self, app: &mut App) {
         let tree_access = RstarAccess::<MarkComp>::new();
         app.insert_resource(tree_access)
+            .add_event::<TeleportEvent>()
             .add_systems(PostStartup, add_added::<MarkComp>)
+            .add_systems(
+                Update,
+                teleport::<MarkComp>
+                    .in_set(Teleports)
+                    .after(teleport_system),
+            )
             .add_systems(
                 PostUpdate,
                 (
@@ -108,6 +128,13 @@ where
                 )
                     .chain(),
             );
+        if self.rebuild_on_level_load {
+            // Goes last, so it clobbers whatever the incremental updates did.
+            app.add_event::<LevelEvent>().add_systems(
+                PostUpdate,
+                rebuild_on_level_events::<MarkComp>.after(update_moved::<MarkComp>),
+            );
+        }
     }
 }
 
@@ -124,8 +151,12 @@ where
 pub struct RstarAccess<MarkComp> {
     #[doc(hidden)]
     component_type: PhantomData<MarkComp>,
-    /// The underlying RTree struct.
+    /// The underlying RTree struct. Read it all you like, but go through the
+    /// methods below to change it, or `locs` falls out of sync.
     pub tree: RTree<EntityLoc, DefaultParams>,
+    /// Where each entity currently sits in the tree, so removing one by
+    /// entity doesn't have to walk the whole tree looking for it.
+    locs: EntityHashMap<Vec2>,
 }
 
 // These consts were members of the plugin in bevy_spatial, but I don't need to be generic like that.
@@ -141,11 +172,12 @@ const MIN_MOVED_SQUARED: f32 = MIN_MOVED * MIN_MOVED; // powi() and powf() aren'
 // Mostly lifted directly from bevy_spatial! (And mostly just delegating to the rstar crate.)
 #[allow(dead_code)]
 impl<MarkComp> RstarAccess<MarkComp> {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         let tree: RTree<EntityLoc, DefaultParams> = RTree::new(); // don't need new_with_params
         Self {
             component_type: PhantomData,
             tree,
+            locs: EntityHashMap::default(),
         }
     }
 
@@ -180,6 +212,17 @@ impl<MarkComp> RstarAccess<MarkComp> {
             .collect::<Vec<(Vec2, Entity)>>();
     }
 
+    /// Get the closest entity to `loc`, as long as it's within `distance`.
+    /// Cheaper than sorting within_distance's results, since it stops at the
+    /// first hit and doesn't build a Vec.
+    pub fn nearest_within_distance(&self, loc: Vec2, distance: f32) -> Option<(Vec2, Entity)> {
+        let point = [loc.x, loc.y];
+        self.tree
+            .nearest_neighbor(&point)
+            .filter(|e| e.distance_2(&point) <= distance.powi(2))
+            .map(|e| (e.loc, e.entity))
+    }
+
     /// Recreates the tree with the provided entity locations/coordinates.
     ///
     /// Only use if manually updating, the plugin will overwrite changes.
@@ -190,12 +233,14 @@ impl<MarkComp> RstarAccess<MarkComp> {
         let _span = info_span!("recreate").entered();
         let tree: RTree<EntityLoc, DefaultParams> = RTree::bulk_load_with_params(data);
         self.tree = tree;
+        self.locs = all.into_iter().map(|(loc, entity)| (entity, loc)).collect();
     }
 
     /// Adds a point to the tree.
     ///
     /// Only use if manually updating, the plugin will overwrite changes.
     pub fn add_point(&mut self, point: (Vec2, Entity)) {
+        self.locs.insert(point.1, point.0);
         self.tree.insert(point.into())
     }
 
@@ -203,21 +248,60 @@ impl<MarkComp> RstarAccess<MarkComp> {
     ///
     /// Only use if manually updating, the plugin will overwrite changes.
     pub fn remove_point(&mut self, point: (Vec2, Entity)) -> bool {
-        self.tree.remove(&point.into()).is_some()
+        let removed = self.tree.remove(&point.into()).is_some();
+        if removed {
+            self.locs.remove(&point.1);
+        }
+        removed
     }
 
     /// Removed a point from the tree by its entity.
     ///
     /// Only use if manually updating, the plugin will overwrite changes.
     pub fn remove_entity(&mut self, entity: Entity) -> bool {
-        let point = EntityLoc::from((entity, Vec2::ZERO));
-        self.tree.remove(&point).is_some()
+        // rstar finds the thing to remove by its envelope before checking
+        // equality, so a made-up location only works while the tree's small
+        // enough to be a single leaf. Look up where it really is.
+        let Some(&loc) = self.locs.get(&entity) else {
+            return false;
+        };
+        self.remove_point((loc, entity))
+    }
+
+    /// Move an entity that's already in the tree. Returns false (and leaves
+    /// it out) if it wasn't there to begin with.
+    ///
+    /// Only use if manually updating; update_moved will notice the entity's
+    /// MovementTracked is out of date, and fix it up.
+    pub fn relocate(&mut self, entity: Entity, loc: Vec2) -> bool {
+        if self.remove_entity(entity) {
+            self.add_point((loc, entity));
+            true
+        } else {
+            false
+        }
     }
 
     /// Size of the tree
     pub fn size(&self) -> usize {
         self.tree.size()
     }
+
+    /// How many entities are being tracked. Same as size(), but reads better
+    /// next to iter().
+    pub fn count(&self) -> usize {
+        self.size()
+    }
+
+    /// Whether the tree's got anything in it yet.
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// Every tracked entity and where it is, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (Vec2, Entity)> + '_ {
+        self.tree.iter().map(|point| (point.loc, point.entity))
+    }
 }
 
 // Then we're gonna need the systems -- add_added, delete, and update_moved.
@@ -238,10 +322,11 @@ fn add_added<MarkComp>(
             .iter()
             .map(|(entity, transform)| {
                 let loc = transform.translation.truncate();
-                // 1. add last-position component, while we're iterating anyway
+                // 1. add last-position component, while we're iterating anyway.
+                // (try_: other PostUpdate systems might despawn it first.)
                 commands
                     .entity(entity)
-                    .insert(MovementTracked::<MarkComp>::new(loc));
+                    .try_insert(MovementTracked::<MarkComp>::new(loc));
                 // 0. finish map transform
                 (loc, entity)
             })
@@ -256,10 +341,10 @@ fn add_added<MarkComp>(
             let loc = transform.translation.truncate();
             // 1. update tree
             tree_access.add_point((loc, entity));
-            // 2. add last-position component
+            // 2. add last-position component (try_, same as above)
             commands
                 .entity(entity)
-                .insert(MovementTracked::<MarkComp>::new(loc));
+                .try_insert(MovementTracked::<MarkComp>::new(loc));
         }
 
         update.exit();
@@ -332,13 +417,15 @@ fn update_moved<MarkComp>(
     } else {
         let update = info_span!("partial_update", name = "partial_update").entered();
         let mut p1 = set.p1();
-        for (entity, last, cur) in moved {
+        for (entity, _, cur) in moved {
             let Ok(mut mut_tqi) = p1.get_mut(entity) else {
                 continue;
             };
             // Hmm, conditional guard on point already being there... I think
             // that only finds by entity, bc of EntityPos's PartialEq.
-            if tree_access.remove_point((last, entity)) {
+            // (Teleported entities aren't at `last` anymore, so this goes by
+            // the tree's own idea of where they are.)
+            if tree_access.remove_entity(entity) {
                 // 1. update tree
                 tree_access.add_point((cur, entity));
                 // 2. update trackers
@@ -349,6 +436,17 @@ fn update_moved<MarkComp>(
     }
 }
 
+fn teleport<MarkComp>(
+    mut tree_access: ResMut<RstarAccess<MarkComp>>,
+    mut teleports: EventReader<TeleportEvent>,
+) where
+    MarkComp: Component,
+{
+    for teleport in teleports.read() {
+        tree_access.relocate(teleport.entity, teleport.target.truncate());
+    }
+}
+
 fn delete<MarkComp>(
     mut tree_access: ResMut<RstarAccess<MarkComp>>,
     mut removed: RemovedComponents<MarkComp>,
@@ -359,3 +457,210 @@ fn delete<MarkComp>(
         tree_access.remove_entity(entity);
     }
 }
+
+fn rebuild_on_level_events<MarkComp>(
+    mut tree_access: ResMut<RstarAccess<MarkComp>>,
+    mut level_events: EventReader<LevelEvent>,
+    mut commands: Commands,
+    all_query: Query<(Entity, &PhysTransform), With<MarkComp>>,
+) where
+    MarkComp: Component,
+{
+    let level_changed = level_events
+        .read()
+        .any(|event| matches!(event, LevelEvent::Spawned(_) | LevelEvent::Despawned(_)));
+    if !level_changed {
+        return;
+    }
+    let all: Vec<(Vec2, Entity)> = all_query
+        .iter()
+        .map(|(entity, transform)| {
+            let loc = transform.translation.truncate();
+            commands
+                .entity(entity)
+                .try_insert(MovementTracked::<MarkComp>::new(loc));
+            (loc, entity)
+        })
+        .collect();
+    tree_access.recreate(all);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::Solid;
+
+    #[test]
+    fn despawned_entities_leave_the_tree() {
+        let mut app = App::new();
+        app.add_plugins(RstarPlugin::<Solid>::new());
+        // Enough points that the tree has some depth to it.
+        let solids: Vec<Entity> = (0..100)
+            .map(|i| {
+                let loc = Vec3::new((i % 10) as f32 * 16.0, (i / 10) as f32 * 16.0, 0.0);
+                app.world_mut()
+                    .spawn((Solid, PhysTransform { translation: loc }))
+                    .id()
+            })
+            .collect();
+        app.update();
+        assert_eq!(app.world().resource::<RstarAccess<Solid>>().size(), 100);
+
+        let doomed = solids[77];
+        app.world_mut().despawn(doomed);
+        app.update();
+        let tree = app.world().resource::<RstarAccess<Solid>>();
+        assert_eq!(tree.size(), 99);
+        assert!(tree.tree.iter().all(|point| point.entity != doomed));
+    }
+
+    #[test]
+    fn iterate_everything_in_the_tree() {
+        let mut tree = RstarAccess::<Solid>::new();
+        assert!(tree.is_empty());
+        let a = Entity::from_raw(1);
+        let b = Entity::from_raw(2);
+        tree.add_point((Vec2::new(1.0, 2.0), a));
+        tree.add_point((Vec2::new(-3.0, 4.0), b));
+        assert!(!tree.is_empty());
+        assert_eq!(tree.count(), 2);
+        let mut all: Vec<(Vec2, Entity)> = tree.iter().collect();
+        all.sort_by_key(|(_, e)| *e);
+        assert_eq!(
+            all,
+            vec![(Vec2::new(1.0, 2.0), a), (Vec2::new(-3.0, 4.0), b)]
+        );
+    }
+
+    #[test]
+    fn nearest_within_distance_respects_the_limit() {
+        let mut tree = RstarAccess::<Solid>::new();
+        let near = Entity::from_raw(1);
+        let far = Entity::from_raw(2);
+        tree.add_point((Vec2::new(3.0, 4.0), near));
+        tree.add_point((Vec2::new(-20.0, 0.0), far));
+        assert_eq!(
+            tree.nearest_within_distance(Vec2::ZERO, 5.0),
+            Some((Vec2::new(3.0, 4.0), near))
+        );
+        assert_eq!(tree.nearest_within_distance(Vec2::ZERO, 4.9), None);
+        assert_eq!(
+            tree.nearest_within_distance(Vec2::new(-15.0, 0.0), 100.0),
+            Some((Vec2::new(-20.0, 0.0), far))
+        );
+        assert_eq!(
+            RstarAccess::<Solid>::new().nearest_within_distance(Vec2::ZERO, 100.0),
+            None
+        );
+    }
+
+    #[test]
+    fn removing_by_entity_finds_the_real_spot() {
+        let mut tree = RstarAccess::<Solid>::new();
+        let points: Vec<(Vec2, Entity)> = (0..100)
+            .map(|i| {
+                let loc = Vec2::new((i % 10) as f32 * 16.0, (i / 10) as f32 * 16.0);
+                (loc, Entity::from_raw(i))
+            })
+            .collect();
+        tree.recreate(points.clone());
+
+        let (_, gone) = points[33];
+        assert!(tree.remove_entity(gone));
+        assert!(!tree.remove_entity(gone));
+        assert_eq!(tree.size(), 99);
+        assert!(tree.iter().all(|(_, e)| e != gone));
+
+        let (_, mover) = points[66];
+        assert!(tree.relocate(mover, Vec2::new(-500.0, 0.0)));
+        assert!(!tree.relocate(gone, Vec2::new(-500.0, 0.0)));
+        assert_eq!(tree.size(), 99);
+        assert_eq!(
+            tree.nearest_neighbour(Vec2::new(-400.0, 0.0)),
+            Some((Vec2::new(-500.0, 0.0), mover))
+        );
+        // Going by a stale spot doesn't knock anything loose.
+        assert!(!tree.remove_point(points[66]));
+        assert!(tree.remove_entity(mover));
+        assert_eq!(tree.size(), 98);
+    }
+
+    #[test]
+    fn level_loads_rebuild_the_tree() {
+        use bevy_ecs_ldtk::prelude::LevelIid;
+
+        let mut app = App::new();
+        app.add_plugins(RstarPlugin::<Solid>::new().rebuild_on_level_load());
+        let solids: Vec<Entity> = (0..3)
+            .map(|i| {
+                let loc = Vec3::new(i as f32 * 16.0, 0.0, 0.0);
+                app.world_mut()
+                    .spawn((Solid, PhysTransform { translation: loc }))
+                    .id()
+            })
+            .collect();
+        app.update();
+        // Knock it out of sync behind the plugin's back...
+        app.world_mut()
+            .resource_mut::<RstarAccess<Solid>>()
+            .remove_entity(solids[0]);
+        app.update();
+        assert_eq!(app.world().resource::<RstarAccess<Solid>>().size(), 2);
+
+        // ...and a level load sets it straight.
+        app.world_mut()
+            .send_event(LevelEvent::Spawned(LevelIid::new("level-1")));
+        app.update();
+        assert_eq!(app.world().resource::<RstarAccess<Solid>>().size(), 3);
+    }
+
+    #[test]
+    fn teleports_show_up_in_the_tree_right_away() {
+        #[derive(Resource, Default)]
+        struct SeenAtTarget(Vec<Entity>);
+        fn look(tree: Res<RstarAccess<Solid>>, mut seen: ResMut<SeenAtTarget>) {
+            seen.0 = tree
+                .within_distance(Vec2::new(500.0, 500.0), 1.0)
+                .into_iter()
+                .map(|(_, e)| e)
+                .collect();
+        }
+
+        let mut app = App::new();
+        app.add_plugins(RstarPlugin::<Solid>::new())
+            .init_resource::<SeenAtTarget>()
+            .add_systems(Update, (teleport_system, look.after(Teleports)));
+        let solids: Vec<Entity> = (0..100)
+            .map(|i| {
+                let loc = Vec3::new((i % 10) as f32 * 16.0, (i / 10) as f32 * 16.0, 0.0);
+                app.world_mut()
+                    .spawn((Solid, PhysTransform { translation: loc }))
+                    .id()
+            })
+            .collect();
+        app.update();
+
+        let jumper = solids[42];
+        app.world_mut().send_event(TeleportEvent {
+            entity: jumper,
+            target: Vec3::new(500.0, 500.0, 0.0),
+        });
+        app.update();
+        assert_eq!(app.world().resource::<SeenAtTarget>().0, vec![jumper]);
+        let transform = app.world().get::<PhysTransform>(jumper).unwrap();
+        assert_eq!(transform.translation, Vec3::new(500.0, 500.0, 0.0));
+
+        // Plain old moves still get tracked afterward.
+        app.world_mut()
+            .get_mut::<PhysTransform>(jumper)
+            .unwrap()
+            .translation = Vec3::new(-300.0, 0.0, 0.0);
+        app.update();
+        let tree = app.world().resource::<RstarAccess<Solid>>();
+        assert_eq!(tree.size(), 100);
+        assert_eq!(
+            tree.nearest_neighbour(Vec2::new(-300.0, 0.0)),
+            Some((Vec2::new(-300.0, 0.0), jumper))
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Status effects: lingering conditions that sit on an entity for a while,
+//! independent of whatever state its state machine is in. Each one is its own
+//! component with its own duration timer, and status_effect_system takes them
+//! off again when they run out.
+
+use crate::{health::TakeDamage, movement::Motion};
+use bevy::prelude::*;
+
+/// Something that wears off.
+pub trait StatusEffect: Component {
+    fn duration_mut(&mut self) -> &mut Timer;
+}
+
+/// Status: taking damage every so often.
+#[derive(Component)]
+pub struct Poisoned {
+    pub duration: Timer,
+    pub tick_interval: Timer,
+    pub damage_per_tick: i32,
+}
+
+impl Poisoned {
+    pub fn new(duration_secs: f32, tick_secs: f32, damage_per_tick: i32) -> Self {
+        Self {
+            duration: Timer::from_seconds(duration_secs, TimerMode::Once),
+            tick_interval: Timer::from_seconds(tick_secs, TimerMode::Repeating),
+            damage_per_tick,
+        }
+    }
+}
+
+impl StatusEffect for Poisoned {
+    fn duration_mut(&mut self) -> &mut Timer {
+        &mut self.duration
+    }
+}
+
+/// Status: moving at a fraction of normal speed.
+#[derive(Component)]
+pub struct Slowed {
+    pub duration: Timer,
+    pub multiplier: f32,
+}
+
+impl Slowed {
+    pub fn new(duration_secs: f32, multiplier: f32) -> Self {
+        Self {
+            duration: Timer::from_seconds(duration_secs, TimerMode::Once),
+            multiplier,
+        }
+    }
+}
+
+impl StatusEffect for Slowed {
+    fn duration_mut(&mut self) -> &mut Timer {
+        &mut self.duration
+    }
+}
+
+/// Status: seeing stars. Can't act on inputs (or notice players, for
+/// enemies), and doesn't plan any movement of its own. Comes and goes a lot,
+/// hence the sparse storage. Deliberately NOT one of the AllBehaviors: state
+/// changes clear those, and a stun has to outlast the state it started in.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Stunned {
+    pub duration: Timer,
+}
+
+impl Stunned {
+    pub fn new(duration_secs: f32) -> Self {
+        Self {
+            duration: Timer::from_seconds(duration_secs, TimerMode::Once),
+        }
+    }
+}
+
+impl StatusEffect for Stunned {
+    fn duration_mut(&mut self) -> &mut Timer {
+        &mut self.duration
+    }
+}
+
+// ------- Systems -------
+
+/// Tick one kind of status effect's duration, and take it off when it's done.
+fn tick_status_effect<T: StatusEffect>(
+    time: &Time,
+    commands: &mut Commands,
+    query: &mut Query<(Entity, &mut T)>,
+) {
+    for (entity, mut effect) in query.iter_mut() {
+        if effect.duration_mut().tick(time.delta()).finished() {
+            commands.entity(entity).remove::<T>();
+        }
+    }
+}
+
+/// Tick all status effect timers, apply poison damage, and remove anything
+/// that's expired.
+pub fn status_effect_system(
+    time: Res<Time>,
+    mut poisoned_q: Query<(Entity, &mut Poisoned)>,
+    mut slowed_q: Query<(Entity, &mut Slowed)>,
+    mut stunned_q: Query<(Entity, &mut Stunned)>,
+    mut damage_events: EventWriter<TakeDamage>,
+    mut commands: Commands,
+) {
+    for (entity, mut poison) in poisoned_q.iter_mut() {
+        poison.tick_interval.tick(time.delta());
+        for _ in 0..poison.tick_interval.times_finished_this_tick() {
+            damage_events.send(TakeDamage {
+                target: entity,
+                amount: poison.damage_per_tick,
+                source: None,
+            });
+        }
+    }
+    tick_status_effect(&time, &mut commands, &mut poisoned_q);
+    tick_status_effect(&time, &mut commands, &mut slowed_q);
+    tick_status_effect(&time, &mut commands, &mut stunned_q);
+}
+
+/// Scale down planned motion for slowed entities. Goes after the move
+/// planners, so it applies to everything they came up with.
+pub fn slowed_velocity_system(mut slowed_q: Query<(&mut Motion, &Slowed)>) {
+    for (mut motion, slowed) in slowed_q.iter_mut() {
+        motion.velocity *= slowed.multiplier;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::utils::Duration;
+
+    fn step(world: &mut World, secs: f32) {
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(secs));
+        world.run_system_once(status_effect_system);
+    }
+
+    #[test]
+    fn poison_ticks_then_wears_off() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<Events<TakeDamage>>();
+        let guy = world.spawn(Poisoned::new(1.0, 0.25, 2)).id();
+
+        step(&mut world, 0.3);
+        assert_eq!(world.resource::<Events<TakeDamage>>().len(), 1);
+        assert!(world.get::<Poisoned>(guy).is_some());
+
+        step(&mut world, 0.8);
+        // Ticks at 0.5, 0.75, and 1.0.
+        assert_eq!(world.resource::<Events<TakeDamage>>().len(), 4);
+        assert!(world.get::<Poisoned>(guy).is_none());
+    }
+
+    #[test]
+    fn stun_and_slow_wear_off() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<Events<TakeDamage>>();
+        let guy = world.spawn((Stunned::new(0.5), Slowed::new(1.0, 0.5))).id();
+
+        step(&mut world, 0.6);
+        assert!(world.get::<Stunned>(guy).is_none());
+        assert!(world.get::<Slowed>(guy).is_some());
+        step(&mut world, 0.6);
+        assert!(world.get::<Slowed>(guy).is_none());
+    }
+}
@@ -0,0 +1,82 @@
+//! Fixtures shared by the unit tests in different modules.
+
+use crate::{
+    char_animation::{CharAnimation, CharAnimationFrame, CharAnimationVariant, Directionality},
+    compass::Dir,
+    movement::Motion,
+    phys_space::PhysTransform,
+};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use bevy_ecs_ldtk::{ldtk::FieldInstance, prelude::*};
+
+/// A PhysTransform sitting on the ground at (x, y).
+pub fn phys_at(x: f32, y: f32) -> PhysTransform {
+    PhysTransform {
+        translation: Vec3::new(x, y, 0.0),
+    }
+}
+
+/// Spawn something that plans its own movement: standing still at (x, y),
+/// plus whatever behavior components you hand it.
+pub fn spawn_mover(world: &mut World, x: f32, y: f32, bundle: impl Bundle) -> Entity {
+    world
+        .spawn((phys_at(x, y), Motion::new(Vec2::ZERO), bundle))
+        .id()
+}
+
+/// An entity's planned velocity for this frame.
+pub fn velocity(world: &World, entity: Entity) -> Vec2 {
+    world.get::<Motion>(entity).unwrap().velocity
+}
+
+/// An LDtk entity with nothing set but the given fields.
+pub fn ldtk_instance(fields: Vec<(&str, FieldValue)>) -> EntityInstance {
+    EntityInstance {
+        field_instances: fields
+            .into_iter()
+            .map(|(identifier, value)| FieldInstance {
+                identifier: identifier.to_string(),
+                tile: None,
+                field_instance_type: String::new(),
+                value,
+                def_uid: 0,
+                real_editor_values: Vec::new(),
+            })
+            .collect(),
+        ..default()
+    }
+}
+
+/// An east-facing animation variant with one blank frame per duration.
+pub fn test_variant(millis: &[u64]) -> CharAnimationVariant {
+    let frames: Vec<CharAnimationFrame> = millis
+        .iter()
+        .enumerate()
+        .map(|(index, &ms)| CharAnimationFrame {
+            index,
+            duration: Duration::from_millis(ms),
+            origin: Vec2::ZERO,
+            anchor: Vec2::ZERO,
+            walkbox: None,
+            hitbox: None,
+            hurtbox: None,
+            slices: Default::default(),
+        })
+        .collect();
+    CharAnimationVariant {
+        name: Dir::E,
+        duration: frames.iter().map(|f| f.duration).sum(),
+        frames,
+    }
+}
+
+/// A one-way (east only) animation made of test_variant, with no art.
+pub fn test_animation(millis: &[u64]) -> CharAnimation {
+    CharAnimation {
+        variants: [(Dir::E, test_variant(millis))].into_iter().collect(),
+        directionality: Directionality::OneE,
+        layout: Handle::default(),
+        texture: Handle::default(),
+    }
+}
@@ -36,6 +36,12 @@ impl CountupTimer {
         Self::new(Duration::from_secs_f32(duration))
     }
 
+    /// Same as from_seconds, but takes an f64 so long or fiddly durations
+    /// don't lose precision on the way in.
+    pub fn from_seconds_mut(duration: f64) -> Self {
+        Self::new(Duration::from_secs_f64(duration))
+    }
+
     // A bunch of stuff we can just copy straight from Timer
     #[inline]
     pub fn finished(&self) -> bool {
@@ -74,6 +80,18 @@ impl CountupTimer {
         self.finished = false;
         self.times_finished_this_tick = 0;
     }
+    /// Like reset, but also unpauses: you end up with what CountupTimer::new
+    /// would give you, without having to build a new one.
+    pub fn reset_and_start(&mut self) {
+        self.reset();
+        self.unpause();
+    }
+    /// Change the target duration, keeping the elapsed time. Same as Timer,
+    /// the finished flags don't catch up until the next tick.
+    #[inline]
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
     #[inline]
     pub fn times_finished_this_tick(&self) -> u32 {
         self.times_finished_this_tick
@@ -197,4 +215,49 @@ mod tests {
         assert!(!t.just_finished());
         assert!(t.finished());
     }
+
+    #[test]
+    fn set_duration_keeps_elapsed() {
+        let mut t = CountupTimer::from_seconds(2.0);
+        t.tick(Duration::from_secs_f32(1.5));
+        assert!(!t.finished());
+        // Shorten it out from under itself; it notices on the next tick.
+        t.set_duration(Duration::from_secs_f32(1.0));
+        assert_eq!(t.elapsed_secs(), 1.5);
+        assert_eq!(t.duration(), Duration::from_secs_f32(1.0));
+        t.tick(Duration::from_secs_f32(0.25));
+        assert!(t.just_finished());
+        assert_eq!(t.countup_elapsed_secs(), 0.75);
+        // And lengthen it again.
+        t.set_duration(Duration::from_secs_f32(4.0));
+        t.tick(Duration::ZERO);
+        assert!(!t.finished());
+        assert_eq!(t.remaining_secs(), 2.25);
+    }
+
+    #[test]
+    fn reset_and_start_is_like_new() {
+        let mut t = CountupTimer::from_seconds(1.0);
+        t.tick(Duration::from_secs_f32(1.5));
+        t.pause();
+        t.reset_and_start();
+        assert_eq!(t.elapsed(), Duration::ZERO);
+        assert!(!t.finished());
+        assert!(!t.just_finished());
+        assert!(!t.paused());
+        assert_eq!(t.duration(), Duration::from_secs_f32(1.0));
+        t.tick(Duration::from_secs_f32(1.0));
+        assert!(t.just_finished());
+    }
+
+    #[test]
+    fn from_seconds_mut_takes_f64() {
+        let t = CountupTimer::from_seconds_mut(0.1);
+        assert_eq!(t.duration(), Duration::from_secs_f64(0.1));
+        assert_eq!(
+            t.duration(),
+            CountupTimer::new(Duration::from_millis(100)).duration()
+        );
+        assert!(!t.finished());
+    }
 }
@@ -37,6 +37,15 @@ pub fn move_rect_origin(r: Rect, origin: Vec2) -> Rect {
     }
 }
 
+/// Grow a Rect by `x` on its left and right sides and `y` on its top and
+/// bottom. Negative shrinks; shrink it past nothing and it bottoms out as a
+/// zero-size rect at its old center.
+pub fn expand_rect_xy(r: Rect, x: f32, y: f32) -> Rect {
+    let center = r.center();
+    let half_size = (r.half_size() + Vec2::new(x, y)).max(Vec2::ZERO);
+    Rect::from_center_half_size(center, half_size)
+}
+
 // Determines whether an input Vec2 no longer has any movement component in a given cardinal direction.
 pub fn turned_away_from(cardinal: Vec2, input: Vec2) -> bool {
     if cardinal.x == 0.0 {
@@ -45,3 +54,126 @@ pub fn turned_away_from(cardinal: Vec2, input: Vec2) -> bool {
         input.x == 0.0 || cardinal.x.signum() != input.x.signum()
     }
 }
+
+/// Move `current` toward `target` by at most `max_delta`, without
+/// overshooting. For frame-rate independence, scale max_delta by the frame
+/// time. A negative max_delta counts as zero; this never backs away.
+pub fn approach(current: f32, target: f32, max_delta: f32) -> f32 {
+    let max_delta = max_delta.max(0.0);
+    current + (target - current).clamp(-max_delta, max_delta)
+}
+
+/// Like approach, but for points: move in a straight line toward `target`,
+/// by at most `max_delta` distance.
+pub fn approach_vec2(current: Vec2, target: Vec2, max_delta: f32) -> Vec2 {
+    current + (target - current).clamp_length_max(max_delta.max(0.0))
+}
+
+/// Round each component to the nearest multiple of `grid`.
+pub fn snap_to_grid(v: Vec2, grid: f32) -> Vec2 {
+    (v / grid).round() * grid
+}
+
+/// Shrink a vector to at most `max` long, keeping its direction. Short
+/// enough vectors pass through untouched.
+pub fn clamp_magnitude(v: Vec2, max: f32) -> Vec2 {
+    let max = max.max(0.0);
+    let length = v.length();
+    if length > max {
+        v * (max / length)
+    } else {
+        v
+    }
+}
+
+/// Round tiny vectors (shorter than `threshold`) down to zero.
+pub fn deadband(v: Vec2, threshold: f32) -> Vec2 {
+    if v.length() < threshold {
+        Vec2::ZERO
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approach_stays_put_at_target() {
+        assert_eq!(approach(3.0, 3.0, 1.0), 3.0);
+        assert_eq!(approach_vec2(Vec2::ONE, Vec2::ONE, 1.0), Vec2::ONE);
+    }
+
+    #[test]
+    fn approach_doesnt_overshoot() {
+        assert_eq!(approach(0.0, 10.0, 4.0), 4.0);
+        assert_eq!(approach(8.0, 10.0, 4.0), 10.0);
+        assert_eq!(
+            approach_vec2(Vec2::ZERO, Vec2::new(3.0, 4.0), 2.5),
+            Vec2::new(1.5, 2.0)
+        );
+        assert_eq!(
+            approach_vec2(Vec2::ZERO, Vec2::new(3.0, 4.0), 100.0),
+            Vec2::new(3.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn approach_goes_down_too() {
+        assert_eq!(approach(10.0, 0.0, 4.0), 6.0);
+        assert_eq!(approach(-1.0, -2.0, 4.0), -2.0);
+    }
+
+    #[test]
+    fn approach_with_no_room_to_move() {
+        assert_eq!(approach(1.0, 5.0, 0.0), 1.0);
+        assert_eq!(approach(1.0, 5.0, -3.0), 1.0);
+        assert_eq!(approach_vec2(Vec2::ZERO, Vec2::X, 0.0), Vec2::ZERO);
+        assert_eq!(approach_vec2(Vec2::ZERO, Vec2::X, -1.0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn resizing_rects() {
+        let r = Rect::new(0.0, 0.0, 4.0, 2.0);
+        let wide = expand_rect_xy(r, 2.0, -0.5);
+        assert_eq!(wide.min, Vec2::new(-2.0, 0.5));
+        assert_eq!(wide.max, Vec2::new(6.0, 1.5));
+        // Way too much shrinking: a point in the middle.
+        let gone = expand_rect_xy(r, -10.0, -10.0);
+        assert_eq!(gone.min, Vec2::new(2.0, 1.0));
+        assert_eq!(gone.max, Vec2::new(2.0, 1.0));
+    }
+
+    #[test]
+    fn snapping_rounds_to_the_nearest_line() {
+        assert_eq!(
+            snap_to_grid(Vec2::new(1.4, -2.6), 1.0),
+            Vec2::new(1.0, -3.0)
+        );
+        assert_eq!(snap_to_grid(Vec2::new(5.0, 7.9), 4.0), Vec2::new(4.0, 8.0));
+        assert_eq!(snap_to_grid(Vec2::new(0.3, 0.2), 0.5), Vec2::new(0.5, 0.0));
+    }
+
+    #[test]
+    fn clamp_magnitude_keeps_direction() {
+        assert_eq!(
+            clamp_magnitude(Vec2::new(30.0, 40.0), 5.0),
+            Vec2::new(3.0, 4.0)
+        );
+        assert_eq!(
+            clamp_magnitude(Vec2::new(3.0, 4.0), 5.0),
+            Vec2::new(3.0, 4.0)
+        );
+        assert_eq!(clamp_magnitude(Vec2::ZERO, 5.0), Vec2::ZERO);
+        assert_eq!(clamp_magnitude(Vec2::X, 0.0), Vec2::ZERO);
+        assert_eq!(clamp_magnitude(Vec2::X, -1.0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn deadband_zeroes_small_stuff() {
+        assert_eq!(deadband(Vec2::new(0.1, 0.1), 0.2), Vec2::ZERO);
+        assert_eq!(deadband(Vec2::new(0.3, 0.0), 0.2), Vec2::new(0.3, 0.0));
+        assert_eq!(deadband(Vec2::new(0.2, 0.0), 0.2), Vec2::new(0.2, 0.0));
+    }
+}
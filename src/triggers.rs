@@ -0,0 +1,612 @@
+//! Trigger zones: invisible rects on the ground that do something to whoever's
+//! standing in them. trigger_zone_system figures out who's where and sends a
+//! ZoneTriggered every frame they're inside; the other systems here decide
+//! what each kind of zone actually does about it. Levels place zones with
+//! LDtk "TriggerZone" entities.
+
+use crate::{
+    behaviors::{launch_and_fall, Launch},
+    collision::{rect_reach, AbsBBox, Solid, Walkbox},
+    health::TakeDamage,
+    levels::LevelTransitionEvent,
+    phys_space::PhysTransform,
+    space_lookup::{RstarAccess, RstarPlugin},
+    status_effects::{Poisoned, Slowed},
+    walls::{ldtk_entity_phys_location, rect_around_pivot},
+    Player,
+};
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashMap};
+use bevy_ecs_ldtk::prelude::*;
+
+/// How often poison zones' poison bites.
+const POISON_ZONE_TICK_SECS: f32 = 1.0;
+
+pub struct TriggersPlugin;
+impl Plugin for TriggersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RstarPlugin::<TriggerZone>::new())
+            .add_event::<ZoneTriggered>()
+            .register_ldtk_entity::<TriggerZoneBundle>("TriggerZone")
+            .add_systems(PostUpdate, trigger_zone_system)
+            .add_systems(
+                Update,
+                (
+                    zone_damage_system,
+                    zone_speed_system,
+                    zone_poison_system,
+                    zone_level_transition_system,
+                    gravity_zone_system.before(launch_and_fall),
+                ),
+            );
+    }
+}
+
+/// Component: a zone on the ground. `rect` is relative to the zone entity's
+/// PhysTransform, same as a walkbox.
+#[derive(Component)]
+pub struct TriggerZone {
+    pub rect: Rect,
+    pub event: TriggerEvent,
+}
+
+/// What a zone does to things inside it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TriggerEvent {
+    /// Hurts. Fractional damage adds up over time.
+    DamagePerSecond(f32),
+    /// Scales movement speed, and it lingers for a bit after leaving.
+    SpeedMultiplier(f32, Duration),
+    /// Poisons you: damage per tick, and how long it lasts after leaving.
+    Poison(i32, Duration),
+    /// Players only: go to another level (by index).
+    LevelTransition(usize),
+    /// Doesn't do anything on its own; for whatever wants to listen.
+    CustomTag(String),
+}
+
+impl TriggerEvent {
+    /// LDtk string field: which kind of zone. Damage, Speed, Poison,
+    /// LevelTransition, or Tag.
+    const LDTK_KIND_FIELD: &'static str = "kind";
+    /// LDtk float field: damage per second, speed multiplier, or poison
+    /// damage per tick.
+    const LDTK_AMOUNT_FIELD: &'static str = "amount";
+    /// LDtk float field: how long a speed change or poison lingers after you
+    /// leave.
+    const LDTK_SECONDS_FIELD: &'static str = "seconds";
+    /// LDtk int field: which level a transition zone goes to.
+    const LDTK_LEVEL_FIELD: &'static str = "level";
+    /// LDtk string field: the tag, for Tag zones.
+    const LDTK_TAG_FIELD: &'static str = "tag";
+
+    /// Read a zone's effect off its LDtk fields. Missing numbers count as
+    /// zero; an unknown kind is an error.
+    pub fn from_ldtk(instance: &EntityInstance) -> Result<Self, String> {
+        let kind = instance
+            .get_string_field(Self::LDTK_KIND_FIELD)
+            .map_or("", String::as_str);
+        let amount = instance
+            .get_float_field(Self::LDTK_AMOUNT_FIELD)
+            .copied()
+            .unwrap_or(0.0);
+        let linger = || {
+            let seconds = instance
+                .get_float_field(Self::LDTK_SECONDS_FIELD)
+                .copied()
+                .unwrap_or(0.0);
+            Duration::from_secs_f32(seconds.max(0.0))
+        };
+        match kind {
+            "Damage" => Ok(Self::DamagePerSecond(amount)),
+            "Speed" => Ok(Self::SpeedMultiplier(amount, linger())),
+            "Poison" => Ok(Self::Poison(amount.round() as i32, linger())),
+            "LevelTransition" => {
+                let level = instance
+                    .get_int_field(Self::LDTK_LEVEL_FIELD)
+                    .copied()
+                    .unwrap_or(0);
+                Ok(Self::LevelTransition(level.max(0) as usize))
+            },
+            "Tag" => {
+                let tag = instance
+                    .get_string_field(Self::LDTK_TAG_FIELD)
+                    .cloned()
+                    .unwrap_or_default();
+                Ok(Self::CustomTag(tag))
+            },
+            other => Err(format!("unknown trigger zone kind {:?}", other)),
+        }
+    }
+}
+
+/// Trigger zones placed in LDtk. The zone covers the LDtk entity's own rect.
+#[derive(Bundle)]
+pub struct TriggerZoneBundle {
+    zone: TriggerZone,
+    phys_transform: PhysTransform,
+}
+
+impl LdtkEntity for TriggerZoneBundle {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _tileset: Option<&Handle<Image>>,
+        _tileset_definition: Option<&TilesetDefinition>,
+        _asset_server: &AssetServer,
+        _texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) -> Self {
+        let event = TriggerEvent::from_ldtk(entity_instance).unwrap_or_else(|e| {
+            warn!(
+                "{} on LDtk entity {}, so it does nothing",
+                e, entity_instance.iid
+            );
+            TriggerEvent::CustomTag(String::new())
+        });
+        let size = Vec2::new(entity_instance.width as f32, entity_instance.height as f32);
+        TriggerZoneBundle {
+            zone: TriggerZone {
+                rect: rect_around_pivot(size, entity_instance.pivot),
+                event,
+            },
+            phys_transform: PhysTransform {
+                translation: ldtk_entity_phys_location(entity_instance, layer_instance).extend(0.0),
+            },
+        }
+    }
+}
+
+/// Component: goes on a trigger zone entity (alongside its TriggerZone, which
+/// supplies the rect) to change how launched things fall while they're over
+/// it. Water, low-grav rooms, etc. `drag` bleeds off z-velocity, as a
+/// fraction per second.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct GravityZone {
+    pub gravity: f32,
+    pub drag: f32,
+}
+
+/// Component: this entity's gravity, overriding NumbersSettings while it's
+/// in a GravityZone. Managed by gravity_zone_system.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+#[component(storage = "SparseSet")]
+pub struct CurrentGravity(pub f32);
+
+/// Event: `entity` is standing in a zone that does `event` this frame.
+#[derive(Event, Debug, Clone)]
+pub struct ZoneTriggered {
+    pub entity: Entity,
+    pub event: TriggerEvent,
+}
+
+// ------- Systems -------
+
+/// Find every mobile thing that's overlapping a zone, and announce it. Zones
+/// come out of the space lookup tree by origin, so the scan distance has to
+/// cover the biggest zone plus the walker's own walkbox.
+pub fn trigger_zone_system(
+    zone_q: Query<(&TriggerZone, &PhysTransform)>,
+    walker_q: Query<(Entity, &Walkbox, &PhysTransform), (Without<TriggerZone>, Without<Solid>)>,
+    zone_tree: Res<RstarAccess<TriggerZone>>,
+    mut triggers: EventWriter<ZoneTriggered>,
+) {
+    let max_zone_reach = zone_q
+        .iter()
+        .map(|(zone, _)| rect_reach(zone.rect))
+        .fold(0.0, f32::max);
+    if max_zone_reach == 0.0 {
+        return;
+    }
+    for (entity, walkbox, transform) in walker_q.iter() {
+        let loc = transform.translation.truncate();
+        let abs_walkbox = transform.to_absbbox(walkbox);
+        let scan_distance = max_zone_reach + rect_reach(walkbox.0);
+        for (_, zone_entity) in zone_tree.within_distance(loc, scan_distance) {
+            let Ok((zone, zone_transform)) = zone_q.get(zone_entity) else {
+                continue;
+            };
+            let abs_zone = AbsBBox::from_rect(zone.rect, zone_transform.translation.truncate());
+            if abs_zone.collide(abs_walkbox) {
+                triggers.send(ZoneTriggered {
+                    entity,
+                    event: zone.event.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// Damage zones: rack up damage per second, and hand it out in whole points.
+pub fn zone_damage_system(
+    time: Res<Time>,
+    mut triggers: EventReader<ZoneTriggered>,
+    mut owed: Local<HashMap<Entity, f32>>,
+    mut damage_events: EventWriter<TakeDamage>,
+) {
+    let mut still_inside = Vec::new();
+    for trigger in triggers.read() {
+        let TriggerEvent::DamagePerSecond(dps) = trigger.event else {
+            continue;
+        };
+        *owed.entry(trigger.entity).or_insert(0.0) += dps * time.delta_seconds();
+        still_inside.push(trigger.entity);
+    }
+    // Stepping out forgives whatever's left over.
+    owed.retain(|entity, _| still_inside.contains(entity));
+    for (entity, amount) in owed.iter_mut() {
+        if *amount >= 1.0 {
+            let whole = amount.floor();
+            *amount -= whole;
+            damage_events.send(TakeDamage {
+                target: *entity,
+                amount: whole as i32,
+                source: None,
+            });
+        }
+    }
+}
+
+/// Speed zones: (re)apply a speed change for as long as you're standing in
+/// one. Slowed is just a multiplier, so it speeds things up fine too. A
+/// stronger Slowed from somewhere else wins, and the zone leaves it be.
+pub fn zone_speed_system(
+    mut triggers: EventReader<ZoneTriggered>,
+    mut slowed_q: Query<Option<&mut Slowed>>,
+    mut commands: Commands,
+) {
+    for trigger in triggers.read() {
+        let TriggerEvent::SpeedMultiplier(multiplier, duration) = trigger.event else {
+            continue;
+        };
+        let zone_slowed = Slowed::new(duration.as_secs_f32(), multiplier);
+        match slowed_q.get_mut(trigger.entity) {
+            Ok(Some(mut slowed)) => {
+                let stronger = (multiplier - 1.0).abs() > (slowed.multiplier - 1.0).abs();
+                // Same speed as what it's got: just keep it from running out.
+                let topping_up =
+                    multiplier == slowed.multiplier && slowed.duration.remaining() < duration;
+                if stronger || topping_up {
+                    *slowed = zone_slowed;
+                }
+            },
+            Ok(None) => {
+                commands.entity(trigger.entity).insert(zone_slowed);
+            },
+            Err(_) => (),
+        }
+    }
+}
+
+/// Poison zones: poison whoever's standing in one, and keep it from wearing
+/// off until they step out. Topping it up leaves the tick timer alone, so the
+/// damage still comes on schedule.
+pub fn zone_poison_system(
+    mut triggers: EventReader<ZoneTriggered>,
+    mut poisoned_q: Query<Option<&mut Poisoned>>,
+    mut commands: Commands,
+) {
+    for trigger in triggers.read() {
+        let TriggerEvent::Poison(damage_per_tick, duration) = trigger.event else {
+            continue;
+        };
+        match poisoned_q.get_mut(trigger.entity) {
+            Ok(Some(mut poisoned)) => {
+                if poisoned.duration.remaining() < duration {
+                    poisoned.duration = Timer::new(duration, TimerMode::Once);
+                }
+                poisoned.damage_per_tick = poisoned.damage_per_tick.max(damage_per_tick);
+            },
+            Ok(None) => {
+                commands.entity(trigger.entity).insert(Poisoned::new(
+                    duration.as_secs_f32(),
+                    POISON_ZONE_TICK_SECS,
+                    damage_per_tick,
+                ));
+            },
+            Err(_) => (),
+        }
+    }
+}
+
+/// Level transition zones: when a player steps in, ask to switch levels.
+/// (This fires every frame they're standing there; LevelTransition ignores
+/// the repeats.)
+pub fn zone_level_transition_system(
+    mut triggers: EventReader<ZoneTriggered>,
+    player_q: Query<(), With<Player>>,
+    mut transitions: EventWriter<LevelTransitionEvent>,
+) {
+    for trigger in triggers.read() {
+        let TriggerEvent::LevelTransition(target_level) = trigger.event else {
+            continue;
+        };
+        if player_q.contains(trigger.entity) {
+            transitions.send(LevelTransitionEvent { target_level });
+        }
+    }
+}
+
+/// Gravity zones: work out which zone (if any) each launched thing is over,
+/// and set its CurrentGravity to match. Unlike the ZoneTriggered zones, this
+/// has to be current as of this frame's launch_and_fall, so it does its own
+/// lookup instead of waiting on trigger_zone_system. If zones overlap, the
+/// last one found wins.
+pub fn gravity_zone_system(
+    zone_q: Query<(&TriggerZone, &GravityZone, &PhysTransform)>,
+    mut launched_q: Query<
+        (
+            Entity,
+            &Walkbox,
+            &PhysTransform,
+            Option<&mut Launch>,
+            Option<&CurrentGravity>,
+        ),
+        Or<(With<Launch>, With<CurrentGravity>)>,
+    >,
+    zone_tree: Res<RstarAccess<TriggerZone>>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let max_zone_reach = zone_q
+        .iter()
+        .map(|(zone, ..)| rect_reach(zone.rect))
+        .fold(0.0, f32::max);
+    for (entity, walkbox, transform, launch, current) in launched_q.iter_mut() {
+        let mut found = None;
+        if let Some(mut launch) = launch {
+            let loc = transform.translation.truncate();
+            let abs_walkbox = transform.to_absbbox(walkbox);
+            let scan_distance = max_zone_reach + rect_reach(walkbox.0);
+            for (_, zone_entity) in zone_tree.within_distance(loc, scan_distance) {
+                let Ok((zone, gravity_zone, zone_transform)) = zone_q.get(zone_entity) else {
+                    continue;
+                };
+                let abs_zone = AbsBBox::from_rect(zone.rect, zone_transform.translation.truncate());
+                if abs_zone.collide(abs_walkbox) {
+                    found = Some(*gravity_zone);
+                }
+            }
+            if let Some(gravity_zone) = found {
+                let keep = (1.0 - gravity_zone.drag * time.delta_seconds()).max(0.0);
+                launch.z_velocity *= keep;
+            }
+        }
+        match (found, current) {
+            (Some(gravity_zone), current) => {
+                if current != Some(&CurrentGravity(gravity_zone.gravity)) {
+                    commands
+                        .entity(entity)
+                        .insert(CurrentGravity(gravity_zone.gravity));
+                }
+            },
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<CurrentGravity>();
+            },
+            (None, None) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collision::centered_rect;
+    use crate::health::HealthEventsPlugin;
+    use crate::test_utils::ldtk_instance;
+
+    #[test]
+    fn zones_read_their_effect_from_ldtk() {
+        let kind = |k: &str| ("kind", FieldValue::String(Some(k.to_string())));
+        let slow = ldtk_instance(vec![
+            kind("Speed"),
+            ("amount", FieldValue::Float(Some(0.5))),
+            ("seconds", FieldValue::Float(Some(1.5))),
+        ]);
+        assert_eq!(
+            TriggerEvent::from_ldtk(&slow),
+            Ok(TriggerEvent::SpeedMultiplier(
+                0.5,
+                Duration::from_millis(1500)
+            ))
+        );
+        let exit = ldtk_instance(vec![
+            kind("LevelTransition"),
+            ("level", FieldValue::Int(Some(2))),
+        ]);
+        assert_eq!(
+            TriggerEvent::from_ldtk(&exit),
+            Ok(TriggerEvent::LevelTransition(2))
+        );
+        // Numbers left blank are zero.
+        let harmless = ldtk_instance(vec![kind("Damage"), ("amount", FieldValue::Float(None))]);
+        assert_eq!(
+            TriggerEvent::from_ldtk(&harmless),
+            Ok(TriggerEvent::DamagePerSecond(0.0))
+        );
+        let tagged = ldtk_instance(vec![
+            kind("Tag"),
+            ("tag", FieldValue::String(Some("water".to_string()))),
+        ]);
+        assert_eq!(
+            TriggerEvent::from_ldtk(&tagged),
+            Ok(TriggerEvent::CustomTag("water".to_string()))
+        );
+        let puddle = ldtk_instance(vec![
+            kind("Poison"),
+            ("amount", FieldValue::Float(Some(1.0))),
+            ("seconds", FieldValue::Float(Some(4.0))),
+        ]);
+        assert_eq!(
+            TriggerEvent::from_ldtk(&puddle),
+            Ok(TriggerEvent::Poison(1, Duration::from_secs(4)))
+        );
+        assert!(TriggerEvent::from_ldtk(&ldtk_instance(vec![kind("Lava")])).is_err());
+        assert!(TriggerEvent::from_ldtk(&ldtk_instance(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn damage_zones_hurt_whoever_stands_in_them() {
+        let mut app = App::new();
+        app.add_plugins((HealthEventsPlugin, RstarPlugin::<TriggerZone>::new()))
+            .add_event::<ZoneTriggered>()
+            .init_resource::<Time>()
+            .add_systems(PostUpdate, trigger_zone_system)
+            .add_systems(Update, zone_damage_system);
+        app.world_mut().spawn((
+            TriggerZone {
+                rect: centered_rect(32.0, 32.0),
+                event: TriggerEvent::DamagePerSecond(4.0),
+            },
+            PhysTransform {
+                translation: Vec3::ZERO,
+            },
+        ));
+        let walkbox = Walkbox(centered_rect(8.0, 4.0));
+        let inside = app
+            .world_mut()
+            .spawn((
+                walkbox,
+                PhysTransform {
+                    translation: Vec3::new(18.0, 0.0, 0.0),
+                },
+            ))
+            .id();
+        app.world_mut().spawn((
+            Walkbox(centered_rect(8.0, 4.0)),
+            PhysTransform {
+                translation: Vec3::new(30.0, 0.0, 0.0),
+            },
+        ));
+
+        // 4 dps for a bit over a quarter second is one point of damage, but
+        // the first frame only finds out who's inside.
+        for _ in 0..3 {
+            app.world_mut()
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_millis(150));
+            app.update();
+        }
+        let events = app.world().resource::<Events<TakeDamage>>();
+        let hurt: Vec<(Entity, i32)> = events
+            .get_reader()
+            .read(events)
+            .map(|d| (d.target, d.amount))
+            .collect();
+        assert_eq!(hurt, vec![(inside, 1)]);
+    }
+
+    #[test]
+    fn poison_zones_top_up_without_resetting_the_ticks() {
+        let mut app = App::new();
+        app.add_event::<ZoneTriggered>()
+            .add_systems(Update, zone_poison_system);
+        let fresh = app.world_mut().spawn_empty().id();
+        let mut half_done = Poisoned::new(3.0, POISON_ZONE_TICK_SECS, 1);
+        half_done.duration.tick(Duration::from_millis(2500));
+        half_done.tick_interval.tick(Duration::from_millis(500));
+        let sick = app.world_mut().spawn(half_done).id();
+        for entity in [fresh, sick] {
+            app.world_mut().send_event(ZoneTriggered {
+                entity,
+                event: TriggerEvent::Poison(2, Duration::from_secs(3)),
+            });
+        }
+        app.update();
+
+        let poisoned = |e: Entity| app.world().get::<Poisoned>(e).unwrap();
+        assert_eq!(poisoned(fresh).damage_per_tick, 2);
+        assert_eq!(poisoned(fresh).duration.remaining(), Duration::from_secs(3));
+        assert_eq!(poisoned(sick).damage_per_tick, 2);
+        assert_eq!(poisoned(sick).duration.remaining(), Duration::from_secs(3));
+        assert_eq!(
+            poisoned(sick).tick_interval.elapsed(),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn speed_zones_dont_clobber_stronger_slows() {
+        let mut app = App::new();
+        app.add_event::<ZoneTriggered>()
+            .add_systems(Update, zone_speed_system);
+        let fresh = app.world_mut().spawn_empty().id();
+        let frozen = app.world_mut().spawn(Slowed::new(5.0, 0.1)).id();
+        let dawdling = app.world_mut().spawn(Slowed::new(5.0, 0.8)).id();
+        let lingering = app.world_mut().spawn(Slowed::new(5.0, 0.5)).id();
+        app.world_mut()
+            .get_mut::<Slowed>(lingering)
+            .unwrap()
+            .duration
+            .tick(Duration::from_secs(3));
+        for entity in [fresh, frozen, dawdling, lingering] {
+            app.world_mut().send_event(ZoneTriggered {
+                entity,
+                event: TriggerEvent::SpeedMultiplier(0.5, Duration::from_secs(5)),
+            });
+        }
+        app.update();
+
+        let multiplier = |app: &App, e: Entity| app.world().get::<Slowed>(e).unwrap().multiplier;
+        assert_eq!(multiplier(&app, fresh), 0.5);
+        assert_eq!(multiplier(&app, frozen), 0.1);
+        assert_eq!(multiplier(&app, dawdling), 0.5);
+        // Standing in the zone keeps its own slow topped up.
+        let remaining = app
+            .world()
+            .get::<Slowed>(lingering)
+            .unwrap()
+            .duration
+            .remaining();
+        assert_eq!(remaining, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn gravity_zones_override_gravity_while_youre_over_them() {
+        let mut app = App::new();
+        app.add_plugins(RstarPlugin::<TriggerZone>::new())
+            .init_resource::<Time>()
+            .add_systems(Update, gravity_zone_system);
+        app.world_mut().spawn((
+            TriggerZone {
+                rect: centered_rect(32.0, 32.0),
+                event: TriggerEvent::CustomTag("water".to_string()),
+            },
+            GravityZone {
+                gravity: 60.0,
+                drag: 0.5,
+            },
+            PhysTransform {
+                translation: Vec3::ZERO,
+            },
+        ));
+        let swimmer = app
+            .world_mut()
+            .spawn((
+                Walkbox(centered_rect(8.0, 4.0)),
+                PhysTransform {
+                    translation: Vec3::new(4.0, 0.0, 0.0),
+                },
+                Launch { z_velocity: 100.0 },
+            ))
+            .id();
+
+        app.world_mut()
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_millis(100));
+        app.update();
+        assert_eq!(
+            app.world().get::<CurrentGravity>(swimmer),
+            Some(&CurrentGravity(60.0))
+        );
+        let z_velocity = app.world().get::<Launch>(swimmer).unwrap().z_velocity;
+        assert!((z_velocity - 95.0).abs() < 0.01);
+
+        app.world_mut()
+            .get_mut::<PhysTransform>(swimmer)
+            .unwrap()
+            .translation
+            .x = 64.0;
+        app.update();
+        assert!(app.world().get::<CurrentGravity>(swimmer).is_none());
+    }
+}
@@ -0,0 +1,67 @@
+//! Helpers for UI that has to line up with stuff out in the world.
+//!
+//! Heads up, the two spaces are upside down from each other: the game world
+//! is Y-up (origin at the bottom left, as far as the camera's concerned), but
+//! bevy_ui and window coordinates are Y-down, with the origin at the top left
+//! of the window. Everything here takes world positions and hands back UI
+//! positions in logical pixels, ready for a Style's `left` and `top`.
+
+use bevy::prelude::*;
+
+/// Where a world position shows up in the window, or None if it's off
+/// screen. Only uses the projection's visible area, not the Camera's
+/// computed viewport, so it works before the camera's had a frame to settle.
+/// Assumes the camera fills the whole window.
+pub fn world_to_screen(
+    world_pos: Vec2,
+    camera: &OrthographicProjection,
+    cam_transform: &GlobalTransform,
+    window: &Window,
+) -> Option<Vec2> {
+    let local = cam_transform
+        .affine()
+        .inverse()
+        .transform_point3(world_pos.extend(0.0))
+        .truncate();
+    let area = camera.area;
+    if area.width() <= 0.0 || area.height() <= 0.0 || !area.contains(local) {
+        return None;
+    }
+    // 0 to 1 across the visible area, still Y-up...
+    let normalized = (local - area.min) / area.size();
+    // ...then flip Y for the window.
+    Some(Vec2::new(
+        normalized.x * window.width(),
+        (1.0 - normalized.y) * window.height(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::camera::CameraProjection;
+
+    #[test]
+    fn world_positions_land_in_the_window() {
+        let mut window = Window::default();
+        window.resolution.set(640.0, 360.0);
+        // Quarter scale: 160x90 world pixels visible.
+        let mut projection = OrthographicProjection {
+            scale: 0.25,
+            ..default()
+        };
+        projection.update(640.0, 360.0);
+        let cam = GlobalTransform::from_translation(Vec3::new(100.0, 50.0, 999.0));
+        let screen = |pos: Vec2| world_to_screen(pos, &projection, &cam, &window);
+
+        assert_eq!(
+            screen(Vec2::new(100.0, 50.0)),
+            Some(Vec2::new(320.0, 180.0))
+        );
+        // Up in the world is up on screen, which is smaller Y.
+        assert_eq!(screen(Vec2::new(80.0, 60.0)), Some(Vec2::new(240.0, 140.0)));
+        assert_eq!(screen(Vec2::new(20.0, 95.0)), Some(Vec2::new(0.0, 0.0)));
+        assert_eq!(screen(Vec2::new(181.0, 50.0)), None);
+        assert_eq!(screen(Vec2::new(100.0, 4.0)), None);
+    }
+}
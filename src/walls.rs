@@ -1,15 +1,28 @@
 use crate::{
-    collision::{centered_rect, Solid, Walkbox},
-    phys_space::PhysOffset,
+    assets_setup::{AnimationsMap, Ases},
+    behaviors::FollowPath,
+    char_animation::{AnimateFinishedEvent, CharAnimationState, Playback},
+    collision::{centered_rect, CollisionLayer, Hurtbox, MovingSolid, Solid, Walkbox, LAYER_WALL},
+    compass::Dir,
+    health::TakeDamage,
+    movement::Motion,
+    phys_space::{PhysOffset, PhysTransform},
+    render::TopDownMatter,
 };
-use bevy::{math::Vec2, prelude::Bundle};
-use bevy_ecs_ldtk::prelude::*;
+use bevy::prelude::*;
+use bevy_ecs_ldtk::{prelude::*, utils::ldtk_pixel_coords_to_translation};
+
+/// How many hits a breakable wall can take.
+pub const DESTRUCTIBLE_WALL_HITS: u8 = 3;
+
+const MOVING_PLATFORM_COLOR: Color = Color::srgb(0.55, 0.42, 0.3);
 
 /// Wall bundle for tilemap walls
 #[derive(Bundle)]
 pub struct Wall {
     solid: Solid,
     walkbox: Walkbox,
+    layer: CollisionLayer,
     offset: PhysOffset,
     int_grid_cell: IntGridCell,
     // transform: Transform, // This is needed, but it's handled by the plugin.
@@ -18,18 +31,209 @@ pub struct Wall {
 // Custom impl instead of derive bc... you'll see!
 impl LdtkIntCell for Wall {
     fn bundle_int_cell(int_grid_cell: IntGridCell, layer_instance: &LayerInstance) -> Self {
-        // there!! v. proud of finding this, the example just cheated w/ prior knowledge.
         let grid_size = layer_instance.grid_size as f32;
-        let translation_offset = Vec2::new(
-            grid_size / 2.0 + layer_instance.px_total_offset_x as f32,
-            grid_size / 2.0 + layer_instance.px_total_offset_y as f32,
+        let translation_offset = int_cell_phys_offset(
+            layer_instance.grid_size,
+            layer_instance.px_total_offset_x,
+            layer_instance.px_total_offset_y,
         );
         Wall {
             solid: Solid,
             // the plugin puts tile anchor points in the center:
             walkbox: Walkbox(centered_rect(grid_size, grid_size)),
+            layer: CollisionLayer(LAYER_WALL),
             offset: PhysOffset(translation_offset),
             int_grid_cell,
         }
     }
 }
+
+/// Component: falls apart after enough hits.
+#[derive(Component, Reflect)]
+pub struct Destructible {
+    pub hits_remaining: u8,
+}
+
+/// Wall bundle for breakable walls. Same as a wall, plus a hurtbox so
+/// attacks can find it.
+#[derive(Bundle)]
+pub struct DestructibleWall {
+    wall: Wall,
+    hurtbox: Hurtbox,
+    destructible: Destructible,
+}
+
+impl LdtkIntCell for DestructibleWall {
+    fn bundle_int_cell(int_grid_cell: IntGridCell, layer_instance: &LayerInstance) -> Self {
+        let grid_size = layer_instance.grid_size as f32;
+        DestructibleWall {
+            wall: Wall::bundle_int_cell(int_grid_cell, layer_instance),
+            hurtbox: Hurtbox(Some(centered_rect(grid_size, grid_size))),
+            destructible: Destructible {
+                hits_remaining: DESTRUCTIBLE_WALL_HITS,
+            },
+        }
+    }
+}
+
+/// Moving platforms placed in LDtk. A solid the size of the LDtk entity's
+/// rect, riding a FollowPath from wherever it's placed.
+#[derive(Bundle)]
+pub struct MovingPlatformBundle {
+    solid: Solid,
+    moving_solid: MovingSolid,
+    walkbox: Walkbox,
+    layer: CollisionLayer,
+    phys_transform: PhysTransform,
+    phys_offset: PhysOffset,
+    motion: Motion,
+    path: FollowPath,
+    sprite: Sprite,
+    texture: Handle<Image>,
+    top_down_matter: TopDownMatter,
+}
+
+impl LdtkEntity for MovingPlatformBundle {
+    fn bundle_entity(
+        entity_instance: &EntityInstance,
+        layer_instance: &LayerInstance,
+        _tileset: Option<&Handle<Image>>,
+        _tileset_definition: Option<&TilesetDefinition>,
+        _asset_server: &AssetServer,
+        _texture_atlases: &mut Assets<TextureAtlasLayout>,
+    ) -> Self {
+        let size = Vec2::new(entity_instance.width as f32, entity_instance.height as f32);
+        let rect = rect_around_pivot(size, entity_instance.pivot);
+        let offset = ldtk_entity_phys_offset(layer_instance);
+        let home = ldtk_entity_phys_location(entity_instance, layer_instance);
+        MovingPlatformBundle {
+            solid: Solid,
+            moving_solid: MovingSolid,
+            walkbox: Walkbox(rect),
+            layer: CollisionLayer(LAYER_WALL),
+            phys_transform: PhysTransform {
+                translation: home.extend(0.0),
+            },
+            // The plugin centers the Transform on the rect, not the pivot.
+            phys_offset: PhysOffset(offset - rect.center()),
+            motion: Motion::new(Vec2::ZERO),
+            path: FollowPath::from_ldtk(
+                entity_instance,
+                home,
+                layer_instance.c_hei,
+                layer_instance.grid_size,
+                offset,
+            ),
+            sprite: Sprite {
+                color: MOVING_PLATFORM_COLOR,
+                custom_size: Some(size),
+                ..default()
+            },
+            texture: Handle::default(),
+            top_down_matter: TopDownMatter::floor(),
+        }
+    }
+}
+
+/// Marker: the crumbly bits left behind by a broken wall. Goes away when
+/// its animation's done.
+#[derive(Component)]
+pub struct WallBreakEffect;
+
+/// Offset from an int grid cell's Transform to its center in phys space.
+/// Takes the loose fields, since both LayerInstance and LayerMetadata have them.
+pub fn int_cell_phys_offset(
+    grid_size: i32,
+    px_total_offset_x: i32,
+    px_total_offset_y: i32,
+) -> Vec2 {
+    // there!! v. proud of finding this, the example just cheated w/ prior knowledge.
+    let half_grid = grid_size as f32 / 2.0;
+    Vec2::new(
+        half_grid + px_total_offset_x as f32,
+        half_grid + px_total_offset_y as f32,
+    )
+}
+
+/// Offset from an LDtk entity's Transform to phys space. bevy_ecs_ldtk
+/// shifts entity layers by the layer offset, with y flipped.
+pub fn ldtk_entity_phys_offset(layer_instance: &LayerInstance) -> Vec2 {
+    Vec2::new(
+        layer_instance.px_total_offset_x as f32,
+        -layer_instance.px_total_offset_y as f32,
+    )
+}
+
+/// Where an LDtk entity's pivot point is in phys space. The plugin centers
+/// the entity's Transform on its rect instead, but for characters the pivot
+/// is where their feet go.
+pub fn ldtk_entity_phys_location(
+    entity_instance: &EntityInstance,
+    layer_instance: &LayerInstance,
+) -> Vec2 {
+    let layer_px_height = layer_instance.c_hei * layer_instance.grid_size;
+    ldtk_pixel_coords_to_translation(entity_instance.px, layer_px_height)
+        + ldtk_entity_phys_offset(layer_instance)
+}
+
+/// An LDtk entity's rect, relative to its pivot point. LDtk's pivot is
+/// measured from the top left, and y goes down over there.
+pub fn rect_around_pivot(size: Vec2, pivot: Vec2) -> Rect {
+    let min_x = -pivot.x * size.x;
+    let max_y = pivot.y * size.y;
+    Rect::new(min_x, max_y - size.y, min_x + size.x, max_y)
+}
+
+// ------- Systems -------
+
+/// Count hits on breakable walls, and knock em down when they run out.
+/// Despawning takes the Solid along with it, so the space lookup tree drops
+/// the wall on its own.
+pub fn destructible_wall_system(
+    mut damage_events: EventReader<TakeDamage>,
+    mut wall_q: Query<(&mut Destructible, &PhysTransform)>,
+    animations: Res<AnimationsMap>,
+    mut commands: Commands,
+) {
+    for damage in damage_events.read() {
+        let Ok((mut destructible, transform)) = wall_q.get_mut(damage.target) else {
+            continue;
+        };
+        // Already broken (e.g. two hits in one frame), just not despawned yet.
+        if destructible.hits_remaining == 0 {
+            continue;
+        }
+        destructible.hits_remaining -= 1;
+        if destructible.hits_remaining > 0 {
+            continue;
+        }
+        commands.entity(damage.target).despawn_recursive();
+        let Some(animation) = animations.get(&Ases::WallBreak) else {
+            continue;
+        };
+        commands.spawn((
+            WallBreakEffect,
+            Name::new("Wall break"),
+            SpriteBundle {
+                transform: Transform::from_translation(transform.translation),
+                ..default()
+            },
+            TextureAtlas::default(),
+            CharAnimationState::new(animation.clone(), Dir::E, Playback::Once),
+            TopDownMatter::character(),
+        ));
+    }
+}
+
+/// Clean up wall break effects once they finish playing.
+pub fn wall_break_effect_system(
+    mut finished_events: EventReader<AnimateFinishedEvent>,
+    effect_q: Query<(), With<WallBreakEffect>>,
+    mut commands: Commands,
+) {
+    for AnimateFinishedEvent(entity) in finished_events.read() {
+        if effect_q.contains(*entity) {
+            commands.entity(*entity).despawn();
+        }
+    }
+}